@@ -1,7 +1,13 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use p3_challenger::{CanObserve, CanSample};
+use p3_field::AbstractField;
 use sp1_core::io::SP1Stdin;
 use sp1_core::runtime::{Program, Runtime};
-use sp1_core::utils::{prove, BabyBearPoseidon2, SP1CoreOpts};
+use sp1_core::stark::StarkGenericConfig;
+use sp1_core::utils::baby_bear_poseidon2::{ChallengerState, Val};
+use sp1_core::utils::{
+    prove, prove_with_options, BabyBearPoseidon2, ProveOptions, ProveStrategy, SP1CoreOpts,
+};
 
 #[allow(unreachable_code)]
 pub fn criterion_benchmark(c: &mut Criterion) {
@@ -33,5 +39,74 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, criterion_benchmark);
+/// Compares cloning the challenger for each of many shards with restoring one from a snapshot.
+pub fn challenger_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("challenger");
+    let num_shards = 1000;
+    let config = BabyBearPoseidon2::new();
+    let mut challenger = config.challenger();
+    for i in 0..num_shards {
+        challenger.observe(Val::from_canonical_u32(i));
+    }
+    let state = ChallengerState::capture(&challenger);
+
+    group.bench_function(format!("clone:{}", num_shards), |b| {
+        b.iter(|| {
+            for _ in 0..num_shards {
+                let mut shard_challenger = black_box(&challenger).clone();
+                let _: Val = shard_challenger.sample();
+            }
+        })
+    });
+    group.bench_function(format!("restore_into:{}", num_shards), |b| {
+        let mut shard_challenger = config.challenger();
+        b.iter(|| {
+            for _ in 0..num_shards {
+                black_box(&state).restore_into(&mut shard_challenger);
+                let _: Val = shard_challenger.sample();
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Compares tracing every checkpoint twice with keeping the traced shards between the commit and
+/// prove phases.
+///
+/// Criterion only measures time. The peak memory of each strategy is logged in the proving
+/// summary, and is only meaningful when one strategy is run per process, e.g. with
+/// `cargo bench -- strategy/two_pass`.
+pub fn strategy_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strategy");
+    group.sample_size(10);
+    let program = Program::from_elf("../programs/demo/fibonacci/elf/riscv32im-succinct-zkvm-elf");
+    for (name, strategy) in [
+        ("two_pass", ProveStrategy::TwoPass),
+        ("interleaved", ProveStrategy::Interleaved),
+    ] {
+        let options = ProveOptions {
+            shard_batch_size: Some(1),
+            strategy,
+            ..Default::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                prove_with_options(
+                    black_box(program.clone()),
+                    &SP1Stdin::new(),
+                    BabyBearPoseidon2::new(),
+                    options.clone(),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    challenger_benchmark,
+    strategy_benchmark
+);
 criterion_main!(benches);
@@ -201,7 +201,7 @@ mod tests {
     use crate::{
         air::MachineAir,
         stark::StarkGenericConfig,
-        utils::{uni_stark_prove as prove, uni_stark_verify as verify},
+        utils::{prove_single_chip, uni_stark_prove as prove, uni_stark_verify as verify},
     };
     use rand::{thread_rng, Rng};
 
@@ -265,4 +265,28 @@ mod tests {
         let mut challenger = config.challenger();
         verify(&config, &chip, &mut challenger, &proof).unwrap();
     }
+
+    #[test]
+    fn prove_single_chip_babybear() {
+        let config = BabyBearPoseidon2::new();
+
+        let mut shard = ExecutionRecord::default();
+        for i in 0..16 {
+            let operand_1 = thread_rng().gen_range(0..u32::MAX);
+            let operand_2 = thread_rng().gen_range(0..u32::MAX);
+            let result = operand_1.wrapping_add(operand_2);
+            shard.add_events.push(AluEvent::new(
+                0,
+                i % 2,
+                0,
+                Opcode::ADD,
+                result,
+                operand_1,
+                operand_2,
+            ));
+        }
+
+        let chip = AddSubChip::default();
+        prove_single_chip(&chip, &shard, &config).unwrap();
+    }
 }
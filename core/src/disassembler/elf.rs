@@ -5,6 +5,7 @@ use elf::abi::{EM_RISCV, ET_EXEC, PF_X, PT_LOAD};
 use elf::endian::LittleEndian;
 use elf::file::Class;
 use elf::ElfBytes;
+use thiserror::Error;
 
 /// The maximum size of the memory in bytes.
 pub const MAXIMUM_MEMORY_SIZE: u32 = u32::MAX;
@@ -28,6 +29,35 @@ pub struct Elf {
     pub memory_image: BTreeMap<u32, u32>,
 }
 
+/// The layout of a RV32IM ELF file, as checked by [Elf::inspect].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElfInfo {
+    /// The start address of the program.
+    pub pc_start: u32,
+
+    /// The number of bytes the loadable segments occupy in memory.
+    pub memory_image_size: u64,
+}
+
+/// A reason an ELF file cannot be loaded as a RV32IM program.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    #[error("failed to parse elf: {0}")]
+    Parse(String),
+    #[error("must be a 32-bit elf")]
+    NotElf32,
+    #[error("must be a riscv machine")]
+    NotRiscv,
+    #[error("must be executable")]
+    NotExecutable,
+    #[error("invalid entrypoint 0x{0:x}")]
+    InvalidEntrypoint(u64),
+    #[error("too many program headers: {0}")]
+    TooManySegments(usize),
+    #[error("invalid segment at vaddr 0x{0:x}")]
+    InvalidSegment(u64),
+}
+
 impl Elf {
     /// Create a new ELF file.
     pub const fn new(
@@ -153,4 +183,62 @@ impl Elf {
 
         Elf::new(instructions, entry, base_address, image)
     }
+
+    /// Check that the input is a RV32IM executable that [Elf::decode] can load, without decoding
+    /// its instructions.
+    ///
+    /// The entrypoint must lie in an executable segment and every loadable segment must be word
+    /// aligned and fit in both the file and the 32-bit address space.
+    pub fn inspect(input: &[u8]) -> Result<ElfInfo, ElfError> {
+        let elf = ElfBytes::<LittleEndian>::minimal_parse(input)
+            .map_err(|e| ElfError::Parse(e.to_string()))?;
+
+        if elf.ehdr.class != Class::ELF32 {
+            return Err(ElfError::NotElf32);
+        } else if elf.ehdr.e_machine != EM_RISCV {
+            return Err(ElfError::NotRiscv);
+        } else if elf.ehdr.e_type != ET_EXEC {
+            return Err(ElfError::NotExecutable);
+        }
+
+        let entry = elf.ehdr.e_entry;
+        if entry >= MAXIMUM_MEMORY_SIZE as u64 || entry % WORD_SIZE as u64 != 0 {
+            return Err(ElfError::InvalidEntrypoint(entry));
+        }
+
+        let segments = elf
+            .segments()
+            .ok_or_else(|| ElfError::Parse("missing program headers".to_string()))?;
+        if segments.len() > 256 {
+            return Err(ElfError::TooManySegments(segments.len()));
+        }
+
+        let mut memory_image_size = 0;
+        let mut entry_is_executable = false;
+        for segment in segments.iter().filter(|x| x.p_type == PT_LOAD) {
+            let vaddr = segment.p_vaddr;
+            let end = vaddr.checked_add(segment.p_memsz);
+            let file_end = segment.p_offset.checked_add(segment.p_filesz);
+            if vaddr % WORD_SIZE as u64 != 0
+                || end.map_or(true, |end| end > MAXIMUM_MEMORY_SIZE as u64)
+                || file_end.map_or(true, |end| end > input.len() as u64)
+            {
+                return Err(ElfError::InvalidSegment(vaddr));
+            }
+
+            if (segment.p_flags & PF_X) != 0 && (vaddr..vaddr + segment.p_memsz).contains(&entry) {
+                entry_is_executable = true;
+            }
+            memory_image_size += segment.p_memsz;
+        }
+
+        if !entry_is_executable {
+            return Err(ElfError::InvalidEntrypoint(entry));
+        }
+
+        Ok(ElfInfo {
+            pc_start: entry as u32,
+            memory_image_size,
+        })
+    }
 }
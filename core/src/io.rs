@@ -1,10 +1,12 @@
 use crate::{
     stark::{ShardProof, StarkVerifyingKey},
-    utils::{BabyBearPoseidon2, Buffer},
+    utils::{baby_bear_poseidon2::Preset, BabyBearPoseidon2, Buffer},
 };
 use k256::sha2::{Digest, Sha256};
 use num_bigint::BigUint;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::marker::PhantomData;
+use thiserror::Error;
 
 /// Standard input for the prover.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,10 +15,37 @@ pub struct SP1Stdin {
     /// a vec of bytes at a time.
     pub buffer: Vec<Vec<u8>>,
     pub ptr: usize,
-    pub proofs: Vec<(
-        ShardProof<BabyBearPoseidon2>,
-        StarkVerifyingKey<BabyBearPoseidon2>,
-    )>,
+    pub proofs: Vec<DeferredProofInput>,
+}
+
+/// A proof whose verification is deferred by the program, together with the verifying key of the
+/// program it proves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeferredProofInput {
+    pub proof: ShardProof<BabyBearPoseidon2>,
+    pub vk: StarkVerifyingKey<BabyBearPoseidon2>,
+    /// The config the proof was made with, which decides how it is verified. Inputs serialized
+    /// before it was recorded are compressed proofs.
+    #[serde(default = "DeferredProofInput::default_config")]
+    pub config: Preset,
+}
+
+impl DeferredProofInput {
+    /// A compressed proof, the only kind of proof the recursion prover can defer.
+    pub const fn new(
+        proof: ShardProof<BabyBearPoseidon2>,
+        vk: StarkVerifyingKey<BabyBearPoseidon2>,
+    ) -> Self {
+        Self {
+            proof,
+            vk,
+            config: Preset::Compress,
+        }
+    }
+
+    const fn default_config() -> Preset {
+        Preset::Compress
+    }
 }
 
 /// Public values for the prover.
@@ -74,12 +103,92 @@ impl SP1Stdin {
         self.buffer.push(vec);
     }
 
+    /// The total number of bytes of the input buffers. Deferred proofs are not counted.
+    pub fn total_len(&self) -> usize {
+        self.buffer.iter().map(Vec::len).sum()
+    }
+
+    /// Checks that the input buffers hold at most `max_bytes` bytes.
+    pub fn check_len(&self, max_bytes: usize) -> Result<(), StdinTooLarge> {
+        let len = self.total_len();
+        match len > max_bytes {
+            true => Err(StdinTooLarge { len, max_bytes }),
+            false => Ok(()),
+        }
+    }
+
+    #[deprecated(note = "use `write_proof_input` instead")]
     pub fn write_proof(
         &mut self,
         proof: ShardProof<BabyBearPoseidon2>,
         vk: StarkVerifyingKey<BabyBearPoseidon2>,
     ) {
-        self.proofs.push((proof, vk));
+        self.write_proof_input(DeferredProofInput::new(proof, vk));
+    }
+
+    /// Write a deferred proof and the verifying key of the program it proves.
+    pub fn write_proof_input(&mut self, input: DeferredProofInput) {
+        self.proofs.push(input);
+    }
+}
+
+/// An error returned when the input buffers of an [SP1Stdin] exceed a size limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("stdin holds {len} bytes, above the limit of {max_bytes}")]
+pub struct StdinTooLarge {
+    pub len: usize,
+    pub max_bytes: usize,
+}
+
+/// Builds an [SP1Stdin] whose input buffers hold at most a given number of bytes, for services
+/// that accept inputs from untrusted users.
+#[derive(Debug, Clone)]
+pub struct SP1StdinBuilder {
+    stdin: SP1Stdin,
+    max_bytes: usize,
+}
+
+impl SP1StdinBuilder {
+    /// Creates a builder that rejects inputs of more than `max_bytes` bytes in total.
+    pub const fn new(max_bytes: usize) -> Self {
+        Self {
+            stdin: SP1Stdin::new(),
+            max_bytes,
+        }
+    }
+
+    /// Write a value to the buffer.
+    pub fn write<T: Serialize>(mut self, data: &T) -> Self {
+        self.stdin.write(data);
+        self
+    }
+
+    /// Write a slice of bytes to the buffer.
+    pub fn write_slice(mut self, slice: &[u8]) -> Self {
+        self.stdin.write_slice(slice);
+        self
+    }
+
+    pub fn write_vec(mut self, vec: Vec<u8>) -> Self {
+        self.stdin.write_vec(vec);
+        self
+    }
+
+    /// Write a deferred proof and the verifying key of the program it proves.
+    pub fn write_proof_input(mut self, input: DeferredProofInput) -> Self {
+        self.stdin.write_proof_input(input);
+        self
+    }
+
+    /// The total number of bytes written so far. Deferred proofs are not counted.
+    pub fn total_len(&self) -> usize {
+        self.stdin.total_len()
+    }
+
+    /// Returns the input, or an error if more than `max_bytes` bytes were written.
+    pub fn build(self) -> Result<SP1Stdin, StdinTooLarge> {
+        self.stdin.check_len(self.max_bytes)?;
+        Ok(self.stdin)
     }
 }
 
@@ -120,6 +229,18 @@ impl SP1PublicValues {
         self.buffer.read_slice(slice);
     }
 
+    /// Iterate over the values of type `T` committed after the current read position, each with
+    /// the number of bytes it was committed as. Reading from the buffer is not advanced.
+    ///
+    /// The iterator yields an error for the first bytes that do not deserialize as a `T`, and
+    /// then stops.
+    pub fn committed<T: Serialize + DeserializeOwned>(&self) -> CommittedValues<'_, T> {
+        CommittedValues {
+            data: &self.buffer.data[self.buffer.ptr..],
+            _marker: PhantomData,
+        }
+    }
+
     /// Write a value to the buffer.
     pub fn write<T: Serialize + DeserializeOwned>(&mut self, data: &T) {
         self.buffer.write(data);
@@ -157,6 +278,32 @@ impl AsRef<[u8]> for SP1PublicValues {
     }
 }
 
+/// An iterator over committed public values, created by [SP1PublicValues::committed].
+pub struct CommittedValues<'a, T> {
+    data: &'a [u8],
+    _marker: PhantomData<T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Iterator for CommittedValues<'_, T> {
+    type Item = Result<(T, usize), bincode::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let result = bincode::deserialize(self.data).and_then(|value: T| {
+            let len = bincode::serialized_size(&value)? as usize;
+            Ok((value, len))
+        });
+        // Nothing after an undecodable value can be located, so stop at the first error.
+        match &result {
+            Ok((_, len)) => self.data = &self.data[*len..],
+            Err(_) => self.data = &[],
+        }
+        Some(result)
+    }
+}
+
 pub mod proof_serde {
     use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 
@@ -213,4 +360,55 @@ mod tests {
 
         assert_eq!(hash, expected_hash_biguint);
     }
+
+    #[test]
+    fn test_stdin_builder() {
+        let builder = SP1StdinBuilder::new(16)
+            .write(&5u64)
+            .write_slice(&[1, 2, 3]);
+        assert_eq!(builder.total_len(), 11);
+        let stdin = builder.clone().build().unwrap();
+        assert_eq!(
+            stdin.buffer,
+            vec![5u64.to_le_bytes().to_vec(), vec![1, 2, 3]]
+        );
+
+        let result = builder.write_vec(vec![0; 6]).build();
+        assert_eq!(
+            result.unwrap_err(),
+            StdinTooLarge {
+                len: 17,
+                max_bytes: 16
+            }
+        );
+    }
+
+    #[test]
+    fn test_committed_public_values() {
+        let mut public_values = SP1PublicValues::new();
+        public_values.write(&(1u32, 2u64));
+        public_values.write(&(3u32, 4u64));
+        public_values.write(&(5u32, 6u64));
+
+        let committed = public_values
+            .committed::<(u32, u64)>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(committed, vec![((1, 2), 12), ((3, 4), 12), ((5, 6), 12)]);
+
+        assert_eq!(public_values.read::<(u32, u64)>(), (1, 2));
+        let remaining = public_values
+            .committed::<(u32, u64)>()
+            .map(|result| result.unwrap().0);
+        assert_eq!(remaining.collect::<Vec<_>>(), vec![(3, 4), (5, 6)]);
+
+        // A truncated value is reported once, and nothing is read past it.
+        let mut truncated = SP1PublicValues::new();
+        truncated.write(&(1u32, 2u64));
+        truncated.write_slice(&[0; 4]);
+        let mut committed = truncated.committed::<(u32, u64)>();
+        assert_eq!(committed.next().unwrap().unwrap(), ((1, 2), 12));
+        assert!(committed.next().unwrap().is_err());
+        assert!(committed.next().is_none());
+    }
 }
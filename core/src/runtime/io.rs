@@ -1,5 +1,6 @@
 use std::io::Read;
 
+use crate::io::DeferredProofInput;
 use crate::stark::{ShardProof, StarkVerifyingKey};
 use crate::utils::BabyBearPoseidon2;
 
@@ -37,7 +38,23 @@ impl Runtime {
         proof: ShardProof<BabyBearPoseidon2>,
         vk: StarkVerifyingKey<BabyBearPoseidon2>,
     ) {
-        self.state.proof_stream.push((proof, vk));
+        self.state
+            .proof_stream
+            .push(bincode::serialize(&(proof, vk)).expect("serialization failed"));
+    }
+
+    /// Writes a batch of proofs and their verifying keys to the proof stream.
+    ///
+    /// Each proof is serialized straight from the borrow into a single buffer, instead of being
+    /// cloned field by field.
+    pub fn write_proofs(&mut self, proofs: &[DeferredProofInput]) {
+        self.state.proof_stream.extend(proofs.iter().map(|input| {
+            let value = (&input.proof, &input.vk);
+            let size = bincode::serialized_size(&value).expect("serialization failed");
+            let mut buf = Vec::with_capacity(size as usize);
+            bincode::serialize_into(&mut buf, &value).expect("serialization failed");
+            buf
+        }));
     }
 
     pub fn read_public_values<T: DeserializeOwned>(&mut self) -> T {
@@ -88,12 +88,19 @@ pub struct Runtime {
 
     /// Whether we should write to the report.
     pub should_report: bool,
+
+    /// The maximum number of cycles to execute before failing, if any.
+    pub max_cycles: Option<u64>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct ExecutionReport {
     pub instruction_counts: HashMap<Opcode, u64>,
     pub syscall_counts: HashMap<SyscallCode, u64>,
+    /// The number of events of each kind in the execution record, keyed like
+    /// [crate::stark::MachineRecord::stats]. The runtime leaves it empty, and it is filled by
+    /// [crate::utils::run_test_io_with_report].
+    pub event_counts: HashMap<String, usize>,
 }
 
 impl ExecutionReport {
@@ -144,6 +151,21 @@ pub enum ExecutionError {
     Breakpoint(),
     #[error("got unimplemented as opcode")]
     Unimplemented(),
+    #[error("exceeded cycle limit of {0}")]
+    ExceededCycleLimit(u64),
+}
+
+/// An error returned by [Runtime::preload_memory] for a region that cannot be loaded.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryPreloadError {
+    #[error("preloaded region at {0:#x} is not word aligned")]
+    Unaligned(u32),
+    #[error("preloaded region at {0:#x} overlaps the registers or the end of the address space")]
+    OutOfBounds(u32),
+    #[error("preloaded word at {0:#x} overlaps the program image")]
+    OverlapsProgram(u32),
+    #[error("preloaded word at {0:#x} was already preloaded")]
+    AlreadyPreloaded(u32),
 }
 
 impl Runtime {
@@ -191,6 +213,7 @@ impl Runtime {
             max_syscall_cycles,
             report: Default::default(),
             should_report: false,
+            max_cycles: None,
         }
     }
 
@@ -806,7 +829,8 @@ impl Runtime {
                 let syscall_id = self.register(t0);
                 c = self.rr(Register::X11, MemoryAccessPosition::C);
                 b = self.rr(Register::X10, MemoryAccessPosition::B);
-                let syscall = SyscallCode::from_u32(syscall_id);
+                let syscall = SyscallCode::try_from_u32(syscall_id)
+                    .ok_or(ExecutionError::UnsupportedSyscall(syscall_id))?;
 
                 if self.should_report && !self.unconstrained {
                     self.report
@@ -969,6 +993,11 @@ impl Runtime {
 
         // Increment the clock.
         self.state.global_clk += 1;
+        if let Some(max_cycles) = self.max_cycles {
+            if self.state.global_clk > max_cycles {
+                return Err(ExecutionError::ExceededCycleLimit(max_cycles));
+            }
+        }
 
         // If there's not enough cycles left for another instruction, move to the next shard.
         // We multiply by 4 because clk is incremented by 4 for each normal instruction.
@@ -997,6 +1026,60 @@ impl Runtime {
         Ok((state, done))
     }
 
+    /// Returns an iterator that lazily executes the program, yielding the prestate of every
+    /// `self.shard_batch_size` cycle range until the program ends.
+    pub fn checkpoint_iter(&mut self) -> CheckpointIter<'_> {
+        CheckpointIter {
+            runtime: self,
+            done: false,
+        }
+    }
+
+    /// Loads `bytes` into memory starting at `addr` before the program runs, so that the program
+    /// reads them like initial memory without them going through stdin. A trailing partial word is
+    /// padded with zeros.
+    ///
+    /// The region must be word aligned and must not overlap the registers, the program image or a
+    /// region that was already preloaded. Nothing is loaded if any word is rejected.
+    pub fn preload_memory(&mut self, addr: u32, bytes: &[u8]) -> Result<(), MemoryPreloadError> {
+        assert_eq!(
+            self.state.global_clk, 0,
+            "memory must be preloaded before execution"
+        );
+        if addr % 4 != 0 {
+            return Err(MemoryPreloadError::Unaligned(addr));
+        }
+        let fits = u32::try_from(bytes.len())
+            .ok()
+            .and_then(|len| addr.checked_add(len))
+            .is_some();
+        if addr < 32 || !fits {
+            return Err(MemoryPreloadError::OutOfBounds(addr));
+        }
+
+        let words = bytes
+            .chunks(4)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut word = [0u8; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                (addr + 4 * i as u32, u32::from_le_bytes(word))
+            })
+            .collect::<Vec<_>>();
+        for &(word_addr, _) in words.iter() {
+            if self.program.memory_image.contains_key(&word_addr) {
+                return Err(MemoryPreloadError::OverlapsProgram(word_addr));
+            }
+            if self.state.uninitialized_memory.contains_key(&word_addr) {
+                return Err(MemoryPreloadError::AlreadyPreloaded(word_addr));
+            }
+        }
+
+        // Preloaded words are initialized on first access, like memory written by a hint.
+        self.state.uninitialized_memory.extend(words);
+        Ok(())
+    }
+
     fn initialize(&mut self) {
         self.state.clk = 0;
         self.state.channel = 0;
@@ -1136,6 +1219,34 @@ impl Runtime {
         self.syscall_map.get(&code)
     }
 }
+/// An iterator over the checkpoints of a [Runtime], created by [Runtime::checkpoint_iter].
+///
+/// Each checkpoint is produced on demand, so callers can persist or consume it before the next one
+/// is executed. The iterator stops after the final checkpoint or the first execution error.
+pub struct CheckpointIter<'a> {
+    runtime: &'a mut Runtime,
+    done: bool,
+}
+
+impl<'a> CheckpointIter<'a> {
+    /// The current state of the runtime, i.e. the state at the end of the last checkpoint.
+    pub fn state(&self) -> &ExecutionState {
+        &self.runtime.state
+    }
+}
+
+impl<'a> Iterator for CheckpointIter<'a> {
+    type Item = Result<ExecutionState, ExecutionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.runtime.execute_state();
+        self.done = !matches!(result, Ok((_, false)));
+        Some(result.map(|(state, _)| state))
+    }
+}
 
 #[cfg(test)]
 pub mod tests {
@@ -1148,7 +1259,7 @@ pub mod tests {
         },
     };
 
-    use super::{Instruction, Opcode, Program, Runtime};
+    use super::{Instruction, MemoryPreloadError, Opcode, Program, Runtime};
 
     pub fn simple_program() -> Program {
         let instructions = vec![
@@ -1179,6 +1290,45 @@ pub mod tests {
         assert_eq!(runtime.register(Register::X31), 42);
     }
 
+    #[test]
+    fn test_preload_memory() {
+        let instructions = vec![
+            Instruction::new(Opcode::LW, 29, 0, 0x1000, false, true),
+            Instruction::new(Opcode::LW, 30, 0, 0x1004, false, true),
+            Instruction::new(Opcode::LW, 31, 0, 0x2000, false, true),
+        ];
+        let mut program = Program::new(instructions, 0, 0);
+        program.memory_image.insert(0x2000, 7);
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+
+        runtime.preload_memory(0x1000, &[1, 0, 0, 0, 2, 1]).unwrap();
+        assert_eq!(
+            runtime.preload_memory(0x1004, &[0; 4]),
+            Err(MemoryPreloadError::AlreadyPreloaded(0x1004))
+        );
+        assert_eq!(
+            runtime.preload_memory(0x1ffc, &[0; 8]),
+            Err(MemoryPreloadError::OverlapsProgram(0x2000))
+        );
+        assert_eq!(
+            runtime.preload_memory(0x3001, &[0; 4]),
+            Err(MemoryPreloadError::Unaligned(0x3001))
+        );
+        assert_eq!(
+            runtime.preload_memory(0, &[0; 4]),
+            Err(MemoryPreloadError::OutOfBounds(0))
+        );
+        assert_eq!(
+            runtime.preload_memory(0xfffffffc, &[0; 8]),
+            Err(MemoryPreloadError::OutOfBounds(0xfffffffc))
+        );
+
+        runtime.run().unwrap();
+        assert_eq!(runtime.register(Register::X29), 1);
+        assert_eq!(runtime.register(Register::X30), 0x102);
+        assert_eq!(runtime.register(Register::X31), 7);
+    }
+
     #[test]
     fn test_ssz_withdrawals_program_run_report() {
         let program = ssz_withdrawals_program();
@@ -1226,6 +1376,7 @@ pub mod tests {
                     (HALT, 1),
                 ]
                 .into(),
+                event_counts: Default::default(),
             }
         });
         assert_eq!(runtime.report.total_instruction_count(), 2757356);
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
-use super::Instruction;
+use super::{default_syscall_map, Instruction, Opcode, Register, SyscallCode};
 
 /// A program that can be executed by the VM.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -18,3 +18,80 @@ pub struct Program {
     /// The initial memory image, useful for global constants.
     pub memory_image: BTreeMap<u32, u32>,
 }
+
+impl Program {
+    /// The syscall ids that the program loads into `t0` right before an `ecall`.
+    ///
+    /// Only ids set by the instructions directly preceding an `ecall`, as emitted for an `li t0`,
+    /// are found. Syscalls whose id is computed any other way are skipped.
+    pub fn syscall_ids(&self) -> BTreeSet<u32> {
+        (0..self.instructions.len())
+            .filter(|&i| self.instructions[i].opcode == Opcode::ECALL)
+            .filter_map(|i| t0_before(&self.instructions[..i]))
+            .collect()
+    }
+
+    /// The syscall ids found by [Program::syscall_ids] that the runtime has no syscall for.
+    pub fn unsupported_syscall_ids(&self) -> Vec<u32> {
+        let syscall_map = default_syscall_map();
+        self.syscall_ids()
+            .into_iter()
+            .filter(|&id| {
+                SyscallCode::try_from_u32(id).map_or(true, |code| !syscall_map.contains_key(&code))
+            })
+            .collect()
+    }
+}
+
+/// The constant written to `t0` by the last one or two of `instructions`, if they are a `li t0`.
+fn t0_before(instructions: &[Instruction]) -> Option<u32> {
+    let t0 = Register::X5 as u32;
+    let writes_t0 = |instruction: &Instruction| {
+        instruction.opcode == Opcode::ADD && instruction.op_a == t0 && instruction.imm_c
+    };
+    let (last, rest) = instructions.split_last()?;
+    if !writes_t0(last) {
+        return None;
+    }
+    if last.imm_b {
+        // `lui t0, hi`
+        Some(last.op_b.wrapping_add(last.op_c))
+    } else if last.op_b == Register::X0 as u32 {
+        // `addi t0, zero, lo`
+        Some(last.op_c)
+    } else if last.op_b == t0 {
+        // `lui t0, hi` followed by `addi t0, t0, lo`
+        let prev = rest.last().filter(|prev| writes_t0(prev) && prev.imm_b)?;
+        Some(prev.op_b.wrapping_add(prev.op_c).wrapping_add(last.op_c))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::tests::simple_program;
+
+    #[test]
+    fn test_unsupported_syscall_ids() {
+        let t0 = Register::X5 as u32;
+        let li = |id: u32| {
+            vec![
+                Instruction::new(Opcode::ADD, t0, 0, id & !0xfff, true, true),
+                Instruction::new(Opcode::ADD, t0, t0, id & 0xfff, false, true),
+                Instruction::new(Opcode::ECALL, t0, 10, 11, false, false),
+            ]
+        };
+        let sha_extend = SyscallCode::SHA_EXTEND as u32;
+        let mut instructions = li(sha_extend);
+        instructions.extend(li(0x00_00_01_FF));
+        let program = Program::new(instructions, 0, 0);
+        assert_eq!(
+            program.syscall_ids(),
+            BTreeSet::from([sha_extend, 0x00_00_01_FF])
+        );
+        assert_eq!(program.unsupported_syscall_ids(), vec![0x00_00_01_FF]);
+        assert!(simple_program().unsupported_syscall_ids().is_empty());
+    }
+}
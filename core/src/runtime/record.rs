@@ -1,11 +1,15 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::fmt::Debug;
 use std::mem::take;
 use std::sync::Arc;
 
 use itertools::Itertools;
 use p3_field::AbstractField;
+use p3_keccak_air::NUM_ROUNDS;
+use p3_util::log2_ceil_usize;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use super::program::Program;
 use super::Opcode;
@@ -16,7 +20,7 @@ use crate::bytes::ByteLookupEvent;
 use crate::cpu::CpuEvent;
 use crate::runtime::MemoryInitializeFinalizeEvent;
 use crate::runtime::MemoryRecordEnum;
-use crate::stark::MachineRecord;
+use crate::stark::{MachineRecord, StarkGenericConfig};
 use crate::syscall::precompiles::blake3::Blake3CompressInnerEvent;
 use crate::syscall::precompiles::edwards::EdDecompressEvent;
 use crate::syscall::precompiles::keccak256::KeccakPermuteEvent;
@@ -105,6 +109,38 @@ pub struct ExecutionRecord {
     pub public_values: PublicValues<u32, u32>,
 }
 
+/// The first difference between the events of one type in two records, found by
+/// [ExecutionRecord::diff].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventDiff {
+    /// The name of the field of [ExecutionRecord] holding the events.
+    pub name: &'static str,
+    /// The index of the first event that differs, or the length of the shorter list of events if
+    /// it is a prefix of the other.
+    pub index: usize,
+    /// The event at `index` in the first record, formatted with `Debug`, if it has one.
+    pub left: Option<String>,
+    /// The event at `index` in the second record, formatted with `Debug`, if it has one.
+    pub right: Option<String>,
+}
+
+/// The differences between two execution records, found by [ExecutionRecord::diff].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordDiff {
+    /// The first difference of every event type that differs, in the order of the fields of
+    /// [ExecutionRecord]. The shard index and public values are compared like a single event, and
+    /// byte lookups as `(shard, event, count)` triples.
+    pub events: Vec<EventDiff>,
+}
+
+impl RecordDiff {
+    /// Whether the records have the same events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct ShardingConfig {
     pub shard_size: usize,
     pub add_len: usize,
@@ -126,15 +162,122 @@ pub struct ShardingConfig {
     pub uint256_mul_len: usize,
 }
 
+/// The estimated number of bytes of memory used per cycle of a shard while it is committed.
+///
+/// The estimate counts one row per cycle in each chip a cycle can touch: the CPU chip, one ALU
+/// or memory chip and the byte lookups, about 1024 BabyBear columns of 4 bytes in total. Each
+/// row is held three times while committing: in the trace, in its low-degree extension at a
+/// blowup of 2, and in the Merkle tree over the extension, giving 12 KiB per cycle.
+pub const SHARD_BYTES_PER_CYCLE: usize = 3 * 4 * 1024;
+
+/// The smallest shard size chosen by [ShardingConfig::for_memory_budget], below which FRI and
+/// recursion overhead dominate the cost of proving.
+pub const MIN_MEMORY_BUDGET_SHARD_SIZE: usize = 1 << 16;
+
+/// The height that the trace of every chip is padded to at least.
+pub const MIN_TRACE_HEIGHT: usize = 16;
+
+/// An error returned by [ShardingConfig::validate] for a config that no shard can be proven with.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ShardingConfigError {
+    #[error("{name} is zero, so its events cannot be split into shards")]
+    ZeroLength { name: &'static str },
+    #[error(
+        "{name} is {len}, below the {min_len} events that fill the minimum trace height of its \
+         chip"
+    )]
+    TooSmall {
+        name: &'static str,
+        len: usize,
+        min_len: usize,
+    },
+    #[error(
+        "{name} allows traces of height 2^{log_height}, above the 2^{max_log_height} supported by \
+         the config"
+    )]
+    TooLarge {
+        name: &'static str,
+        log_height: usize,
+        max_log_height: usize,
+    },
+}
+
 impl ShardingConfig {
     pub const fn shard_size(&self) -> usize {
         self.shard_size
     }
-}
 
-impl Default for ShardingConfig {
-    fn default() -> Self {
-        let shard_size = SP1CoreOpts::default().shard_size;
+    /// A config whose shards, traced and committed, are estimated to fit in `bytes` of memory.
+    ///
+    /// The shard size is the largest power of two whose cost at [SHARD_BYTES_PER_CYCLE] fits in
+    /// the budget, clamped between [MIN_MEMORY_BUDGET_SHARD_SIZE] and the default shard size.
+    pub fn for_memory_budget(bytes: usize) -> Self {
+        let max_shard_size = SP1CoreOpts::default().shard_size;
+        let rows = bytes / SHARD_BYTES_PER_CYCLE;
+        let shard_size = if rows < MIN_MEMORY_BUDGET_SHARD_SIZE {
+            MIN_MEMORY_BUDGET_SHARD_SIZE
+        } else {
+            (1 << rows.ilog2()).min(max_shard_size)
+        };
+        Self::with_shard_size(shard_size)
+    }
+
+    /// Checks that shards split with this config can be committed to with `config`.
+    ///
+    /// Every length must be positive, and a chip filled up to its length must fit within the
+    /// largest trace height supported by the PCS of `config`. It must also reach
+    /// [MIN_TRACE_HEIGHT], since every trace is padded to that height anyway and a smaller length
+    /// would only split the events into more shards.
+    pub fn validate<SC: StarkGenericConfig>(&self, config: &SC) -> Result<(), ShardingConfigError> {
+        for (name, len, rows_per_event) in self.lengths() {
+            if len == 0 {
+                return Err(ShardingConfigError::ZeroLength { name });
+            }
+            let min_len = MIN_TRACE_HEIGHT.div_ceil(rows_per_event);
+            if len < min_len {
+                return Err(ShardingConfigError::TooSmall { name, len, min_len });
+            }
+            if let Some(max_log_height) = config.max_log_trace_height() {
+                let log_height = log2_ceil_usize(len);
+                if log_height > max_log_height {
+                    return Err(ShardingConfigError::TooLarge {
+                        name,
+                        log_height,
+                        max_log_height,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The name and value of every length of this config, with the number of rows each event
+    /// takes in the trace of its chip.
+    pub(crate) fn lengths(&self) -> [(&'static str, usize, usize); 18] {
+        [
+            ("shard_size", self.shard_size, 1),
+            ("add_len", self.add_len, 1),
+            ("mul_len", self.mul_len, 1),
+            ("sub_len", self.sub_len, 1),
+            ("bitwise_len", self.bitwise_len, 1),
+            ("shift_left_len", self.shift_left_len, 1),
+            ("shift_right_len", self.shift_right_len, 1),
+            ("divrem_len", self.divrem_len, 1),
+            ("lt_len", self.lt_len, 1),
+            ("field_len", self.field_len, 1),
+            ("keccak_len", self.keccak_len, NUM_ROUNDS),
+            ("secp256k1_add_len", self.secp256k1_add_len, 1),
+            ("secp256k1_double_len", self.secp256k1_double_len, 1),
+            ("bn254_add_len", self.bn254_add_len, 1),
+            ("bn254_double_len", self.bn254_double_len, 1),
+            ("bls12381_add_len", self.bls12381_add_len, 1),
+            ("bls12381_double_len", self.bls12381_double_len, 1),
+            ("uint256_mul_len", self.uint256_mul_len, 1),
+        ]
+    }
+
+    /// A config splitting every chip at `shard_size` rows, or four times that for field ops.
+    fn with_shard_size(shard_size: usize) -> Self {
         Self {
             shard_size,
             add_len: shard_size,
@@ -158,6 +301,12 @@ impl Default for ShardingConfig {
     }
 }
 
+impl Default for ShardingConfig {
+    fn default() -> Self {
+        Self::with_shard_size(SP1CoreOpts::default().shard_size)
+    }
+}
+
 impl MachineRecord for ExecutionRecord {
     type Config = ShardingConfig;
 
@@ -546,6 +695,103 @@ impl ExecutionRecord {
         }
     }
 
+    /// Finds the first differing event of every event type between `self` and `other`, such as a
+    /// re-traced checkpoint and the record it is expected to reproduce. The program is not
+    /// compared.
+    ///
+    /// Events are compared by their serialized bytes, since not every event type implements
+    /// `PartialEq`.
+    pub fn diff(&self, other: &ExecutionRecord) -> RecordDiff {
+        fn first_difference<T: Serialize + Debug>(
+            name: &'static str,
+            left: impl IntoIterator<Item = T>,
+            right: impl IntoIterator<Item = T>,
+        ) -> Option<EventDiff> {
+            let (mut left, mut right) = (left.into_iter(), right.into_iter());
+            let mut index = 0;
+            loop {
+                let (l, r) = match (left.next(), right.next()) {
+                    (None, None) => return None,
+                    (Some(l), Some(r))
+                        if bincode::serialize(&l).ok() == bincode::serialize(&r).ok() =>
+                    {
+                        index += 1;
+                        continue;
+                    }
+                    pair => pair,
+                };
+                return Some(EventDiff {
+                    name,
+                    index,
+                    left: l.map(|event| format!("{:?}", event)),
+                    right: r.map(|event| format!("{:?}", event)),
+                });
+            }
+        }
+
+        fn byte_lookups(
+            record: &ExecutionRecord,
+        ) -> impl Iterator<Item = (u32, &ByteLookupEvent, &usize)> {
+            record.byte_lookups.iter().flat_map(|(&shard, events)| {
+                events
+                    .iter()
+                    .map(move |(event, count)| (shard, event, count))
+            })
+        }
+
+        macro_rules! diff_events {
+            ($($field:ident),*) => {
+                [$(first_difference(stringify!($field), &self.$field, &other.$field)),*]
+            };
+        }
+
+        let events = [first_difference("index", [self.index], [other.index])]
+            .into_iter()
+            .chain(diff_events!(
+                cpu_events,
+                add_events,
+                mul_events,
+                sub_events,
+                bitwise_events,
+                shift_left_events,
+                shift_right_events,
+                divrem_events,
+                lt_events
+            ))
+            .chain([first_difference(
+                "byte_lookups",
+                byte_lookups(self),
+                byte_lookups(other),
+            )])
+            .chain(diff_events!(
+                sha_extend_events,
+                sha_compress_events,
+                keccak_permute_events,
+                ed_add_events,
+                ed_decompress_events,
+                secp256k1_add_events,
+                secp256k1_double_events,
+                bn254_add_events,
+                bn254_double_events,
+                k256_decompress_events,
+                blake3_compress_inner_events,
+                bls12381_add_events,
+                bls12381_double_events,
+                uint256_mul_events,
+                memory_initialize_events,
+                memory_finalize_events,
+                bls12381_decompress_events
+            ))
+            .chain([first_difference(
+                "public_values",
+                [self.public_values],
+                [other.public_values],
+            )])
+            .flatten()
+            .collect();
+        RecordDiff { events }
+    }
+
     pub fn add_mul_event(&mut self, mul_event: AluEvent) {
         self.mul_events.push(mul_event);
     }
@@ -607,3 +853,40 @@ pub struct MemoryAccessRecord {
     pub c: Option<MemoryRecordEnum>,
     pub memory: Option<MemoryRecordEnum>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::tests::simple_program;
+    use crate::runtime::{Instruction, Runtime};
+
+    #[test]
+    fn test_record_diff() {
+        let run = |program: Program| {
+            let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+            runtime.run().unwrap();
+            runtime.record
+        };
+        let record = run(simple_program());
+        assert!(record.diff(&run(simple_program())).is_empty());
+
+        let instructions = vec![
+            Instruction::new(Opcode::ADD, 29, 0, 5, false, true),
+            Instruction::new(Opcode::ADD, 30, 0, 38, false, true),
+            Instruction::new(Opcode::ADD, 31, 30, 29, false, false),
+        ];
+        let diff = record.diff(&run(Program::new(instructions, 0, 0)));
+        let cpu_events = diff
+            .events
+            .iter()
+            .find(|event| event.name == "cpu_events")
+            .unwrap();
+        assert_eq!(cpu_events.index, 1);
+        assert!(cpu_events.left.is_some() && cpu_events.right.is_some());
+
+        // Differences are listed in the order of the fields of the record.
+        let position = |name| diff.events.iter().position(|event| event.name == name);
+        assert_eq!(position("cpu_events"), Some(0));
+        assert!(position("add_events").unwrap() < position("byte_lookups").unwrap());
+    }
+}
@@ -4,11 +4,6 @@ use nohash_hasher::BuildNoHashHasher;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
-use crate::{
-    stark::{ShardProof, StarkVerifyingKey},
-    utils::BabyBearPoseidon2,
-};
-
 use super::{ExecutionRecord, MemoryAccessRecord, MemoryRecord};
 
 /// Holds data describing the current state of a program's execution.
@@ -46,11 +41,9 @@ pub struct ExecutionState {
     /// A ptr to the current position in the input stream incremented by HINT_READ opcode.
     pub input_stream_ptr: usize,
 
-    /// A stream of proofs inputted to the program.
-    pub proof_stream: Vec<(
-        ShardProof<BabyBearPoseidon2>,
-        StarkVerifyingKey<BabyBearPoseidon2>,
-    )>,
+    /// A stream of proofs inputted to the program, each serialized with bincode together with its
+    /// verifying key.
+    pub proof_stream: Vec<Vec<u8>>,
 
     /// A ptr to the current position in the proof stream, incremented after verifying a proof.
     pub proof_stream_ptr: usize,
@@ -108,7 +108,12 @@ pub enum SyscallCode {
 impl SyscallCode {
     /// Create a syscall from a u32.
     pub fn from_u32(value: u32) -> Self {
-        match value {
+        Self::try_from_u32(value).unwrap_or_else(|| panic!("invalid syscall number: {}", value))
+    }
+
+    /// Create a syscall from a u32, or return `None` if no syscall has that number.
+    pub fn try_from_u32(value: u32) -> Option<Self> {
+        let syscall = match value {
             0x00_00_00_00 => SyscallCode::HALT,
             0x00_00_00_02 => SyscallCode::WRITE,
             0x00_00_00_03 => SyscallCode::ENTER_UNCONSTRAINED,
@@ -133,8 +138,9 @@ impl SyscallCode {
             0x00_00_00_F1 => SyscallCode::HINT_READ,
             0x00_00_01_1D => SyscallCode::UINT256_MUL,
             0x00_00_01_1C => SyscallCode::BLS12381_DECOMPRESS,
-            _ => panic!("invalid syscall number: {}", value),
-        }
+            _ => return None,
+        };
+        Some(syscall)
     }
 
     pub fn syscall_id(&self) -> u32 {
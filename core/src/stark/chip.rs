@@ -24,6 +24,8 @@ pub struct Chip<F: Field, A> {
     sends: Vec<Interaction<F>>,
     /// The interactions that the chip receives.
     receives: Vec<Interaction<F>>,
+    /// The maximum degree of the constraints of the chip, including its interactions.
+    max_constraint_degree: usize,
     /// The relative log degree of the quotient polynomial, i.e. `log2(max_constraint_degree - 1)`.
     log_quotient_degree: usize,
 }
@@ -39,6 +41,11 @@ impl<F: Field, A> Chip<F, A> {
         &self.receives
     }
 
+    /// The maximum degree of the constraints of the chip, including its interactions.
+    pub const fn max_constraint_degree(&self) -> usize {
+        self.max_constraint_degree
+    }
+
     /// The relative log degree of the quotient polynomial, i.e. `log2(max_constraint_degree - 1)`.
     pub const fn log_quotient_degree(&self) -> usize {
         self.log_quotient_degree
@@ -79,6 +86,7 @@ where
             air,
             sends,
             receives,
+            max_constraint_degree,
             log_quotient_degree,
         }
     }
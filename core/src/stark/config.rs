@@ -46,6 +46,15 @@ pub type PcsProverData<SC> = <<SC as StarkGenericConfig>::Pcs as Pcs<
 pub type Challenge<SC> = <SC as StarkGenericConfig>::Challenge;
 pub type Challenger<SC> = <SC as StarkGenericConfig>::Challenger;
 
+/// The downstream operations that proofs over a [StarkGenericConfig] can flow into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConfigCapabilities {
+    /// Whether proofs can be verified by the recursion programs and compressed.
+    pub supports_recursion: bool,
+    /// Whether compressed proofs can be wrapped into a Groth16 or PLONK proof over BN254.
+    pub supports_wrapping: bool,
+}
+
 pub trait StarkGenericConfig: Send + Sync + Serialize + DeserializeOwned + Clone {
     type Val: PrimeField;
 
@@ -67,6 +76,27 @@ pub trait StarkGenericConfig: Send + Sync + Serialize + DeserializeOwned + Clone
 
     /// Initialize a new challenger.
     fn challenger(&self) -> Self::Challenger;
+
+    /// The largest log height of a trace that the PCS can commit to, if it is bounded.
+    fn max_log_trace_height(&self) -> Option<usize> {
+        None
+    }
+
+    /// The conjectured bits of security of proofs over this configuration, if it is known.
+    fn conjectured_security_bits(&self) -> Option<usize> {
+        None
+    }
+
+    /// The log blowup and number of queries of the FRI proofs of the PCS, if it uses FRI.
+    fn fri_query_shape(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// The downstream operations supported by proofs over this configuration. Neither is
+    /// supported unless the configuration opts in.
+    fn capabilities() -> ConfigCapabilities {
+        ConfigCapabilities::default()
+    }
 }
 
 pub struct UniConfig<SC>(pub SC);
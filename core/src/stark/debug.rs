@@ -1,5 +1,5 @@
+use std::backtrace::Backtrace;
 use std::borrow::Borrow;
-use std::panic::{self, AssertUnwindSafe};
 use std::process::exit;
 
 use p3_air::{
@@ -11,10 +11,61 @@ use p3_field::{ExtensionField, Field};
 use p3_matrix::dense::RowMajorMatrixView;
 use p3_matrix::stack::VerticalPair;
 use p3_matrix::{dense::RowMajorMatrix, Matrix};
+use thiserror::Error;
 
 use super::{MachineChip, StarkGenericConfig, Val};
 use crate::air::{EmptyMessageBuilder, MachineAir, MultiTableAirBuilder};
 
+/// A constraint of a machine that is not satisfied by a shard's traces.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConstraintError {
+    /// A constraint of a chip fails at a row. Constraints are indexed in the order the chip
+    /// evaluates them, and `left` and `right` are the values it expected to be equal.
+    #[error("constraint {constraint} failed at row {row} of chip {chip}: {left} != {right}")]
+    Failed {
+        chip: String,
+        row: usize,
+        constraint: usize,
+        left: String,
+        right: String,
+    },
+    /// The interactions between the chips of the machine are not balanced.
+    #[error("cumulative sum is not zero")]
+    NonZeroCumulativeSum,
+}
+
+/// A [ConstraintError] together with the context that [debug_constraints] prints about it.
+pub(crate) struct ConstraintFailure {
+    pub(crate) error: ConstraintError,
+    /// The local and next rows of the main trace that the failing constraint was evaluated on.
+    pub(crate) rows: Option<(String, String)>,
+    /// The backtrace of the failing constraint.
+    pub(crate) backtrace: Option<Backtrace>,
+}
+
+impl ConstraintFailure {
+    pub(crate) fn print(&self) {
+        if let Some((local, next)) = &self.rows {
+            eprintln!("local: {}", local);
+            eprintln!("next:  {}", next);
+        }
+        eprintln!("{}", self.error);
+        if let Some(backtrace) = &self.backtrace {
+            eprintln!("{}", backtrace);
+        }
+    }
+}
+
+impl From<ConstraintError> for ConstraintFailure {
+    fn from(error: ConstraintError) -> Self {
+        Self {
+            error,
+            rows: None,
+            backtrace: None,
+        }
+    }
+}
+
 /// Checks that the constraints of the given AIR are satisfied, including the permutation trace.
 ///
 /// Note that this does not actually verify the proof.
@@ -29,17 +80,73 @@ pub fn debug_constraints<SC, A>(
     SC: StarkGenericConfig,
     Val<SC>: PrimeField32,
     A: MachineAir<Val<SC>> + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+{
+    let result = find_constraint_failure::<SC, A>(
+        chip,
+        preprocessed,
+        main,
+        perm,
+        perm_challenges,
+        public_values,
+    );
+    if let Err(failure) = result {
+        failure.print();
+        exit(1);
+    }
+}
+
+/// Checks that the constraints of the given AIR are satisfied, including the permutation trace,
+/// returning the first constraint that fails.
+///
+/// Note that this does not actually verify the proof.
+pub fn check_chip_constraints<SC, A>(
+    chip: &MachineChip<SC, A>,
+    preprocessed: Option<&RowMajorMatrix<Val<SC>>>,
+    main: &RowMajorMatrix<Val<SC>>,
+    perm: &RowMajorMatrix<SC::Challenge>,
+    perm_challenges: &[SC::Challenge],
+    public_values: Vec<Val<SC>>,
+) -> Result<(), ConstraintError>
+where
+    SC: StarkGenericConfig,
+    Val<SC>: PrimeField32,
+    A: MachineAir<Val<SC>> + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+{
+    find_constraint_failure::<SC, A>(
+        chip,
+        preprocessed,
+        main,
+        perm,
+        perm_challenges,
+        public_values,
+    )
+    .map_err(|failure| failure.error)
+}
+
+/// Like [check_chip_constraints], but keeps the context of the failing constraint.
+pub(crate) fn find_constraint_failure<SC, A>(
+    chip: &MachineChip<SC, A>,
+    preprocessed: Option<&RowMajorMatrix<Val<SC>>>,
+    main: &RowMajorMatrix<Val<SC>>,
+    perm: &RowMajorMatrix<SC::Challenge>,
+    perm_challenges: &[SC::Challenge],
+    public_values: Vec<Val<SC>>,
+) -> Result<(), ConstraintFailure>
+where
+    SC: StarkGenericConfig,
+    Val<SC>: PrimeField32,
+    A: MachineAir<Val<SC>> + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
 {
     assert_eq!(main.height(), perm.height());
     let height = main.height();
     if height == 0 {
-        return;
+        return Ok(());
     }
 
     let cumulative_sum = perm.row_slice(perm.height() - 1).last().copied().unwrap();
 
     // Check that constraints are satisfied.
-    (0..height).for_each(|i| {
+    for i in 0..height {
         let i_next = (i + 1) % height;
 
         let main_local = main.row_slice(i);
@@ -85,6 +192,8 @@ pub fn debug_constraints<SC, A>(
             is_last_row: Val::<SC>::zero(),
             is_transition: Val::<SC>::one(),
             public_values: &public_values,
+            constraint_index: 0,
+            failure: None,
         };
         if i == 0 {
             builder.is_first_row = Val::<SC>::one();
@@ -93,24 +202,22 @@ pub fn debug_constraints<SC, A>(
             builder.is_last_row = Val::<SC>::one();
             builder.is_transition = Val::<SC>::zero();
         }
-        let result = catch_unwind_silent(AssertUnwindSafe(|| {
-            chip.eval(&mut builder);
-        }));
-        if result.is_err() {
-            eprintln!("local: {:?}", main_local);
-            eprintln!("next:  {:?}", main_next);
-            eprintln!("failed at row {} of chip {}", i, chip.name());
-            exit(1);
+        chip.eval(&mut builder);
+        if let Some((constraint, left, right, backtrace)) = builder.failure {
+            return Err(ConstraintFailure {
+                error: ConstraintError::Failed {
+                    chip: chip.name(),
+                    row: i,
+                    constraint,
+                    left,
+                    right,
+                },
+                rows: Some((format!("{:?}", main_local), format!("{:?}", main_next))),
+                backtrace: Some(backtrace),
+            });
         }
-    });
-}
-
-fn catch_unwind_silent<F: FnOnce() -> R + panic::UnwindSafe, R>(f: F) -> std::thread::Result<R> {
-    let prev_hook = panic::take_hook();
-    panic::set_hook(Box::new(|_| {}));
-    let result = panic::catch_unwind(f);
-    panic::set_hook(prev_hook);
-    result
+    }
+    Ok(())
 }
 
 /// Checks that all the interactions between the chips has been satisfied.
@@ -135,6 +242,10 @@ pub struct DebugConstraintBuilder<'a, F: Field, EF: ExtensionField<F>> {
     pub(crate) is_last_row: F,
     pub(crate) is_transition: F,
     pub(crate) public_values: &'a [F],
+    /// The index of the next constraint to be checked.
+    pub(crate) constraint_index: usize,
+    /// The index, two sides and backtrace of the first constraint that failed on the row.
+    pub(crate) failure: Option<(usize, String, String, Backtrace)>,
 }
 
 impl<'a, F, EF> ExtensionBuilder for DebugConstraintBuilder<'a, F, EF>
@@ -150,7 +261,7 @@ where
     where
         I: Into<Self::ExprEF>,
     {
-        assert_eq!(x.into(), EF::zero(), "constraints must evaluate to zero");
+        self.debug_constraint(x.into(), EF::zero());
     }
 }
 
@@ -187,13 +298,21 @@ where
     F: Field,
     EF: ExtensionField<F>,
 {
+    /// Counts a constraint, recording it as the failure of the row if it is the first one that
+    /// does not hold. The remaining constraints of the chip are still evaluated.
     #[inline]
-    fn debug_constraint(&self, x: F, y: F) {
-        if x != y {
-            let backtrace = std::backtrace::Backtrace::force_capture();
-            eprintln!("constraint failed: {:?} != {:?}\n{}", x, y, backtrace);
-            panic!();
+    fn check_constraint(&mut self, holds: bool, sides: impl FnOnce() -> (String, String)) {
+        if !holds && self.failure.is_none() {
+            let (left, right) = sides();
+            let backtrace = Backtrace::force_capture();
+            self.failure = Some((self.constraint_index, left, right, backtrace));
         }
+        self.constraint_index += 1;
+    }
+
+    #[inline]
+    fn debug_constraint<T: Field>(&mut self, x: T, y: T) {
+        self.check_constraint(x == y, || (format!("{:?}", x), format!("{:?}", y)));
     }
 }
 
@@ -242,11 +361,12 @@ where
     /// Assert that `x` is a boolean, i.e. either 0 or 1.
     fn assert_bool<I: Into<Self::Expr>>(&mut self, x: I) {
         let x = x.into();
-        if x != F::zero() && x != F::one() {
-            let backtrace = std::backtrace::Backtrace::force_capture();
-            eprintln!("constraint failed: {:?} is not a bool\n{}", x, backtrace);
-            panic!();
-        }
+        self.check_constraint(x == F::zero() || x == F::one(), || {
+            (
+                format!("{:?}", x),
+                format!("{:?} or {:?}", F::zero(), F::one()),
+            )
+        });
     }
 }
 
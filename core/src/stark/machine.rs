@@ -1,5 +1,6 @@
 use itertools::Itertools;
 use p3_air::Air;
+use p3_air::BaseAir;
 use p3_challenger::CanObserve;
 use p3_challenger::FieldChallenger;
 use p3_commit::Pcs;
@@ -16,9 +17,12 @@ use serde::Serialize;
 use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::process::exit;
 use tracing::instrument;
 
-use super::debug_constraints;
+use super::find_constraint_failure;
+use super::ConstraintError;
+use super::ConstraintFailure;
 use super::Dom;
 use crate::air::MachineAir;
 use crate::air::MachineProgram;
@@ -29,12 +33,15 @@ use crate::stark::record::MachineRecord;
 use crate::stark::DebugConstraintBuilder;
 use crate::stark::ProverConstraintFolder;
 use crate::stark::ShardProof;
+use crate::stark::ShardTranscript;
 use crate::stark::VerifierConstraintFolder;
 use crate::utils::SP1CoreOpts;
 
 use super::Chip;
 use super::Com;
 use super::MachineProof;
+use super::MachineProofChunk;
+use super::OpeningProof;
 use super::PcsProverData;
 use super::Prover;
 use super::StarkGenericConfig;
@@ -53,6 +60,21 @@ pub struct StarkMachine<SC: StarkGenericConfig, A> {
 
     /// The number of public values elements that the machine uses
     num_pv_elts: usize,
+
+    /// The tag observed before the public values of every shard, if any.
+    transcript_domain: Option<[u8; 4]>,
+}
+
+/// Metadata describing a chip of a [StarkMachine].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChipInfo {
+    pub name: String,
+    /// The number of main trace columns.
+    pub width: usize,
+    /// The maximum degree of the constraints of the chip, including its interactions.
+    pub constraint_degree: usize,
+    /// The number of sends and receives of the chip.
+    pub num_interactions: usize,
 }
 
 impl<SC: StarkGenericConfig, A> StarkMachine<SC, A> {
@@ -61,8 +83,24 @@ impl<SC: StarkGenericConfig, A> StarkMachine<SC, A> {
             config,
             chips,
             num_pv_elts,
+            transcript_domain: None,
         }
     }
+
+    /// Sets a domain separator observed into the challenger right before the public values of
+    /// every shard, so that the transcripts of proofs made for different protocols never collide.
+    ///
+    /// The prover and verifier must use the same domain, since a proof only verifies against a
+    /// machine with the domain it was proven with. The recursion verifiers do not observe a domain,
+    /// so a proof made with one cannot be compressed.
+    pub fn with_transcript_domain(mut self, domain: Option<[u8; 4]>) -> Self {
+        self.transcript_domain = domain;
+        self
+    }
+
+    pub const fn transcript_domain(&self) -> Option<[u8; 4]> {
+        self.transcript_domain
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -98,6 +136,28 @@ impl<SC: StarkGenericConfig> StarkVerifyingKey<SC> {
         challenger.observe(self.commit.clone());
         challenger.observe(self.pc_start);
     }
+
+    /// A blake3 digest of the preprocessed commitment, start pc and chip layout of the key.
+    ///
+    /// Two keys have the same fingerprint exactly when they verify the same program, so it can be
+    /// compared to check that a checkpoint or a worker belongs to the program being proven.
+    ///
+    /// The digest is blake3 rather than the hash of `SC`, because [StarkGenericConfig] only
+    /// exposes a hash over field elements inside its PCS, not one over bytes. The fingerprint is
+    /// an off-circuit identifier that is never verified in a proof, so it does not need to match
+    /// the config, and blake3 is already how checkpoints and resumed runs are identified.
+    pub fn fingerprint(&self) -> [u8; 32]
+    where
+        Dom<SC>: Serialize,
+    {
+        let mut hasher = blake3::Hasher::new();
+        bincode::serialize_into(
+            &mut hasher,
+            &(&self.commit, &self.pc_start, &self.chip_information),
+        )
+        .expect("serializing a verifying key into a hasher cannot fail");
+        *hasher.finalize().as_bytes()
+    }
 }
 
 impl<SC: StarkGenericConfig> Debug for StarkVerifyingKey<SC> {
@@ -116,6 +176,34 @@ impl<SC: StarkGenericConfig, A: MachineAir<Val<SC>>> StarkMachine<SC, A> {
         self.num_pv_elts
     }
 
+    /// Observes the public values of a shard, preceded by the transcript domain if one is set.
+    ///
+    /// Only the first [Self::num_pv_elts] elements of `public_values` are observed.
+    pub fn observe_public_values(
+        &self,
+        challenger: &mut SC::Challenger,
+        public_values: &[Val<SC>],
+    ) {
+        if let Some(domain) = self.transcript_domain {
+            challenger.observe_slice(&domain.map(Val::<SC>::from_canonical_u8));
+        }
+        challenger.observe_slice(&public_values[0..self.num_pv_elts]);
+    }
+
+    /// The name, width, constraint degree and number of interactions of each chip, in the order
+    /// of [Self::chips].
+    pub fn chip_info(&self) -> Vec<ChipInfo> {
+        self.chips
+            .iter()
+            .map(|chip| ChipInfo {
+                name: chip.name(),
+                width: chip.width(),
+                constraint_degree: chip.max_constraint_degree(),
+                num_interactions: chip.num_interactions(),
+            })
+            .collect()
+    }
+
     /// Returns the id of all chips in the machine that have preprocessed columns.
     pub fn preprocessed_chip_ids(&self) -> Vec<usize> {
         self.chips
@@ -285,6 +373,25 @@ impl<SC: StarkGenericConfig, A: MachineAir<Val<SC>>> StarkMachine<SC, A> {
         let shards = tracing::info_span!("shard_record")
             .in_scope(|| self.shard(record, &<A::Record as MachineRecord>::Config::default()));
 
+        self.prove_shards::<P>(pk, shards, challenger, opts)
+    }
+
+    /// Commits to and proves shards that were already split from a record, in the given order.
+    ///
+    /// The shards must be complete, as produced by [StarkMachine::shard], including the events
+    /// generated for their dependencies.
+    pub fn prove_shards<P: Prover<SC, A>>(
+        &self,
+        pk: &StarkProvingKey<SC>,
+        shards: Vec<A::Record>,
+        challenger: &mut SC::Challenger,
+        opts: SP1CoreOpts,
+    ) -> MachineProof<SC>
+    where
+        A: for<'a> Air<ProverConstraintFolder<'a, SC>>
+            + Air<InteractionBuilder<Val<SC>>>
+            + for<'a> Air<VerifierConstraintFolder<'a, SC>>,
+    {
         tracing::info_span!("prove_shards")
             .in_scope(|| P::prove_shards(self, pk, shards, challenger, opts))
     }
@@ -305,41 +412,110 @@ impl<SC: StarkGenericConfig, A: MachineAir<Val<SC>>> StarkMachine<SC, A> {
         SC::Challenger: Clone,
         A: for<'a> Air<VerifierConstraintFolder<'a, SC>>,
     {
+        self.observe_proof(vk, proof, challenger)?;
+
+        tracing::debug_span!("verify shard proofs").in_scope(|| {
+            for (i, shard_proof) in proof.shard_proofs.iter().enumerate() {
+                tracing::debug_span!("verifying shard", segment = i)
+                    .in_scope(|| self.verify_shard_at(i, vk, shard_proof, challenger))?;
+            }
+
+            Ok(())
+        })?;
+
+        Self::verify_cumulative_sum(proof)
+    }
+
+    /// Verifies a proof like [Self::verify], first checking that every shard was proven against a
+    /// challenger in the state the verifier reaches after observing the proof.
+    ///
+    /// `digests` holds one [challenger_digest] per shard, taken by the prover of that shard from
+    /// the challenger it proved against. They travel next to the proof rather than in it, so
+    /// proofs serialize the same in every build. A shard proven against a challenger that observed
+    /// different values, such as by a worker of a distributed prover that skipped a commitment,
+    /// fails with [MachineVerificationError::ChallengerDesync] instead of an opaque FRI error.
+    #[cfg(debug_assertions)]
+    pub fn verify_with_challenger_digests(
+        &self,
+        vk: &StarkVerifyingKey<SC>,
+        proof: &MachineProof<SC>,
+        digests: &[SC::Challenge],
+        challenger: &mut SC::Challenger,
+    ) -> Result<(), MachineVerificationError<SC>>
+    where
+        SC::Challenger: Clone,
+        A: for<'a> Air<VerifierConstraintFolder<'a, SC>>,
+    {
+        let mut observed = challenger.clone();
+        self.observe_proof(vk, proof, &mut observed)?;
+        let expected = challenger_digest::<SC>(&observed);
+        if let Some(shard) =
+            (0..proof.shard_proofs.len()).find(|&i| digests.get(i) != Some(&expected))
+        {
+            return Err(MachineVerificationError::ChallengerDesync(shard));
+        }
+        self.verify(vk, proof, challenger)
+    }
+
+    /// Verifies a proof like [Self::verify], verifying its shards in parallel.
+    ///
+    /// Only the observation of the shard commitments is sequential. Each shard is then verified
+    /// against its own copy of the challenger, so the shards are independent.
+    #[instrument("verify parallel", level = "info", skip_all)]
+    pub fn verify_parallel(
+        &self,
+        vk: &StarkVerifyingKey<SC>,
+        proof: &MachineProof<SC>,
+        challenger: &mut SC::Challenger,
+    ) -> Result<(), MachineVerificationError<SC>>
+    where
+        SC::Challenger: Clone + Sync,
+        A: for<'a> Air<VerifierConstraintFolder<'a, SC>> + Sync,
+        Com<SC>: Send + Sync,
+        OpeningProof<SC>: Send + Sync,
+        MachineVerificationError<SC>: Send,
+    {
+        self.observe_proof(vk, proof, challenger)?;
+
+        let challenger = &*challenger;
+        tracing::debug_span!("verify shard proofs").in_scope(|| {
+            proof
+                .shard_proofs
+                .par_iter()
+                .enumerate()
+                .try_for_each(|(i, shard_proof)| {
+                    self.verify_shard_at(i, vk, shard_proof, challenger)
+                })
+        })?;
+
+        Self::verify_cumulative_sum(proof)
+    }
+
+    /// Observes the verifying key and the commitment and public values of every shard of a
+    /// non-empty proof.
+    fn observe_proof(
+        &self,
+        vk: &StarkVerifyingKey<SC>,
+        proof: &MachineProof<SC>,
+        challenger: &mut SC::Challenger,
+    ) -> Result<(), MachineVerificationError<SC>> {
         // Observe the preprocessed commitment.
         vk.observe_into(challenger);
         tracing::debug_span!("observe challenges for all shards").in_scope(|| {
             proof.shard_proofs.iter().for_each(|proof| {
                 challenger.observe(proof.commitment.main_commit.clone());
-                challenger.observe_slice(&proof.public_values[0..self.num_pv_elts()]);
+                self.observe_public_values(challenger, &proof.public_values);
             });
         });
 
-        // Verify the shard proofs.
         if proof.shard_proofs.is_empty() {
             return Err(MachineVerificationError::EmptyProof);
         }
+        Ok(())
+    }
 
-        tracing::debug_span!("verify shard proofs").in_scope(|| {
-            for (i, shard_proof) in proof.shard_proofs.iter().enumerate() {
-                tracing::debug_span!("verifying shard", segment = i).in_scope(|| {
-                    let chips = self
-                        .shard_chips_ordered(&shard_proof.chip_ordering)
-                        .collect::<Vec<_>>();
-                    Verifier::verify_shard(
-                        &self.config,
-                        vk,
-                        &chips,
-                        &mut challenger.clone(),
-                        shard_proof,
-                    )
-                    .map_err(MachineVerificationError::InvalidSegmentProof)
-                })?;
-            }
-
-            Ok(())
-        })?;
-
-        // Verify the cumulative sum is 0.
+    /// Verifies that the cumulative sums of the shards of a proof add up to zero.
+    fn verify_cumulative_sum(proof: &MachineProof<SC>) -> Result<(), MachineVerificationError<SC>> {
         tracing::debug_span!("verify cumulative sum is 0").in_scope(|| {
             let mut sum = SC::Challenge::zero();
             for proof in proof.shard_proofs.iter() {
@@ -352,6 +528,103 @@ impl<SC: StarkGenericConfig, A: MachineAir<Val<SC>>> StarkMachine<SC, A> {
         })
     }
 
+    /// Verifies the constraints and opening proof of a single shard, such as one received from an
+    /// untrusted worker, before it is assembled into a [MachineProof].
+    ///
+    /// `challenger` must have observed the verifying key and the commitment and public values of
+    /// every shard of the proof, as [Self::verify] does. The cumulative sum is not checked, since it
+    /// only vanishes across all the shards of a proof.
+    ///
+    /// Errors identify the shard by the position it takes in a complete proof, one less than its
+    /// [ShardProof::shard_index], like those of [Self::verify].
+    pub fn verify_shard(
+        &self,
+        vk: &StarkVerifyingKey<SC>,
+        shard_proof: &ShardProof<SC>,
+        challenger: &SC::Challenger,
+    ) -> Result<(), MachineVerificationError<SC>>
+    where
+        SC::Challenger: Clone,
+        Val<SC>: PrimeField32,
+        A: for<'a> Air<VerifierConstraintFolder<'a, SC>>,
+    {
+        let index = (shard_proof.shard_index() as usize).saturating_sub(1);
+        self.verify_shard_at(index, vk, shard_proof, challenger)
+    }
+
+    /// Verifies a chunk of a proof made by [MachineProof::split_into_chunks] without the rest of
+    /// its shard proofs.
+    ///
+    /// `challenger` observes the verifying key and the transcripts of the shards outside of the
+    /// chunk in place of their proofs, and the cumulative sums of all the shards must vanish.
+    pub fn verify_chunk(
+        &self,
+        vk: &StarkVerifyingKey<SC>,
+        chunk: &MachineProofChunk<SC>,
+        challenger: &mut SC::Challenger,
+    ) -> Result<(), MachineVerificationError<SC>>
+    where
+        SC::Challenger: Clone,
+        A: for<'a> Air<VerifierConstraintFolder<'a, SC>>,
+    {
+        if chunk.shard_proofs.is_empty() {
+            return Err(MachineVerificationError::EmptyProof);
+        }
+
+        // Observe every shard of the proof in order, with the chunk's shards in the middle.
+        vk.observe_into(challenger);
+        let chunk_transcripts = chunk.shard_proofs.iter().map(ShardTranscript::new);
+        let transcripts = chunk
+            .before
+            .iter()
+            .cloned()
+            .chain(chunk_transcripts)
+            .chain(chunk.after.iter().cloned())
+            .collect::<Vec<_>>();
+        for transcript in transcripts.iter() {
+            challenger.observe(transcript.main_commit.clone());
+            self.observe_public_values(challenger, &transcript.public_values);
+        }
+
+        for (index, shard_proof) in chunk.shard_range().zip(chunk.shard_proofs.iter()) {
+            self.verify_shard_at(index, vk, shard_proof, challenger)?;
+        }
+
+        let sum = transcripts
+            .iter()
+            .map(|transcript| transcript.cumulative_sum)
+            .sum::<SC::Challenge>();
+        match sum.is_zero() {
+            true => Ok(()),
+            false => Err(MachineVerificationError::NonZeroCumulativeSum),
+        }
+    }
+
+    /// Verifies the shard proof at `index` against its own copy of `challenger`.
+    fn verify_shard_at(
+        &self,
+        index: usize,
+        vk: &StarkVerifyingKey<SC>,
+        shard_proof: &ShardProof<SC>,
+        challenger: &SC::Challenger,
+    ) -> Result<(), MachineVerificationError<SC>>
+    where
+        SC::Challenger: Clone,
+        A: for<'a> Air<VerifierConstraintFolder<'a, SC>>,
+    {
+        let chips = self
+            .shard_chips_ordered(&shard_proof.chip_ordering)
+            .collect::<Vec<_>>();
+        Verifier::verify_shard(
+            &self.config,
+            vk,
+            &chips,
+            &mut challenger.clone(),
+            shard_proof,
+        )
+        .map_err(|e| MachineVerificationError::InvalidSegmentProof(index, e))
+    }
+
     #[instrument("debug constraints", level = "debug", skip_all)]
     pub fn debug_constraints(
         &self,
@@ -365,6 +638,57 @@ impl<SC: StarkGenericConfig, A: MachineAir<Val<SC>>> StarkMachine<SC, A> {
         tracing::debug!("sharding the execution record");
         let shards = self.shard(record, &<A::Record as MachineRecord>::Config::default());
 
+        match self.check_shard_constraints(pk, &shards, challenger) {
+            Ok(()) => {}
+            Err(ConstraintFailure {
+                error: ConstraintError::NonZeroCumulativeSum,
+                ..
+            }) => {
+                debug_interactions_with_all_chips::<SC, A>(
+                    self,
+                    pk,
+                    &shards,
+                    InteractionKind::all_kinds(),
+                );
+                panic!("Cumulative sum is not zero");
+            }
+            Err(failure) => {
+                failure.print();
+                exit(1);
+            }
+        }
+    }
+
+    /// Checks the constraints of every chip on the shards of a record, returning the first
+    /// constraint that fails instead of panicking.
+    ///
+    /// Unlike [Self::debug_constraints], which the prover only calls with the `debug` feature,
+    /// this can be called on demand. Note that this does not actually verify a proof.
+    pub fn check_constraints(
+        &self,
+        pk: &StarkProvingKey<SC>,
+        record: A::Record,
+        challenger: &mut SC::Challenger,
+    ) -> Result<(), ConstraintError>
+    where
+        SC::Val: PrimeField32,
+        A: for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+    {
+        let shards = self.shard(record, &<A::Record as MachineRecord>::Config::default());
+        self.check_shard_constraints(pk, &shards, challenger)
+            .map_err(|failure| failure.error)
+    }
+
+    fn check_shard_constraints(
+        &self,
+        pk: &StarkProvingKey<SC>,
+        shards: &[A::Record],
+        challenger: &mut SC::Challenger,
+    ) -> Result<(), ConstraintFailure>
+    where
+        SC::Val: PrimeField32,
+        A: for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+    {
         tracing::debug!("checking constraints for each shard");
 
         let mut cumulative_sum = SC::Challenge::zero();
@@ -440,46 +764,68 @@ impl<SC: StarkGenericConfig, A: MachineAir<Val<SC>>> StarkMachine<SC, A> {
                         .chip_ordering
                         .get(&chips[i].name())
                         .map(|index| &pk.traces[*index]);
-                    debug_constraints::<SC, A>(
+                    find_constraint_failure::<SC, A>(
                         chips[i],
                         permutation_trace,
                         &traces[i].0,
                         &permutation_traces[i],
                         &permutation_challenges,
                         shard.public_values(),
-                    );
+                    )?;
                 }
-            });
+                Ok::<_, ConstraintFailure>(())
+            })?;
         }
 
-        // If the cumulative sum is not zero, debug the interactions.
         if !cumulative_sum.is_zero() {
-            debug_interactions_with_all_chips::<SC, A>(
-                self,
-                pk,
-                &shards,
-                InteractionKind::all_kinds(),
-            );
-            panic!("Cumulative sum is not zero");
+            return Err(ConstraintError::NonZeroCumulativeSum.into());
         }
+        Ok(())
     }
 }
 
+/// Samples a digest of the challenger's state from a copy, leaving the challenger unchanged.
+///
+/// A prover records the digest of the challenger it proves each shard against, to be checked by
+/// [StarkMachine::verify_with_challenger_digests].
+#[cfg(debug_assertions)]
+pub fn challenger_digest<SC>(challenger: &SC::Challenger) -> SC::Challenge
+where
+    SC: StarkGenericConfig,
+    SC::Challenger: Clone,
+{
+    challenger.clone().sample_ext_element()
+}
+
+/// An error returned when verifying a [MachineProof].
+///
+/// Shards are identified by their position in [MachineProof::shard_proofs], counted from zero.
 pub enum MachineVerificationError<SC: StarkGenericConfig> {
-    InvalidSegmentProof(VerificationError<SC>),
+    /// The proof of the shard at the given position does not verify.
+    InvalidSegmentProof(usize, VerificationError<SC>),
     InvalidGlobalProof(VerificationError<SC>),
     NonZeroCumulativeSum,
     InvalidPublicValuesDigest,
     DebugInteractionsFailed,
     EmptyProof,
     InvalidPublicValues(&'static str),
+    /// The shard at the given position was proven against a challenger in a different state than
+    /// the verifier's, as found by [StarkMachine::verify_with_challenger_digests].
+    ChallengerDesync(usize),
+    /// The verifier's transcript diverged from the prover's at the given event, as found by
+    /// [crate::utils::run_test_machine_with_transcripts]. Unlike the other variants, this is not
+    /// the position of a shard.
+    TranscriptDivergence(usize),
+    /// The shard at the given position committed to a different public values digest than the
+    /// execution.
+    PublicValuesDigestMismatch(usize),
 }
 
 impl<SC: StarkGenericConfig> Debug for MachineVerificationError<SC> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            MachineVerificationError::InvalidSegmentProof(e) => {
-                write!(f, "Invalid segment proof: {:?}", e)
+            MachineVerificationError::InvalidSegmentProof(shard, e) => {
+                write!(f, "Invalid segment proof at shard {}: {:?}", shard, e)
             }
             MachineVerificationError::InvalidGlobalProof(e) => {
                 write!(f, "Invalid global proof: {:?}", e)
@@ -499,6 +845,27 @@ impl<SC: StarkGenericConfig> Debug for MachineVerificationError<SC> {
             MachineVerificationError::InvalidPublicValues(s) => {
                 write!(f, "Invalid public values: {}", s)
             }
+            MachineVerificationError::ChallengerDesync(shard) => {
+                write!(
+                    f,
+                    "Challenger state diverged from the prover's at shard {}",
+                    shard
+                )
+            }
+            MachineVerificationError::TranscriptDivergence(event) => {
+                write!(
+                    f,
+                    "Challenger transcript diverged from the prover's at event {}",
+                    event
+                )
+            }
+            MachineVerificationError::PublicValuesDigestMismatch(shard) => {
+                write!(
+                    f,
+                    "Shard {} committed to a different public values digest than the execution",
+                    shard
+                )
+            }
         }
     }
 }
@@ -515,6 +882,7 @@ impl<SC: StarkGenericConfig> std::error::Error for MachineVerificationError<SC>
 #[allow(non_snake_case)]
 pub mod tests {
 
+    use crate::alu::AluEvent;
     use crate::io::SP1Stdin;
     use crate::runtime::tests::fibonacci_program;
     use crate::runtime::tests::simple_memory_program;
@@ -523,15 +891,49 @@ pub mod tests {
     use crate::runtime::Instruction;
     use crate::runtime::Opcode;
     use crate::runtime::Program;
+    use crate::runtime::Runtime;
+    use crate::runtime::ShardingConfig;
+    use crate::stark::ConstraintError;
+    use crate::stark::LocalProver;
+    use crate::stark::MachineProof;
+    use crate::stark::MachineProofChunk;
+    use crate::stark::MachineVerificationError;
     use crate::stark::RiscvAir;
+    use crate::stark::StarkGenericConfig;
     use crate::stark::StarkProvingKey;
     use crate::stark::StarkVerifyingKey;
     use crate::utils;
-    use crate::utils::prove;
+    use crate::utils::log_transcript_divergence;
+    use crate::utils::prove_with_options;
     use crate::utils::run_test;
     use crate::utils::setup_logger;
+    use crate::utils::verify_machine_proof;
     use crate::utils::BabyBearPoseidon2;
+    use crate::utils::ProveOptions;
+    use crate::utils::RecordingConfig;
     use crate::utils::SP1CoreOpts;
+    use crate::utils::SP1CoreProverError;
+
+    /// Proves the fibonacci program with `options` and checks that the proof verifies, returning
+    /// the proof and the verifying key of the program.
+    pub fn prove_fibonacci(
+        options: ProveOptions,
+    ) -> Result<
+        (
+            MachineProof<BabyBearPoseidon2>,
+            StarkVerifyingKey<BabyBearPoseidon2>,
+        ),
+        SP1CoreProverError,
+    > {
+        setup_logger();
+        let program = fibonacci_program();
+        let config = BabyBearPoseidon2::new();
+        let (proof, _) =
+            prove_with_options(program.clone(), &SP1Stdin::new(), config.clone(), options)?;
+        let (_, vk) = RiscvAir::machine(config.clone()).setup(&program);
+        verify_machine_proof(&vk, &proof, config).unwrap();
+        Ok((proof, vk))
+    }
 
     #[test]
     fn test_simple_prove() {
@@ -540,6 +942,109 @@ pub mod tests {
         run_test(program).unwrap();
     }
 
+    #[test]
+    fn test_simple_prove_shards() {
+        utils::setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let (pk, vk) = machine.setup(runtime.program.as_ref());
+        let shards = machine.shard(runtime.record, &ShardingConfig::default());
+        let mut challenger = machine.config().challenger();
+        let proof = machine.prove_shards::<LocalProver<_, _>>(
+            &pk,
+            shards,
+            &mut challenger,
+            SP1CoreOpts::default(),
+        );
+
+        let mut challenger = machine.config().challenger();
+        machine.verify(&vk, &proof, &mut challenger).unwrap();
+    }
+
+    #[test]
+    fn test_simple_verify_challenger_desync() {
+        use p3_baby_bear::BabyBear;
+        use p3_challenger::CanObserve;
+        use p3_field::AbstractField;
+
+        utils::setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+
+        let machine = RiscvAir::machine(RecordingConfig(BabyBearPoseidon2::new()));
+        let (pk, vk) = machine.setup(runtime.program.as_ref());
+        let mut prover_challenger = machine.config().challenger();
+        let proof = machine.prove::<LocalProver<_, _>>(
+            &pk,
+            runtime.record,
+            &mut prover_challenger,
+            SP1CoreOpts::default(),
+        );
+
+        // A verifier that observes a value the prover did not fails, and the recorded transcripts
+        // point at the first observation.
+        let mut verifier_challenger = machine.config().challenger();
+        verifier_challenger.observe(BabyBear::one());
+        assert!(machine
+            .verify(&vk, &proof, &mut verifier_challenger)
+            .is_err());
+        let divergence = log_transcript_divergence(
+            prover_challenger.transcript(),
+            verifier_challenger.transcript(),
+        );
+        assert_eq!(divergence, Some(0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_simple_verify_with_challenger_digests() {
+        use p3_baby_bear::BabyBear;
+        use p3_challenger::CanObserve;
+        use p3_field::AbstractField;
+
+        use crate::stark::challenger_digest;
+
+        utils::setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let (pk, vk) = machine.setup(runtime.program.as_ref());
+        let mut prover_challenger = machine.config().challenger();
+        let proof = machine.prove::<LocalProver<_, _>>(
+            &pk,
+            runtime.record,
+            &mut prover_challenger,
+            SP1CoreOpts::default(),
+        );
+
+        // Every shard is proven against a copy of the challenger that observed the whole proof.
+        let digest = challenger_digest::<BabyBearPoseidon2>(&prover_challenger);
+        let digests = vec![digest; proof.shard_proofs.len()];
+        let mut challenger = machine.config().challenger();
+        machine
+            .verify_with_challenger_digests(&vk, &proof, &digests, &mut challenger)
+            .unwrap();
+
+        // A prover whose challenger observed a value the verifier's did not is caught at its shard.
+        let mut desynced = prover_challenger.clone();
+        desynced.observe(BabyBear::one());
+        let mut digests = digests;
+        let last = digests.len() - 1;
+        digests[last] = challenger_digest::<BabyBearPoseidon2>(&desynced);
+        let mut challenger = machine.config().challenger();
+        let result = machine.verify_with_challenger_digests(&vk, &proof, &digests, &mut challenger);
+        assert!(matches!(
+            result,
+            Err(MachineVerificationError::ChallengerDesync(shard)) if shard == last
+        ));
+    }
+
     #[test]
     fn test_shift_prove() {
         utils::setup_logger();
@@ -676,16 +1181,166 @@ pub mod tests {
 
     #[test]
     fn test_fibonacci_prove_batch() {
+        prove_fibonacci(ProveOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn test_verify_parallel() {
+        use p3_field::AbstractField;
+
+        let (mut proof, vk) = prove_fibonacci(ProveOptions::default()).unwrap();
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let verify = |proof: &MachineProof<BabyBearPoseidon2>| {
+            let sequential = machine.verify(&vk, proof, &mut machine.config().challenger());
+            let parallel = machine.verify_parallel(&vk, proof, &mut machine.config().challenger());
+            (sequential.is_ok(), parallel.is_ok())
+        };
+        assert_eq!(verify(&proof), (true, true));
+
+        let last = proof.shard_proofs.len() - 1;
+        proof.shard_proofs[last].opened_values.chips[0].main.local[0] +=
+            <BabyBearPoseidon2 as StarkGenericConfig>::Challenge::one();
+        assert_eq!(verify(&proof), (false, false));
+
+        // Every entry point reports the bad shard at the same position.
+        let mut challenger = machine.config().challenger();
+        let result = machine.verify(&vk, &proof, &mut challenger);
+        assert!(matches!(
+            result,
+            Err(MachineVerificationError::InvalidSegmentProof(shard, _)) if shard == last
+        ));
+        let result = machine.verify_shard(&vk, &proof.shard_proofs[last], &challenger);
+        assert!(matches!(
+            result,
+            Err(MachineVerificationError::InvalidSegmentProof(shard, _)) if shard == last
+        ));
+    }
+
+    #[test]
+    fn test_verify_chunks() {
+        use p3_baby_bear::BabyBear;
+        use p3_field::AbstractField;
+
+        let opts = SP1CoreOpts {
+            shard_size: 1 << 10,
+            shard_batch_size: 0,
+            ..Default::default()
+        };
+        let (proof, vk) = prove_fibonacci(ProveOptions::from(opts)).unwrap();
+        let num_shards = proof.shard_proofs.len();
+        assert!(num_shards > 2);
+
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let chunks = proof.split_into_chunks(2);
+        assert_eq!(chunks.len(), num_shards.div_ceil(2));
+        for chunk in chunks.iter() {
+            assert_eq!(chunk.num_shards(), num_shards);
+            let bytes = bincode::serialize(chunk).unwrap();
+            let chunk: MachineProofChunk<BabyBearPoseidon2> = bincode::deserialize(&bytes).unwrap();
+            machine
+                .verify_chunk(&vk, &chunk, &mut machine.config().challenger())
+                .unwrap();
+        }
+        assert_eq!(chunks[1].shard_range(), 2..4.min(num_shards));
+        let whole = proof.split_into_chunks(0);
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0].shard_range(), 0..num_shards);
+
+        // A chunk is verified against the commitments of the shards outside of it.
+        let mut chunk = chunks[1].clone();
+        chunk.before[0].public_values[0] += BabyBear::one();
+        assert!(machine
+            .verify_chunk(&vk, &chunk, &mut machine.config().challenger())
+            .is_err());
+
+        let mut chunk = chunks[0].clone();
+        chunk.after[0].cumulative_sum +=
+            <BabyBearPoseidon2 as StarkGenericConfig>::Challenge::one();
+        assert!(matches!(
+            machine.verify_chunk(&vk, &chunk, &mut machine.config().challenger()),
+            Err(MachineVerificationError::NonZeroCumulativeSum)
+        ));
+    }
+
+    #[test]
+    fn test_chip_info() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let info = machine.chip_info();
+        assert_eq!(info.len(), machine.chips().len());
+
+        let cpu = info.iter().find(|chip| chip.name == "CPU").unwrap();
+        assert!(cpu.width > 0);
+        assert!(cpu.num_interactions > 0);
+        // Chips with interactions have constraints of degree at least three.
+        assert!(info
+            .iter()
+            .filter(|chip| chip.num_interactions > 0)
+            .all(|chip| chip.constraint_degree >= 3));
+    }
+
+    #[test]
+    fn test_transcript_domain() {
         setup_logger();
-        let program = fibonacci_program();
-        let stdin = SP1Stdin::new();
-        prove(
-            program,
-            &stdin,
-            BabyBearPoseidon2::new(),
-            SP1CoreOpts::default(),
-        )
-        .unwrap();
+        let program = simple_program();
+        let options = ProveOptions {
+            transcript_domain: Some(*b"SP1A"),
+            ..Default::default()
+        };
+        let config = BabyBearPoseidon2::new();
+        let (proof, _) =
+            prove_with_options(program.clone(), &SP1Stdin::new(), config.clone(), options).unwrap();
+
+        let verify = |domain| {
+            let machine = RiscvAir::machine(config.clone()).with_transcript_domain(domain);
+            let (_, vk) = machine.setup(&program);
+            let mut challenger = machine.config().challenger();
+            machine.verify(&vk, &proof, &mut challenger)
+        };
+        verify(Some(*b"SP1A")).unwrap();
+        assert!(verify(Some(*b"SP1B")).is_err());
+        assert!(verify(None).is_err());
+    }
+
+    #[test]
+    fn test_simple_check_constraints() {
+        setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let config = BabyBearPoseidon2::new();
+        let machine = RiscvAir::machine(config);
+        let (pk, _) = machine.setup(runtime.program.as_ref());
+        let mut challenger = machine.config().challenger();
+        machine
+            .check_constraints(&pk, runtime.record, &mut challenger)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_simple_check_constraints_failed() {
+        setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let shard = runtime.record.public_values.shard;
+        runtime
+            .record
+            .add_events
+            .push(AluEvent::new(shard, 0, 0, Opcode::ADD, 5, 2, 2));
+        let config = BabyBearPoseidon2::new();
+        let machine = RiscvAir::machine(config);
+        let (pk, _) = machine.setup(runtime.program.as_ref());
+        let mut challenger = machine.config().challenger();
+        let result = machine.check_constraints(&pk, runtime.record, &mut challenger);
+        match result {
+            Err(ConstraintError::Failed {
+                chip, left, right, ..
+            }) => {
+                assert_eq!(chip, "AddSub");
+                assert_ne!(left, right);
+            }
+            _ => panic!("expected a failed constraint, got {:?}", result),
+        }
     }
 
     #[test]
@@ -701,6 +1356,16 @@ pub mod tests {
         run_test(program).unwrap();
     }
 
+    #[test]
+    fn test_vk_fingerprint() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let (_, vk) = machine.setup(&simple_program());
+        let (_, same_vk) = machine.setup(&simple_program());
+        let (_, other_vk) = machine.setup(&fibonacci_program());
+        assert_eq!(vk.fingerprint(), same_vk.fingerprint());
+        assert_ne!(vk.fingerprint(), other_vk.fingerprint());
+    }
+
     #[test]
     fn test_key_serde() {
         let program = ssz_withdrawals_program();
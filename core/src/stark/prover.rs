@@ -88,8 +88,7 @@ where
                 .zip(shards.iter())
                 .for_each(|(commitment, shard)| {
                     challenger.observe(commitment);
-                    challenger
-                        .observe_slice(&shard.public_values::<SC::Val>()[0..machine.num_pv_elts()]);
+                    machine.observe_public_values(challenger, &shard.public_values::<SC::Val>());
                 });
         });
 
@@ -148,6 +147,85 @@ where
 
 pub struct LocalProver<SC, A>(PhantomData<SC>, PhantomData<A>);
 
+/// Commits to and proves individual shards.
+///
+/// The proving functions that prove shards one at a time are generic over this trait, so shards
+/// can be committed and proven by a prover other than [LocalProver], for example on remote
+/// workers.
+pub trait ShardProver<SC: StarkGenericConfig, A: MachineAir<Val<SC>>> {
+    /// Generates the traces of a shard and commits to them.
+    fn commit_main(
+        config: &SC,
+        machine: &StarkMachine<SC, A>,
+        shard: &A::Record,
+        index: usize,
+    ) -> ShardMainData<SC>;
+
+    /// Commits to the traces of each shard, returning the commitments and the committed data.
+    fn commit_shards(
+        machine: &StarkMachine<SC, A>,
+        shards: &[A::Record],
+        opts: SP1CoreOpts,
+    ) -> (Vec<Com<SC>>, Vec<ShardMainDataWrapper<SC>>);
+
+    /// Proves a committed shard against a challenger that has observed the commitments of every
+    /// shard.
+    fn prove_shard(
+        config: &SC,
+        pk: &StarkProvingKey<SC>,
+        chips: &[&MachineChip<SC, A>],
+        shard_data: ShardMainData<SC>,
+        challenger: &mut SC::Challenger,
+    ) -> ShardProof<SC>
+    where
+        A: for<'a> Air<ProverConstraintFolder<'a, SC>>
+            + Air<InteractionBuilder<Val<SC>>>
+            + for<'a> Air<VerifierConstraintFolder<'a, SC>>;
+}
+
+impl<SC, A> ShardProver<SC, A> for LocalProver<SC, A>
+where
+    SC::Val: PrimeField32,
+    SC: StarkGenericConfig + Send + Sync,
+    SC::Challenger: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    A: MachineAir<Val<SC>>,
+{
+    fn commit_main(
+        config: &SC,
+        machine: &StarkMachine<SC, A>,
+        shard: &A::Record,
+        index: usize,
+    ) -> ShardMainData<SC> {
+        LocalProver::commit_main(config, machine, shard, index)
+    }
+
+    fn commit_shards(
+        machine: &StarkMachine<SC, A>,
+        shards: &[A::Record],
+        opts: SP1CoreOpts,
+    ) -> (Vec<Com<SC>>, Vec<ShardMainDataWrapper<SC>>) {
+        LocalProver::commit_shards(machine, shards, opts)
+    }
+
+    fn prove_shard(
+        config: &SC,
+        pk: &StarkProvingKey<SC>,
+        chips: &[&MachineChip<SC, A>],
+        shard_data: ShardMainData<SC>,
+        challenger: &mut SC::Challenger,
+    ) -> ShardProof<SC>
+    where
+        A: for<'a> Air<ProverConstraintFolder<'a, SC>>
+            + Air<InteractionBuilder<Val<SC>>>
+            + for<'a> Air<VerifierConstraintFolder<'a, SC>>,
+    {
+        LocalProver::prove_shard(config, pk, chips, shard_data, challenger)
+    }
+}
+
 impl<SC, A> LocalProver<SC, A>
 where
     SC: StarkGenericConfig,
@@ -570,21 +648,24 @@ where
                     shard_batch
                         .iter()
                         .map(|shard| {
-                            tracing::debug_span!(parent: &parent_span, "commit to shard").in_scope(
-                                || {
-                                    let index = shard.index();
-                                    let data =
-                                        Self::commit_main(config, machine, shard, index as usize);
-                                    finished.fetch_add(1, Ordering::Relaxed);
-                                    let commitment = data.main_commit.clone();
-                                    let data = if reconstruct_commitments {
-                                        ShardMainDataWrapper::Empty()
-                                    } else {
-                                        data.to_in_memory()
-                                    };
-                                    (commitment, data)
-                                },
+                            tracing::debug_span!(
+                                parent: &parent_span,
+                                "commit to shard",
+                                shard_index = shard.index()
                             )
+                            .in_scope(|| {
+                                let index = shard.index();
+                                let data =
+                                    Self::commit_main(config, machine, shard, index as usize);
+                                finished.fetch_add(1, Ordering::Relaxed);
+                                let commitment = data.main_commit.clone();
+                                let data = if reconstruct_commitments {
+                                    ShardMainDataWrapper::Empty()
+                                } else {
+                                    data.to_in_memory()
+                                };
+                                (commitment, data)
+                            })
                         })
                         .collect::<Vec<_>>()
                 })
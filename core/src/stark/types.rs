@@ -1,11 +1,14 @@
 use std::{
+    cmp::Ordering,
     collections::HashMap,
     fmt::Debug,
     fs::File,
     io::{BufReader, BufWriter, Seek},
+    ops::Range,
 };
 
 use bincode::{deserialize_from, Error};
+use p3_field::PrimeField32;
 use p3_matrix::dense::RowMajorMatrix;
 use p3_matrix::dense::RowMajorMatrixView;
 use p3_matrix::stack::VerticalPair;
@@ -14,6 +17,7 @@ use size::Size;
 use tracing::trace;
 
 use super::{Challenge, Com, OpeningProof, PcsProverData, StarkGenericConfig, Val};
+use crate::air::{PublicValues, Word};
 
 pub type QuotientOpenedValues<T> = Vec<T>;
 
@@ -151,6 +155,16 @@ impl<T: Send + Sync + Clone> AirOpenedValues<T> {
 }
 
 impl<SC: StarkGenericConfig> ShardProof<SC> {
+    /// The index of the shard, as committed to in its public values. Shards are numbered from one.
+    pub fn shard_index(&self) -> u32
+    where
+        Val<SC>: PrimeField32,
+    {
+        PublicValues::<Word<Val<SC>>, Val<SC>>::from_vec(self.public_values.clone())
+            .shard
+            .as_canonical_u32()
+    }
+
     pub fn cumulative_sum(&self) -> Challenge<SC> {
         self.opened_values
             .chips
@@ -158,6 +172,52 @@ impl<SC: StarkGenericConfig> ShardProof<SC> {
             .map(|c| c.cumulative_sum)
             .sum()
     }
+
+    /// The sizes of the components of this proof, failing if a component cannot be serialized.
+    pub fn size_breakdown(&self) -> Result<ShardProofSizes, bincode::Error> {
+        let main_commit_bytes = bincode::serialized_size(&self.commitment.main_commit)? as usize;
+        let opening_proof_bytes = bincode::serialized_size(&self.opening_proof)? as usize;
+        let quotient_degree = self
+            .opened_values
+            .chips
+            .iter()
+            .map(|chip| chip.quotient.len())
+            .max()
+            .unwrap_or(0);
+        let num_openings = self
+            .opened_values
+            .chips
+            .iter()
+            .map(|chip| {
+                chip.preprocessed.local.len()
+                    + chip.preprocessed.next.len()
+                    + chip.main.local.len()
+                    + chip.main.next.len()
+                    + chip.permutation.local.len()
+                    + chip.permutation.next.len()
+                    + chip.quotient.iter().map(Vec::len).sum::<usize>()
+            })
+            .sum();
+        Ok(ShardProofSizes {
+            main_commit_bytes,
+            opening_proof_bytes,
+            quotient_degree,
+            num_openings,
+        })
+    }
+}
+
+/// The sizes of the components of a [ShardProof].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardProofSizes {
+    /// The serialized size of the commitment to the main trace, in bytes.
+    pub main_commit_bytes: usize,
+    /// The serialized size of the PCS opening proof, in bytes.
+    pub opening_proof_bytes: usize,
+    /// The largest number of quotient chunks of any chip.
+    pub quotient_degree: usize,
+    /// The number of opened values across all chips.
+    pub num_openings: usize,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -166,6 +226,46 @@ pub struct MachineProof<SC: StarkGenericConfig> {
     pub shard_proofs: Vec<ShardProof<SC>>,
 }
 
+/// An error returned by [MachineProof::merge] when the shards of the parts are not the contiguous
+/// sequence of shards of one program.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum MergeError {
+    #[error("shard {0} is proven more than once")]
+    DuplicateShard(u32),
+    #[error("shard {0} is missing")]
+    MissingShard(u32),
+}
+
+impl<SC: StarkGenericConfig> MachineProof<SC>
+where
+    Val<SC>: PrimeField32,
+{
+    /// Merges proofs of disjoint sets of shards of a program, such as the proofs of its
+    /// checkpoints, into one proof with its shards in order.
+    ///
+    /// The shards of all the parts together must be numbered from one without duplicates or gaps.
+    pub fn merge(parts: Vec<MachineProof<SC>>) -> Result<MachineProof<SC>, MergeError> {
+        let mut shard_proofs = parts
+            .into_iter()
+            .flat_map(|part| part.shard_proofs)
+            .map(|proof| (proof.shard_index(), proof))
+            .collect::<Vec<_>>();
+        shard_proofs.sort_by_key(|(index, _)| *index);
+
+        for (expected, (index, _)) in (1..).zip(shard_proofs.iter()) {
+            match index.cmp(&expected) {
+                Ordering::Less => return Err(MergeError::DuplicateShard(*index)),
+                Ordering::Greater => return Err(MergeError::MissingShard(expected)),
+                Ordering::Equal => {}
+            }
+        }
+
+        Ok(MachineProof {
+            shard_proofs: shard_proofs.into_iter().map(|(_, proof)| proof).collect(),
+        })
+    }
+}
+
 impl<SC: StarkGenericConfig> Debug for MachineProof<SC> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Proof")
@@ -174,6 +274,87 @@ impl<SC: StarkGenericConfig> Debug for MachineProof<SC> {
     }
 }
 
+/// The values of a shard proof that the verifier of every other shard of the proof depends on.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct ShardTranscript<SC: StarkGenericConfig> {
+    /// The commitment to the main trace of the shard, observed before any shard is verified.
+    pub main_commit: Com<SC>,
+    /// The public values of the shard, observed after its commitment.
+    pub public_values: Vec<Val<SC>>,
+    /// The cumulative sum claimed by the shard, which must cancel out with those of the others.
+    pub cumulative_sum: Challenge<SC>,
+}
+
+impl<SC: StarkGenericConfig> ShardTranscript<SC> {
+    pub fn new(proof: &ShardProof<SC>) -> Self {
+        Self {
+            main_commit: proof.commitment.main_commit.clone(),
+            public_values: proof.public_values.clone(),
+            cumulative_sum: proof.cumulative_sum(),
+        }
+    }
+}
+
+/// A contiguous range of the shard proofs of a [MachineProof], made by
+/// [MachineProof::split_into_chunks] and verified on its own by
+/// [super::StarkMachine::verify_chunk].
+///
+/// The challenger every shard is verified against observes the commitments and public values of
+/// all the shards of the proof, so a chunk carries the [ShardTranscript] of every shard outside of
+/// it. Verifying every chunk of a proof, with the same shards before and after each one, is
+/// equivalent to verifying the whole proof.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound = "")]
+pub struct MachineProofChunk<SC: StarkGenericConfig> {
+    /// The transcripts of the shards of the proof before the chunk.
+    pub before: Vec<ShardTranscript<SC>>,
+    pub shard_proofs: Vec<ShardProof<SC>>,
+    /// The transcripts of the shards of the proof after the chunk.
+    pub after: Vec<ShardTranscript<SC>>,
+}
+
+impl<SC: StarkGenericConfig> MachineProofChunk<SC> {
+    /// The range of the shards of the chunk within the proof.
+    pub fn shard_range(&self) -> Range<usize> {
+        self.before.len()..self.before.len() + self.shard_proofs.len()
+    }
+
+    /// The number of shards of the whole proof.
+    pub fn num_shards(&self) -> usize {
+        self.before.len() + self.shard_proofs.len() + self.after.len()
+    }
+}
+
+impl<SC: StarkGenericConfig> MachineProof<SC> {
+    /// Splits the proof into chunks of at most `max_shards_per_chunk` shard proofs, in order, that
+    /// can each be sent and verified separately.
+    ///
+    /// A `max_shards_per_chunk` of zero puts the whole proof in a single chunk.
+    pub fn split_into_chunks(&self, max_shards_per_chunk: usize) -> Vec<MachineProofChunk<SC>> {
+        let max_shards_per_chunk = match max_shards_per_chunk {
+            0 => self.shard_proofs.len().max(1),
+            n => n,
+        };
+        let transcripts = self
+            .shard_proofs
+            .iter()
+            .map(ShardTranscript::new)
+            .collect::<Vec<_>>();
+        (0..self.shard_proofs.len())
+            .step_by(max_shards_per_chunk)
+            .map(|start| {
+                let end = (start + max_shards_per_chunk).min(self.shard_proofs.len());
+                MachineProofChunk {
+                    before: transcripts[..start].to_vec(),
+                    shard_proofs: self.shard_proofs[start..end].to_vec(),
+                    after: transcripts[end..].to_vec(),
+                }
+            })
+            .collect()
+    }
+}
+
 /// PublicValuesDigest is a hash of all the public values that a zkvm program has committed to.
 pub struct PublicValuesDigest(pub [u8; 32]);
 
@@ -199,3 +380,36 @@ impl From<[u32; 8]> for DeferredDigest {
         DeferredDigest(bytes)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stark::tests::prove_fibonacci;
+    use crate::utils::{verify_machine_proof, BabyBearPoseidon2, ProveOptions, SP1CoreOpts};
+
+    #[test]
+    fn test_fibonacci_merge_proofs() {
+        let opts = SP1CoreOpts {
+            shard_size: 1 << 10,
+            shard_batch_size: 0,
+            ..Default::default()
+        };
+        let (proof, vk) = prove_fibonacci(ProveOptions::from(opts)).unwrap();
+        let num_shards = proof.shard_proofs.len();
+        assert!(num_shards > 1);
+
+        // Parts can arrive in any order.
+        let (first, second) = proof.shard_proofs.split_at(1);
+        let part = |shard_proofs: &[ShardProof<_>]| MachineProof {
+            shard_proofs: shard_proofs.to_vec(),
+        };
+        let merged = MachineProof::merge(vec![part(second), part(first)]).unwrap();
+        assert_eq!(merged.shard_proofs.len(), num_shards);
+        verify_machine_proof(&vk, &merged, BabyBearPoseidon2::new()).unwrap();
+
+        let duplicated = MachineProof::merge(vec![part(first), part(first), part(second)]);
+        assert_eq!(duplicated.unwrap_err(), MergeError::DuplicateShard(1));
+        let missing = MachineProof::merge(vec![part(second)]);
+        assert_eq!(missing.unwrap_err(), MergeError::MissingShard(1));
+    }
+}
@@ -33,7 +33,8 @@ impl Syscall for SyscallVerifySP1Proof {
             .map(|i| rt.word(pv_digest_ptr + i * 4))
             .collect::<Vec<u32>>();
 
-        let (proof, proof_vk) = &rt.state.proof_stream[rt.state.proof_stream_ptr];
+        // The proof and its verifying key, serialized together when written to the runtime.
+        let proof_bytes = &rt.state.proof_stream[rt.state.proof_stream_ptr];
         rt.state.proof_stream_ptr += 1;
 
         let config = BabyBearPoseidon2Inner::new();
@@ -46,6 +47,8 @@ impl Syscall for SyscallVerifySP1Proof {
         // Assert that the public values digest from runtime inputs matches the one from syscall.
 
         // TODO: Verify proof
+        // let (proof, proof_vk): (ShardProof<_>, StarkVerifyingKey<_>) =
+        //     bincode::deserialize(proof_bytes).expect("failed to deserialize proof");
         // machine
         //     .verify(proof_vk, proof, &mut challenger)
         //     .expect("proof verification failed");
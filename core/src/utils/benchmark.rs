@@ -0,0 +1,127 @@
+use p3_field::PrimeField32;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use web_time::Instant;
+
+use crate::io::SP1Stdin;
+use crate::runtime::{Program, Runtime};
+use crate::stark::StarkGenericConfig;
+use crate::stark::{Com, OpeningProof, PcsProverData, RiscvAir, ShardMainData};
+use crate::utils::{
+    prove_with_setup_cache, write_stdin, ProveOptions, ProveStats, SP1CoreProverError, SetupCache,
+};
+
+/// The proving throughput of a program over several iterations, as measured by
+/// [benchmark_prove].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkResult {
+    /// The number of measured iterations.
+    pub iterations: usize,
+    /// The number of cycles executed by the program.
+    pub cycles: u64,
+    /// The slowest throughput of an iteration, in thousands of cycles per second.
+    pub min_khz: f64,
+    /// The median throughput of the iterations, in thousands of cycles per second.
+    pub median_khz: f64,
+    /// The fastest throughput of an iteration, in thousands of cycles per second.
+    pub max_khz: f64,
+    /// The size of the serialized proof of each iteration, in bytes.
+    pub proof_bytes: Vec<usize>,
+}
+
+/// Proves a program `iterations` times with `options` and reports its proving throughput.
+///
+/// The program is executed once and set up before any iteration, so neither is timed. If `warmup`
+/// is set, one more iteration is run first and left out of the results. Fails with
+/// [SP1CoreProverError::NoBenchmarkIterations] if `iterations` is zero.
+pub fn benchmark_prove<SC: StarkGenericConfig + Send + Sync + 'static>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: ProveOptions,
+    iterations: usize,
+    warmup: bool,
+) -> Result<BenchmarkResult, SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    if iterations == 0 {
+        return Err(SP1CoreProverError::NoBenchmarkIterations);
+    }
+
+    let mut runtime = Runtime::new(program.clone(), options.core_opts());
+    write_stdin(&mut runtime, stdin);
+    runtime
+        .run_untraced()
+        .map_err(SP1CoreProverError::ExecutionError)?;
+    let cycles = runtime.state.global_clk;
+
+    let setup_cache = SetupCache::new();
+    let machine = RiscvAir::machine(config.clone());
+    setup_cache.get_or_setup(&machine, &program)?;
+
+    let mut stats = Vec::with_capacity(iterations);
+    for i in 0..iterations + usize::from(warmup) {
+        let start = Instant::now();
+        let (proof, _) = prove_with_setup_cache(
+            program.clone(),
+            stdin,
+            config.clone(),
+            options.clone(),
+            &setup_cache,
+        )?;
+        let elapsed = start.elapsed();
+        if warmup && i == 0 {
+            continue;
+        }
+        stats.push(ProveStats::new(&config, cycles, elapsed, &proof));
+    }
+
+    let mut khz = stats.iter().map(ProveStats::khz).collect::<Vec<_>>();
+    khz.sort_by(f64::total_cmp);
+    Ok(BenchmarkResult {
+        iterations,
+        cycles,
+        min_khz: khz[0],
+        median_khz: khz[khz.len() / 2],
+        max_khz: khz[khz.len() - 1],
+        proof_bytes: stats.iter().map(|stats| stats.proof_bytes).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::tests::simple_program;
+    use crate::utils::BabyBearPoseidon2;
+
+    #[test]
+    fn test_benchmark_prove() {
+        let stdin = SP1Stdin::new();
+        let benchmark = |iterations| {
+            benchmark_prove(
+                simple_program(),
+                &stdin,
+                BabyBearPoseidon2::new(),
+                ProveOptions::default(),
+                iterations,
+                true,
+            )
+        };
+        let result = benchmark(2).unwrap();
+
+        assert_eq!(result.iterations, 2);
+        assert_eq!(result.proof_bytes.len(), 2);
+        assert!(result.cycles > 0);
+        assert!(result.min_khz <= result.median_khz && result.median_khz <= result.max_khz);
+        assert!(matches!(
+            benchmark(0),
+            Err(SP1CoreProverError::NoBenchmarkIterations)
+        ));
+    }
+}
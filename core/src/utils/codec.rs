@@ -0,0 +1,94 @@
+use std::io::{Read, Write};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// An encoding used to serialize proofs.
+pub trait ProofCodec {
+    /// Encodes a value into bytes.
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, bincode::Error>;
+
+    /// Decodes a value from bytes produced by [ProofCodec::encode].
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, bincode::Error>;
+}
+
+/// The default bincode encoding, with fixed-width integers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+impl ProofCodec for BincodeCodec {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(value)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// The encoding of the checkpoints saved while proving.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CheckpointCodec {
+    /// Plain bincode.
+    #[default]
+    Plain,
+    /// Bincode compressed with zstd at the given level.
+    Zstd(i32),
+}
+
+impl CheckpointCodec {
+    /// Encodes a value into a writer.
+    pub fn encode_into<W: Write, T: Serialize>(
+        self,
+        writer: W,
+        value: &T,
+    ) -> Result<(), bincode::Error> {
+        match self {
+            CheckpointCodec::Plain => bincode::serialize_into(writer, value),
+            CheckpointCodec::Zstd(level) => {
+                let mut encoder = zstd::Encoder::new(writer, level)?;
+                bincode::serialize_into(&mut encoder, value)?;
+                encoder.finish()?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Decodes a value from a reader written by [CheckpointCodec::encode_into] with the same codec.
+    pub fn decode_from<R: Read, T: DeserializeOwned>(self, reader: R) -> Result<T, bincode::Error> {
+        match self {
+            CheckpointCodec::Plain => bincode::deserialize_from(reader),
+            CheckpointCodec::Zstd(_) => bincode::deserialize_from(zstd::Decoder::new(reader)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Value = (Vec<u32>, u64, Vec<Vec<u8>>);
+
+    #[test]
+    fn test_codecs_roundtrip() {
+        let value: Value = (vec![1, 2, 3, u32::MAX], 7, vec![vec![0; 4]; 3]);
+
+        let bytes = BincodeCodec::encode(&value).unwrap();
+        assert_eq!(BincodeCodec::decode::<Value>(&bytes).unwrap(), value);
+    }
+
+    #[test]
+    fn test_checkpoint_codecs_roundtrip() {
+        let value: Value = (vec![7; 4096], 7, vec![vec![0; 1024]; 4]);
+        let mut sizes = Vec::new();
+        for codec in [CheckpointCodec::Plain, CheckpointCodec::Zstd(3)] {
+            let mut bytes = Vec::new();
+            codec.encode_into(&mut bytes, &value).unwrap();
+            assert_eq!(
+                codec.decode_from::<_, Value>(bytes.as_slice()).unwrap(),
+                value
+            );
+            sizes.push(bytes.len());
+        }
+        assert!(sizes[1] < sizes[0]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt::Debug;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::utils::ProveStats;
+
+/// A destination for the statistics of the proofs generated with
+/// [crate::utils::ProveOptions::metrics_sink] set.
+pub trait MetricsSink: Debug + Send + Sync {
+    fn emit(&self, metrics: &ProveStats);
+}
+
+/// The memory used by the process at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemoryUsage {
+    /// The resident set size, in bytes.
+    pub rss_bytes: u64,
+    /// The largest resident set size reached by the process so far, in bytes.
+    pub peak_rss_bytes: u64,
+}
+
+/// The memory used by the process at the end of each proving phase, where it could be sampled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PhaseMemoryUsage {
+    pub after_execution: Option<MemoryUsage>,
+    pub after_commit: Option<MemoryUsage>,
+    pub after_prove: Option<MemoryUsage>,
+}
+
+/// Samples the memory used by the process, or returns `None` on platforms where it cannot be read.
+pub fn sample_memory() -> Option<MemoryUsage> {
+    #[cfg(target_os = "linux")]
+    {
+        read_proc_status(&std::fs::read_to_string("/proc/self/status").ok()?)
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Reads the current and peak resident set sizes from the contents of `/proc/self/status`.
+///
+/// Unlike `/proc/self/statm`, it reports the peak and uses kB rather than pages, so no page size
+/// lookup is needed.
+#[cfg(target_os = "linux")]
+fn read_proc_status(status: &str) -> Option<MemoryUsage> {
+    let read_kb = |key: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(key))?
+            .trim()
+            .strip_suffix("kB")?
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .map(|kb| kb * 1024)
+    };
+    Some(MemoryUsage {
+        rss_bytes: read_kb("VmRSS:")?,
+        peak_rss_bytes: read_kb("VmHWM:")?,
+    })
+}
+
+/// A sink that appends each proof's statistics to a file as a line of JSON.
+#[derive(Debug, Clone)]
+pub struct JsonFileMetricsSink {
+    path: PathBuf,
+}
+
+impl JsonFileMetricsSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl MetricsSink for JsonFileMetricsSink {
+    fn emit(&self, metrics: &ProveStats) {
+        let line = serde_json::json!({
+            "cycles": metrics.cycles,
+            "shard_count": metrics.shard_count,
+            "prove_time_ms": metrics.prove_time_ms as u64,
+            "khz": metrics.khz(),
+            "proof_bytes": metrics.proof_bytes,
+            "shard_timings": metrics.shard_timings,
+            "security_bits": metrics.security_bits,
+            "checkpoint_bytes": metrics.checkpoint_bytes,
+            "checkpoint_uncompressed_bytes": metrics.checkpoint_uncompressed_bytes,
+            "memory": metrics.memory,
+        });
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        if let Err(e) = result {
+            tracing::warn!("failed to write metrics to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_file_metrics_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics.jsonl");
+        let sink = JsonFileMetricsSink::new(&path);
+        let metrics = ProveStats {
+            cycles: 2000,
+            shard_count: 2,
+            prove_time_ms: 1000,
+            proof_bytes: 512,
+            shard_timings: Vec::new(),
+            security_bits: Some(116),
+            checkpoint_bytes: 0,
+            checkpoint_uncompressed_bytes: 0,
+            memory: PhaseMemoryUsage::default(),
+        };
+        sink.emit(&metrics);
+        sink.emit(&metrics);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let value: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(value["cycles"], 2000);
+        assert_eq!(value["khz"], 2.0);
+
+        // A proof that took less than a millisecond still has a finite throughput.
+        let instant = ProveStats {
+            prove_time_ms: 0,
+            ..metrics
+        };
+        assert_eq!(instant.khz(), 2000.0);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_read_proc_status() {
+        let status = "Name:\tsp1\nVmHWM:\t    2048 kB\nVmRSS:\t    1024 kB\n";
+        let usage = read_proc_status(status).unwrap();
+        assert_eq!(usage.rss_bytes, 1024 * 1024);
+        assert_eq!(usage.peak_rss_bytes, 2048 * 1024);
+        assert!(read_proc_status("Name:\tsp1\n").is_none());
+        assert!(sample_memory().is_some());
+    }
+}
@@ -1,18 +1,34 @@
+mod benchmark;
 mod buffer;
+mod codec;
 mod config;
 pub mod ec;
 mod logger;
+mod metrics;
 mod options;
 mod programs;
+#[cfg(feature = "metrics")]
+mod prometheus;
 mod prove;
+mod setup;
 mod tracer;
+mod transcript;
+mod worker;
 
+pub use benchmark::*;
 pub use buffer::*;
+pub use codec::*;
 pub use config::*;
 pub use logger::*;
+pub use metrics::*;
 pub use options::*;
+#[cfg(feature = "metrics")]
+pub use prometheus::*;
 pub use prove::*;
+pub use setup::*;
 pub use tracer::*;
+pub use transcript::*;
+pub use worker::*;
 
 #[cfg(test)]
 pub use programs::*;
@@ -1,3 +1,10 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::air::PublicValues;
+use crate::runtime::ShardingConfig;
+use crate::utils::{CheckpointCodec, MetricsSink};
+
 #[derive(Debug, Clone, Copy)]
 pub struct SP1CoreOpts {
     pub shard_size: usize,
@@ -24,3 +31,216 @@ impl SP1CoreOpts {
         opts
     }
 }
+
+/// Options for [crate::utils::prove_with_options].
+#[derive(Debug, Clone)]
+pub struct ProveOptions {
+    /// The options used to execute the program and commit to its shards.
+    pub core_opts: SP1CoreOpts,
+    /// The number of shards per checkpoint. When `None`, `core_opts.shard_batch_size` is used.
+    pub shard_batch_size: Option<usize>,
+    /// The configuration used to split each checkpoint into shards when batching.
+    pub sharding_config: ShardingConfig,
+    /// Whether the first checkpoint is executed with events, so that a program that fits in it is
+    /// proven from that execution like with a batch size of zero, without saving or tracing any
+    /// checkpoint. `strategy`, `parallel_shard_proving` and `checkpoint_codec` then have no effect.
+    /// For a longer program, the events of the first checkpoint are dropped and it is saved as
+    /// usual. Ignored when `per_shard_timeout` is set.
+    pub reuse_single_batch: bool,
+    /// Whether to prove the shards of a checkpoint concurrently. Shard proofs are returned in the
+    /// same order either way.
+    pub parallel_shard_proving: bool,
+    /// Whether proving must be checked to be reproducible.
+    pub determinism: Determinism,
+    /// The memory available to a single shard, in bytes. When set, the shard size and sharding
+    /// config are derived from it with [ShardingConfig::for_memory_budget], overriding
+    /// `core_opts.shard_size` and `sharding_config`.
+    pub memory_budget: Option<usize>,
+    /// The maximum number of cycles the program may execute. Execution fails with
+    /// [crate::runtime::ExecutionError::ExceededCycleLimit] once it is exceeded.
+    pub max_cycles: Option<u64>,
+    /// The public values the program is expected to produce. When set, proving fails with
+    /// [crate::utils::SP1CoreProverError::PublicValuesMismatch] right after execution if the
+    /// committed value digest or the deferred proofs digest differ.
+    pub expected_public_values: Option<PublicValues<u32, u32>>,
+    /// The encoding of the checkpoints saved to disk when batching.
+    pub checkpoint_codec: CheckpointCodec,
+    /// The longest a single shard may take to prove. When set, each shard is proven on its own
+    /// thread and proving fails with [crate::utils::SP1CoreProverError::ShardTimeout] once a shard
+    /// exceeds it. Has no effect with a batch size of zero.
+    ///
+    /// A timed-out shard cannot be interrupted: its thread keeps proving in the background until
+    /// it finishes, and only its result is discarded. The threads already started for other
+    /// shards, such as the rest of the checkpoint with `parallel_shard_proving`, are not joined or
+    /// cancelled either when proving fails.
+    pub per_shard_timeout: Option<Duration>,
+    /// How the checkpoints are traced when batching.
+    pub strategy: ProveStrategy,
+    /// A domain separator observed before the public values of every shard, for protocols that
+    /// compose several proofs. The proof must be verified by a machine with the same domain, see
+    /// [crate::stark::StarkMachine::with_transcript_domain].
+    pub transcript_domain: Option<[u8; 4]>,
+    /// Whether to execute the program a second time from scratch and fail with
+    /// [crate::utils::SP1CoreProverError::NonDeterministicExecution] before proving any shard if
+    /// the two runs commit different public values. This doubles the execution time and is meant
+    /// for debugging programs whose proofs fail intermittently.
+    pub verify_reexecution: bool,
+    /// The maximum number of bytes of the input buffers of the program. When set, proving fails
+    /// with [crate::utils::SP1CoreProverError::StdinTooLarge] before execution if they are larger.
+    pub max_stdin_bytes: Option<usize>,
+    /// The sink that the statistics of the proof are emitted to, in addition to the summary line
+    /// that is logged.
+    pub metrics_sink: Option<Arc<dyn MetricsSink>>,
+}
+
+impl ProveOptions {
+    /// The effective core options, with any override from these options applied.
+    pub fn core_opts(&self) -> SP1CoreOpts {
+        let mut opts = self.core_opts;
+        if let Some(shard_batch_size) = self.shard_batch_size {
+            opts.shard_batch_size = shard_batch_size;
+        }
+        if self.memory_budget.is_some() {
+            opts.shard_size = self.sharding_config().shard_size();
+        }
+        opts
+    }
+
+    /// The effective sharding config, derived from the memory budget when one is set.
+    pub fn sharding_config(&self) -> ShardingConfig {
+        match self.memory_budget {
+            Some(bytes) => ShardingConfig::for_memory_budget(bytes),
+            None => self.sharding_config,
+        }
+    }
+}
+
+impl Default for ProveOptions {
+    fn default() -> Self {
+        Self::from(SP1CoreOpts::default())
+    }
+}
+
+impl From<SP1CoreOpts> for ProveOptions {
+    fn from(core_opts: SP1CoreOpts) -> Self {
+        Self {
+            core_opts,
+            shard_batch_size: None,
+            sharding_config: ShardingConfig::default(),
+            reuse_single_batch: true,
+            parallel_shard_proving: false,
+            determinism: Determinism::default(),
+            memory_budget: None,
+            max_cycles: None,
+            expected_public_values: None,
+            checkpoint_codec: CheckpointCodec::default(),
+            per_shard_timeout: None,
+            strategy: ProveStrategy::default(),
+            transcript_domain: None,
+            verify_reexecution: false,
+            max_stdin_bytes: None,
+            metrics_sink: None,
+        }
+    }
+}
+
+/// How the checkpoints of a batched proof are traced.
+///
+/// Every shard commitment must be observed by the challenger before any shard is proven, so the
+/// commit phase always finishes before the prove phase starts. The strategies differ in whether
+/// the shards traced for the commit phase are kept for the prove phase.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProveStrategy {
+    /// Trace every checkpoint once to commit to its shards and again to prove them, so that only
+    /// the shards of one checkpoint are in memory at a time.
+    #[default]
+    TwoPass,
+    /// Trace every checkpoint once and keep its shards until they are proven. This skips the
+    /// second trace, but the shards of every checkpoint are in memory at the end of the commit
+    /// phase, so peak memory grows with the number of checkpoints.
+    Interleaved,
+}
+
+/// How strictly proving is checked to be reproducible.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Determinism {
+    /// Prove once on the global thread pool.
+    #[default]
+    Relaxed,
+    /// Prove twice on a single-threaded pool and assert that both proofs serialize to the same
+    /// bytes. This is slow and is meant for CI regression tests that diff proof bytes.
+    Strict,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{
+        ShardingConfigError, MIN_MEMORY_BUDGET_SHARD_SIZE, MIN_TRACE_HEIGHT, SHARD_BYTES_PER_CYCLE,
+    };
+    use crate::stark::StarkGenericConfig;
+    use crate::utils::BabyBearPoseidon2;
+
+    #[test]
+    fn test_memory_budget_sharding() {
+        let options = ProveOptions {
+            memory_budget: Some(3 * (1 << 20) * SHARD_BYTES_PER_CYCLE),
+            ..Default::default()
+        };
+        assert_eq!(options.sharding_config().shard_size(), 1 << 21);
+        assert_eq!(options.core_opts().shard_size, 1 << 21);
+
+        let small = ShardingConfig::for_memory_budget(1);
+        assert_eq!(small.shard_size(), MIN_MEMORY_BUDGET_SHARD_SIZE);
+        let large = ShardingConfig::for_memory_budget(usize::MAX);
+        assert_eq!(large.shard_size(), ShardingConfig::default().shard_size());
+    }
+
+    #[test]
+    fn test_validate_sharding_config() {
+        let config = BabyBearPoseidon2::new();
+        assert_eq!(ShardingConfig::default().validate(&config), Ok(()));
+
+        let empty = ShardingConfig {
+            keccak_len: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            empty.validate(&config),
+            Err(ShardingConfigError::ZeroLength { name: "keccak_len" })
+        );
+
+        let small = ShardingConfig {
+            add_len: MIN_TRACE_HEIGHT - 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            small.validate(&config),
+            Err(ShardingConfigError::TooSmall {
+                name: "add_len",
+                len: MIN_TRACE_HEIGHT - 1,
+                min_len: MIN_TRACE_HEIGHT,
+            })
+        );
+        // A single keccak permutation fills more than the minimum height of its chip.
+        let single_keccak = ShardingConfig {
+            keccak_len: 1,
+            ..Default::default()
+        };
+        assert_eq!(single_keccak.validate(&config), Ok(()));
+
+        let max_log_height = config.max_log_trace_height().unwrap();
+        let large = ShardingConfig {
+            field_len: 1 << (max_log_height + 1),
+            ..Default::default()
+        };
+        assert_eq!(
+            large.validate(&config),
+            Err(ShardingConfigError::TooLarge {
+                name: "field_len",
+                log_height: max_log_height + 1,
+                max_log_height,
+            })
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::utils::{MetricsSink, ProveStats};
+
+/// The upper bounds of the buckets of the prove duration histogram, in seconds.
+const PROVE_DURATION_BUCKETS: [f64; 10] = [
+    1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+];
+
+/// A sink that aggregates the statistics of every proof into Prometheus counters and a histogram
+/// of prove durations.
+///
+/// Clones share their metrics, so one clone can be set as the
+/// [metrics_sink](crate::utils::ProveOptions::metrics_sink) of every proof while another serves
+/// [Self::render] from an HTTP endpoint, with [Self::CONTENT_TYPE] as the content type of the
+/// response.
+#[derive(Debug, Clone, Default)]
+pub struct PrometheusMetricsSink {
+    state: Arc<Mutex<PrometheusState>>,
+}
+
+#[derive(Debug, Default)]
+struct PrometheusState {
+    cycles: u64,
+    proofs: u64,
+    shards: u64,
+    /// The number of proofs that fall in each bucket, not including the smaller buckets.
+    duration_buckets: [u64; PROVE_DURATION_BUCKETS.len()],
+    duration_sum: f64,
+}
+
+impl PrometheusMetricsSink {
+    /// The content type of the text exposition format returned by [Self::render].
+    pub const CONTENT_TYPE: &'static str = "text/plain; version=0.0.4";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+        write_counter(
+            &mut out,
+            "sp1_cycles_proved_total",
+            "Cycles executed by the proven programs.",
+            state.cycles,
+        );
+        write_counter(
+            &mut out,
+            "sp1_proofs_completed_total",
+            "Proofs completed.",
+            state.proofs,
+        );
+        write_counter(
+            &mut out,
+            "sp1_shards_proved_total",
+            "Shards in the completed proofs.",
+            state.shards,
+        );
+
+        let name = "sp1_prove_duration_seconds";
+        writeln!(out, "# HELP {} Time spent proving a program.", name).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+        let mut cumulative = 0;
+        for (bound, count) in PROVE_DURATION_BUCKETS.iter().zip(state.duration_buckets) {
+            cumulative += count;
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative).unwrap();
+        }
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, state.proofs).unwrap();
+        writeln!(out, "{}_sum {}", name, state.duration_sum).unwrap();
+        writeln!(out, "{}_count {}", name, state.proofs).unwrap();
+        out
+    }
+}
+
+impl MetricsSink for PrometheusMetricsSink {
+    fn emit(&self, metrics: &ProveStats) {
+        let seconds = metrics.prove_time_ms as f64 / 1000.0;
+        let mut state = self.state.lock().unwrap();
+        state.cycles += metrics.cycles;
+        state.proofs += 1;
+        state.shards += metrics.shard_count as u64;
+        if let Some(i) = PROVE_DURATION_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+        {
+            state.duration_buckets[i] += 1;
+        }
+        state.duration_sum += seconds;
+    }
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} counter", name).unwrap();
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::PhaseMemoryUsage;
+
+    #[test]
+    fn test_prometheus_metrics_sink() {
+        let sink = PrometheusMetricsSink::new();
+        let mut metrics = ProveStats {
+            cycles: 2000,
+            shard_count: 2,
+            prove_time_ms: 3500,
+            proof_bytes: 512,
+            shard_timings: Vec::new(),
+            security_bits: Some(116),
+            checkpoint_bytes: 0,
+            checkpoint_uncompressed_bytes: 0,
+            memory: PhaseMemoryUsage::default(),
+        };
+        sink.clone().emit(&metrics);
+        metrics.prove_time_ms = 7_200_000;
+        sink.emit(&metrics);
+
+        let text = sink.render();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert!(lines.contains(&"sp1_cycles_proved_total 4000"));
+        assert!(lines.contains(&"sp1_proofs_completed_total 2"));
+        assert!(lines.contains(&"sp1_shards_proved_total 4"));
+        assert!(lines.contains(&"sp1_prove_duration_seconds_bucket{le=\"1\"} 0"));
+        assert!(lines.contains(&"sp1_prove_duration_seconds_bucket{le=\"5\"} 1"));
+        assert!(lines.contains(&"sp1_prove_duration_seconds_bucket{le=\"3600\"} 1"));
+        assert!(lines.contains(&"sp1_prove_duration_seconds_bucket{le=\"+Inf\"} 2"));
+        assert!(lines.contains(&"sp1_prove_duration_seconds_sum 7203.5"));
+        assert!(lines.contains(&"sp1_prove_duration_seconds_count 2"));
+    }
+}
@@ -1,30 +1,52 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
 use std::fs::File;
 use std::io;
-use std::io::{Seek, Write};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::Duration;
 use web_time::Instant;
 
 pub use baby_bear_blake3::BabyBearBlake3;
+use p3_air::BaseAir;
 use p3_challenger::CanObserve;
-use p3_field::PrimeField32;
+use p3_field::{AbstractExtensionField, AbstractField, Field, PrimeField32};
+use p3_matrix::Matrix;
+use p3_maybe_rayon::prelude::*;
+use p3_util::log2_ceil_usize;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use size::Size;
 use thiserror::Error;
+use tracing::instrument;
 
-use crate::air::MachineAir;
-use crate::io::{SP1PublicValues, SP1Stdin};
+use crate::air::{MachineAir, PublicValues, Word};
+use crate::io::{SP1PublicValues, SP1Stdin, StdinTooLarge};
 use crate::lookup::InteractionBuilder;
-use crate::runtime::ExecutionError;
-use crate::runtime::{ExecutionRecord, ShardingConfig};
+use crate::runtime::{ExecutionError, ExecutionReport};
+use crate::runtime::{
+    ExecutionRecord, ExecutionState, MemoryPreloadError, ShardingConfig, ShardingConfigError,
+};
 use crate::stark::DebugConstraintBuilder;
 use crate::stark::MachineProof;
+use crate::stark::MergeError;
 use crate::stark::ProverConstraintFolder;
 use crate::stark::StarkVerifyingKey;
 use crate::stark::Val;
 use crate::stark::VerifierConstraintFolder;
-use crate::stark::{Com, PcsProverData, RiscvAir, ShardProof, StarkProvingKey, UniConfig};
-use crate::stark::{MachineRecord, StarkMachine};
-use crate::utils::SP1CoreOpts;
+use crate::stark::{Com, Dom, PcsProverData, RiscvAir, ShardProof, StarkProvingKey, UniConfig};
+use crate::stark::{MachineRecord, ShardProver, StarkMachine};
+use crate::utils::metrics::{sample_memory, PhaseMemoryUsage};
+use crate::utils::{
+    log_transcript_divergence, BincodeCodec, CheckpointCodec, Determinism, LocalWorkerPool,
+    MetricsSink, ProofCodec, ProveOptions, ProveStrategy, RecordingChallenger, SP1CoreOpts,
+    SetupCache, SetupKeys, VerifiedJobError,
+};
 use crate::{
     runtime::{Program, Runtime},
     stark::StarkGenericConfig,
@@ -41,12 +63,252 @@ pub enum SP1CoreProverError {
     IoError(io::Error),
     #[error("serialization error: {0}")]
     SerializationError(bincode::Error),
+    #[error("checkpoint {0} is out of range")]
+    CheckpointOutOfRange(usize),
+    #[error("checkpoint range {0:?} is empty")]
+    InvalidCheckpointRange(Range<usize>),
+    #[error("resume manifest does not match the program being proven")]
+    ResumeMismatch,
+    #[error("proving was cancelled")]
+    Cancelled,
+    #[error("checkpoint was produced by a different program")]
+    ProgramMismatch,
+    #[error("execution produced public values {got:?}, expected {expected:?}")]
+    PublicValuesMismatch {
+        expected: PublicValues<u32, u32>,
+        got: PublicValues<u32, u32>,
+    },
+    #[error(
+        "shard {shard} needs a trace of height 2^{log_height}, above the 2^{max_log_height} \
+         supported by the config"
+    )]
+    ShardTooLarge {
+        shard: u32,
+        log_height: usize,
+        max_log_height: usize,
+    },
+    #[error("deferred proof {index} does not verify against its verifying key: {error}")]
+    InvalidDeferredProof { index: usize, error: String },
+    #[error(
+        "deferred proof {index} was made with the {config:?} config, which cannot be deferred"
+    )]
+    IncompatibleDeferredProof {
+        index: usize,
+        config: baby_bear_poseidon2::Preset,
+    },
+    /// A shard took longer than [ProveOptions::per_shard_timeout] to prove. Proving returns
+    /// without joining or cancelling the threads already spawned for this and other shards, which
+    /// keep running in the background until they finish.
+    #[error("shard {index} took longer than the per-shard timeout to prove")]
+    ShardTimeout { index: usize },
+    /// The thread proving a shard under [ProveOptions::per_shard_timeout] panicked. As with
+    /// [Self::ShardTimeout], the threads spawned for other shards are neither joined nor
+    /// cancelled.
+    #[error("the prover of shard {index} panicked")]
+    ShardProverPanicked { index: usize },
+    #[error("shard {shard} committed to a different public values digest than the execution")]
+    PublicValuesDigestMismatch { shard: usize },
+    #[error("invalid sharding config: {0}")]
+    InvalidShardingConfig(ShardingConfigError),
+    #[error("invalid memory preload: {0}")]
+    InvalidMemoryPreload(MemoryPreloadError),
+    #[error("a worker returned an invalid proof of shard {shard}: {error}")]
+    ShardProofInvalid { shard: u32, error: String },
+    #[error("every worker returned an invalid proof before all checkpoints were proven")]
+    NoWorkersLeft,
+    #[error("proof does not verify: {0}")]
+    InvalidProof(String),
+    #[error("re-executing the program committed different public values")]
+    NonDeterministicExecution,
+    #[error("{0}")]
+    StdinTooLarge(StdinTooLarge),
+    #[error("checkpoints {0:?} have not been proven")]
+    MissingCheckpoints(Vec<usize>),
+    #[error("partial proofs do not form a proof: {0}")]
+    InvalidPartialProofs(MergeError),
+    #[error("a benchmark needs at least one iteration")]
+    NoBenchmarkIterations,
+}
+
+/// A phase of proving reported through [ProveProgress].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProvePhase {
+    Execution,
+    Commit,
+    Prove,
+    /// Reducing shard proofs with the recursion prover, in reduction layer `layer` of
+    /// `total_layers`, counted from zero. The shard counts of the update refer to the proofs
+    /// produced by the layer, and no cycles are reported.
+    Reduce {
+        layer: usize,
+        total_layers: usize,
+    },
+}
+
+/// A progress update emitted by [prove_with_progress].
+#[derive(Debug, Clone, Copy)]
+pub struct ProveProgress {
+    pub phase: ProvePhase,
+    /// The number of shards processed so far in this phase.
+    pub shard_index: usize,
+    /// The total number of shards, if it is known at this point.
+    pub total_shards: Option<usize>,
+    /// The number of cycles processed so far in this phase.
+    pub cycles_done: u64,
+}
+
+/// A handle for aborting a proof started with [prove_cancellable].
+///
+/// Clones share the same state, so a proof can be cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests the proofs observing this token to stop.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn check(&self) -> Result<(), SP1CoreProverError> {
+        if self.is_cancelled() {
+            Err(SP1CoreProverError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The time spent on a single shard while proving it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ShardTiming {
+    /// The index of the shard.
+    pub index: usize,
+    /// The trace height of every chip used by the shard, keyed by chip name.
+    pub chip_heights: BTreeMap<String, usize>,
+    /// The time spent generating and committing to the main trace, in milliseconds.
+    pub commit_ms: u128,
+    /// The time spent proving the shard once committed, in milliseconds.
+    pub prove_ms: u128,
+}
+
+/// Summary statistics of a proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProveStats {
+    /// The number of cycles executed by the program.
+    pub cycles: u64,
+    /// The number of shards in the proof.
+    pub shard_count: usize,
+    /// The time spent proving, in milliseconds.
+    pub prove_time_ms: u128,
+    /// The size of the serialized proof, in bytes.
+    pub proof_bytes: usize,
+    /// The timings of each shard, when the proving function measured them.
+    pub shard_timings: Vec<ShardTiming>,
+    /// The conjectured bits of security of the config, when it reports them.
+    pub security_bits: Option<usize>,
+    /// The size of the checkpoints saved while proving, as written to disk, in bytes.
+    pub checkpoint_bytes: u64,
+    /// The size of the checkpoints saved while proving before any compression, in bytes.
+    pub checkpoint_uncompressed_bytes: u64,
+    /// The memory used by the process after each proving phase.
+    pub memory: PhaseMemoryUsage,
+}
+
+impl ProveStats {
+    pub fn new<SC: StarkGenericConfig>(
+        config: &SC,
+        cycles: u64,
+        elapsed: Duration,
+        proof: &MachineProof<SC>,
+    ) -> Self
+    where
+        MachineProof<SC>: Serialize,
+    {
+        let proof_bytes = BincodeCodec::encode(proof).unwrap().len();
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            for (i, shard_proof) in proof.shard_proofs.iter().enumerate() {
+                let sizes = match shard_proof.size_breakdown() {
+                    Ok(sizes) => sizes,
+                    Err(e) => {
+                        tracing::debug!("shard {} proof size: {}", i, e);
+                        continue;
+                    }
+                };
+                tracing::debug!(
+                    "shard {} proof size: mainCommit={}, openingProof={}, quotientDegree={}, \
+                     openings={}",
+                    i,
+                    Size::from_bytes(sizes.main_commit_bytes),
+                    Size::from_bytes(sizes.opening_proof_bytes),
+                    sizes.quotient_degree,
+                    sizes.num_openings,
+                );
+            }
+        }
+        Self {
+            cycles,
+            shard_count: proof.shard_proofs.len(),
+            prove_time_ms: elapsed.as_millis(),
+            proof_bytes,
+            shard_timings: Vec::new(),
+            security_bits: config.conjectured_security_bits(),
+            checkpoint_bytes: 0,
+            checkpoint_uncompressed_bytes: 0,
+            memory: PhaseMemoryUsage {
+                after_prove: sample_memory(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// The proving throughput, in thousands of cycles per second. A proof that took less than a
+    /// millisecond is counted as taking one, so that the throughput stays finite.
+    pub fn khz(&self) -> f64 {
+        self.cycles as f64 / self.prove_time_ms.max(1) as f64
+    }
+}
+
+/// The shard layout of a program, as reported by [analyze_sharding].
+#[derive(Debug, Clone)]
+pub struct ShardingReport {
+    /// The number of shards the program is split into.
+    pub shard_count: usize,
+    /// The trace height of every chip used by each shard, keyed by chip name.
+    pub per_shard_chip_heights: Vec<BTreeMap<String, usize>>,
+    /// The number of cycles executed by the program.
+    pub total_cycles: u64,
 }
 
 pub fn prove_simple<SC: StarkGenericConfig>(
     config: SC,
     runtime: Runtime,
 ) -> Result<MachineProof<SC>, SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    Ok(prove_record(config, &runtime.program, runtime.record))
+}
+
+/// Proves the record of an execution of `program` like [prove_simple], for callers that executed
+/// the program themselves.
+pub fn prove_record<SC: StarkGenericConfig>(
+    config: SC,
+    program: &Program,
+    record: ExecutionRecord,
+) -> MachineProof<SC>
 where
     SC::Challenger: Clone,
     OpeningProof<SC>: Send + Sync,
@@ -57,38 +319,62 @@ where
 {
     // Setup the machine.
     let machine = RiscvAir::machine(config);
-    let (pk, _) = machine.setup(runtime.program.as_ref());
+    let (pk, _) = machine.setup(program);
 
+    let shards = tracing::info_span!("shard_record")
+        .in_scope(|| machine.shard(record, &ShardingConfig::default()));
+    prove_shards_with_keys(&machine, &pk, shards, None)
+}
+
+/// Proves the shards of the record of an execution with already computed keys.
+fn prove_shards_with_keys<SC: StarkGenericConfig>(
+    machine: &StarkMachine<SC, RiscvAir<SC::Val>>,
+    pk: &StarkProvingKey<SC>,
+    shards: Vec<ExecutionRecord>,
+    metrics_sink: Option<&dyn MetricsSink>,
+) -> MachineProof<SC>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
     // Prove the program.
+    let memory_after_execution = sample_memory();
+    let cycles = shards
+        .iter()
+        .map(|shard| shard.cpu_events.len() as u64)
+        .sum::<u64>();
     let mut challenger = machine.config().challenger();
     let proving_start = Instant::now();
-    let proof = machine.prove::<LocalProver<_, _>>(
-        &pk,
-        runtime.record,
+    let proof = machine.prove_shards::<LocalProver<_, _>>(
+        pk,
+        shards,
         &mut challenger,
         SP1CoreOpts::default(),
     );
-    let proving_duration = proving_start.elapsed().as_millis();
-    let nb_bytes = bincode::serialize(&proof).unwrap().len();
 
     // Print the summary.
-    tracing::info!(
-        "summary: cycles={}, e2e={}, khz={:.2}, proofSize={}",
-        runtime.state.global_clk,
-        proving_duration,
-        (runtime.state.global_clk as f64 / proving_duration as f64),
-        Size::from_bytes(nb_bytes),
-    );
+    let mut stats = ProveStats::new(machine.config(), cycles, proving_start.elapsed(), &proof);
+    stats.memory.after_execution = memory_after_execution;
+    log_summary(&stats, metrics_sink);
 
-    Ok(proof)
+    proof
 }
 
-pub fn prove<SC: StarkGenericConfig + Send + Sync>(
-    program: Program,
-    stdin: &SP1Stdin,
+/// Proves an executed runtime like [prove_simple], writing each shard proof to `writer` as soon
+/// as it is proven instead of keeping the whole proof in memory.
+///
+/// The output is the number of shards as a little-endian `u64`, followed by each bincode-encoded
+/// shard proof prefixed by its length as a little-endian `u64`. Use
+/// [read_machine_proof_from_reader] to read it back.
+pub fn prove_core_to_writer<SC: StarkGenericConfig + Send + Sync, W: Write>(
     config: SC,
-    opts: SP1CoreOpts,
-) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+    runtime: Runtime,
+    mut writer: W,
+) -> Result<(), SP1CoreProverError>
 where
     SC::Challenger: Clone,
     OpeningProof<SC>: Send + Sync,
@@ -97,247 +383,2582 @@ where
     ShardMainData<SC>: Serialize + DeserializeOwned,
     <SC as StarkGenericConfig>::Val: PrimeField32,
 {
+    // Setup the machine.
+    let machine = RiscvAir::machine(config);
+    let (pk, _) = machine.setup(runtime.program.as_ref());
     let proving_start = Instant::now();
+    let shards = machine.shard(runtime.record, &ShardingConfig::default());
 
-    // Execute the program.
-    let mut runtime = Runtime::new(program.clone(), opts);
-    runtime.write_vecs(&stdin.buffer);
-    for proof in stdin.proofs.iter() {
-        runtime.write_proof(proof.0.clone(), proof.1.clone());
+    // Observe the commitment of each shard, dropping its traces until it is proven.
+    let mut challenger = machine.config().challenger();
+    pk.observe_into(&mut challenger);
+    for shard in shards.iter() {
+        let shard_data =
+            LocalProver::commit_main(machine.config(), &machine, shard, shard.index() as usize);
+        challenger.observe(shard_data.main_commit);
+        machine.observe_public_values(&mut challenger, &shard.public_values::<SC::Val>());
     }
+    let memory_after_commit = sample_memory();
 
-    // Setup the machine.
-    let machine = RiscvAir::machine(config);
-    let (pk, vk) = machine.setup(runtime.program.as_ref());
+    // Prove each shard and write it out before proving the next.
+    let mut proof_bytes = std::mem::size_of::<u64>();
+    writer
+        .write_all(&(shards.len() as u64).to_le_bytes())
+        .map_err(SP1CoreProverError::IoError)?;
+    let mut shard_timings = Vec::with_capacity(shards.len());
+    for shard in shards.iter() {
+        let (shard_proof, timing) =
+            prove_shard::<SC, LocalProver<_, _>>(&machine, &pk, shard, &challenger);
+        let bytes =
+            bincode::serialize(&shard_proof).map_err(SP1CoreProverError::SerializationError)?;
+        writer
+            .write_all(&(bytes.len() as u64).to_le_bytes())
+            .and_then(|_| writer.write_all(&bytes))
+            .map_err(SP1CoreProverError::IoError)?;
+        proof_bytes += std::mem::size_of::<u64>() + bytes.len();
+        shard_timings.push(timing);
+    }
+    writer.flush().map_err(SP1CoreProverError::IoError)?;
 
-    // If we don't need to batch, we can just run the program normally and prove it.
-    if opts.shard_batch_size == 0 {
-        // Execute the runtime and collect all the events..
-        runtime.run().map_err(SP1CoreProverError::ExecutionError)?;
+    // Print the summary.
+    log_summary(
+        &ProveStats {
+            cycles: runtime.state.global_clk,
+            shard_count: shards.len(),
+            prove_time_ms: proving_start.elapsed().as_millis(),
+            proof_bytes,
+            shard_timings,
+            security_bits: machine.config().conjectured_security_bits(),
+            checkpoint_bytes: 0,
+            checkpoint_uncompressed_bytes: 0,
+            memory: PhaseMemoryUsage {
+                after_execution: None,
+                after_commit: memory_after_commit,
+                after_prove: sample_memory(),
+            },
+        },
+        None,
+    );
 
-        // If debugging is enabled, we will also debug the constraints.
-        #[cfg(feature = "debug")]
-        {
-            let mut challenger = machine.config().challenger();
-            machine.debug_constraints(&pk, runtime.record.clone(), &mut challenger);
-        }
+    Ok(())
+}
 
-        // Generate the proof and return the proof and public values.
-        let public_values = std::mem::take(&mut runtime.state.public_values_stream);
-        let proof = prove_simple(machine.config().clone(), runtime)?;
-        return Ok((proof, public_values));
+/// Reads a proof written by [prove_core_to_writer].
+pub fn read_machine_proof_from_reader<SC: StarkGenericConfig, R: Read>(
+    mut reader: R,
+) -> Result<MachineProof<SC>, SP1CoreProverError> {
+    let num_shards = read_u64(&mut reader)?;
+    let mut shard_proofs = Vec::new();
+    for _ in 0..num_shards {
+        let bytes = read_length_prefixed(&mut reader)?;
+        let shard_proof =
+            bincode::deserialize(&bytes).map_err(SP1CoreProverError::SerializationError)?;
+        shard_proofs.push(shard_proof);
     }
+    Ok(MachineProof { shard_proofs })
+}
 
-    // Execute the program, saving checkpoints at the start of every `shard_batch_size` cycle range.
-    let mut checkpoints = Vec::new();
-    let (public_values_stream, public_values) = loop {
-        // Execute the runtime until we reach a checkpoint.
-        let (checkpoint, done) = runtime
-            .execute_state()
-            .map_err(SP1CoreProverError::ExecutionError)?;
+/// Verifies a RISC-V proof written by [prove_core_to_writer] as it is read, holding at most one
+/// shard proof in memory at a time.
+///
+/// Every shard is verified against a challenger that has observed the commitments of all the
+/// shards, so the proof is read twice: once from `reader` to observe each shard while spooling its
+/// bytes to a temporary file, and once from that file to verify each shard. After the last shard,
+/// the cumulative sums are checked to vanish and every shard to share the public values digests of
+/// the first.
+pub fn verify_machine_proof_from_reader<SC: StarkGenericConfig, R: Read>(
+    vk: &StarkVerifyingKey<SC>,
+    mut reader: R,
+    config: SC,
+) -> Result<(), SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let invalid = |e: crate::stark::MachineVerificationError<SC>| {
+        SP1CoreProverError::InvalidProof(format!("{:?}", e))
+    };
+    let machine = RiscvAir::machine(config);
+    let mut challenger = machine.config().challenger();
+    vk.observe_into(&mut challenger);
 
-        // Save the checkpoint to a temp file.
-        let mut tempfile = tempfile::tempfile().map_err(SP1CoreProverError::IoError)?;
-        let mut writer = std::io::BufWriter::new(&mut tempfile);
-        bincode::serialize_into(&mut writer, &checkpoint)
-            .map_err(SP1CoreProverError::SerializationError)?;
-        writer.flush().map_err(SP1CoreProverError::IoError)?;
-        drop(writer);
-        tempfile
-            .seek(std::io::SeekFrom::Start(0))
-            .map_err(SP1CoreProverError::IoError)?;
-        checkpoints.push(tempfile);
+    // Observe the commitment and public values of each shard, spooling it to verify it later.
+    let mut spool = tempfile::tempfile().map_err(SP1CoreProverError::IoError)?;
+    let mut writer = BufWriter::new(&mut spool);
+    let num_shards = read_u64(&mut reader)?;
+    let mut cumulative_sum = SC::Challenge::zero();
+    let mut digests_mismatch = None;
+    let mut first_digests = None;
+    for shard in 0..num_shards as usize {
+        let bytes = read_length_prefixed(&mut reader)?;
+        let shard_proof: ShardProof<SC> =
+            bincode::deserialize(&bytes).map_err(SP1CoreProverError::SerializationError)?;
+        challenger.observe(shard_proof.commitment.main_commit.clone());
+        machine.observe_public_values(&mut challenger, &shard_proof.public_values);
+        cumulative_sum += shard_proof.cumulative_sum();
 
-        // If we've reached the final checkpoint, break out of the loop.
-        if done {
-            break (
-                std::mem::take(&mut runtime.state.public_values_stream),
-                runtime.record.public_values,
-            );
+        let public_values =
+            PublicValues::<Word<Val<SC>>, Val<SC>>::from_vec(shard_proof.public_values);
+        let digests = (
+            public_values.committed_value_digest,
+            public_values.deferred_proofs_digest,
+        );
+        if *first_digests.get_or_insert(digests) != digests {
+            digests_mismatch.get_or_insert(shard);
         }
-    };
 
-    // For each checkpoint, generate events, shard them, commit shards, and observe in challenger.
-    let sharding_config = ShardingConfig::default();
-    let mut shard_main_datas = Vec::new();
-    let mut challenger = machine.config().challenger();
-    vk.observe_into(&mut challenger);
-    for checkpoint_file in checkpoints.iter_mut() {
-        let mut record = trace_checkpoint(program.clone(), checkpoint_file, opts);
-        record.public_values = public_values;
-        reset_seek(&mut *checkpoint_file);
+        writer
+            .write_all(&(bytes.len() as u64).to_le_bytes())
+            .and_then(|_| writer.write_all(&bytes))
+            .map_err(SP1CoreProverError::IoError)?;
+    }
+    writer.flush().map_err(SP1CoreProverError::IoError)?;
+    drop(writer);
+    if num_shards == 0 {
+        return Err(invalid(crate::stark::MachineVerificationError::EmptyProof));
+    }
 
-        // Shard the record into shards.
-        let checkpoint_shards =
-            tracing::info_span!("shard").in_scope(|| machine.shard(record, &sharding_config));
+    // Verify each shard from the spool against the challenger that observed all of them.
+    spool
+        .seek(SeekFrom::Start(0))
+        .map_err(SP1CoreProverError::IoError)?;
+    let mut spool = BufReader::new(spool);
+    for _ in 0..num_shards {
+        let bytes = read_length_prefixed(&mut spool)?;
+        let shard_proof: ShardProof<SC> =
+            bincode::deserialize(&bytes).map_err(SP1CoreProverError::SerializationError)?;
+        machine
+            .verify_shard(vk, &shard_proof, &challenger)
+            .map_err(invalid)?;
+    }
 
-        // Commit to each shard.
-        let (commitments, commit_data) = tracing::info_span!("commit")
-            .in_scope(|| LocalProver::commit_shards(&machine, &checkpoint_shards, opts));
-        shard_main_datas.push(commit_data);
+    if !cumulative_sum.is_zero() {
+        return Err(invalid(
+            crate::stark::MachineVerificationError::NonZeroCumulativeSum,
+        ));
+    }
+    if let Some(shard) = digests_mismatch {
+        return Err(invalid(
+            crate::stark::MachineVerificationError::PublicValuesDigestMismatch(shard),
+        ));
+    }
+    Ok(())
+}
 
-        // Observe the commitments.
-        for (commitment, shard) in commitments.into_iter().zip(checkpoint_shards.iter()) {
-            challenger.observe(commitment);
-            challenger.observe_slice(&shard.public_values::<SC::Val>()[0..machine.num_pv_elts()]);
-        }
+/// Reads a little-endian `u64`, as written by [prove_core_to_writer].
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, SP1CoreProverError> {
+    let mut bytes = [0u8; 8];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(SP1CoreProverError::IoError)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Reads the bytes of a shard proof prefixed by their length, as written by
+/// [prove_core_to_writer].
+///
+/// The length comes from the stream, so the buffer only grows as bytes are actually read rather
+/// than being allocated up front, and a truncated stream fails with an unexpected end of file.
+fn read_length_prefixed<R: Read>(reader: &mut R) -> Result<Vec<u8>, SP1CoreProverError> {
+    let len = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    reader
+        .by_ref()
+        .take(len)
+        .read_to_end(&mut bytes)
+        .map_err(SP1CoreProverError::IoError)?;
+    if (bytes.len() as u64) < len {
+        return Err(SP1CoreProverError::IoError(io::Error::from(
+            io::ErrorKind::UnexpectedEof,
+        )));
     }
+    Ok(bytes)
+}
 
-    // For each checkpoint, generate events and shard again, then prove the shards.
-    let mut shard_proofs = Vec::<ShardProof<SC>>::new();
-    for mut checkpoint_file in checkpoints.into_iter() {
-        let checkpoint_shards = {
-            let mut events = trace_checkpoint(program.clone(), &checkpoint_file, opts);
-            events.public_values = public_values;
-            reset_seek(&mut checkpoint_file);
-            tracing::debug_span!("shard").in_scope(|| machine.shard(events, &sharding_config))
-        };
-        let mut checkpoint_proofs = checkpoint_shards
-            .into_iter()
-            .map(|shard| {
-                let config = machine.config();
-                let shard_data =
-                    LocalProver::commit_main(config, &machine, &shard, shard.index() as usize);
-
-                let chip_ordering = shard_data.chip_ordering.clone();
-                let ordered_chips = machine
-                    .shard_chips_ordered(&chip_ordering)
-                    .collect::<Vec<_>>()
-                    .to_vec();
-                LocalProver::prove_shard(
-                    config,
-                    &pk,
-                    &ordered_chips,
-                    shard_data,
-                    &mut challenger.clone(),
-                )
-            })
-            .collect::<Vec<_>>();
-        shard_proofs.append(&mut checkpoint_proofs);
+pub fn prove<SC: StarkGenericConfig + Send + Sync + 'static>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    opts: SP1CoreOpts,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    prove_with_options(program, stdin, config, ProveOptions::from(opts))
+}
+
+/// Proves a program like [prove], returning the public values stream as [SP1PublicValues].
+pub fn prove_typed<SC: StarkGenericConfig + Send + Sync + 'static>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    opts: SP1CoreOpts,
+) -> Result<(MachineProof<SC>, SP1PublicValues), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let (proof, public_values_stream) = prove(program, stdin, config, opts)?;
+    Ok((proof, SP1PublicValues::from(&public_values_stream)))
+}
+
+/// Proves a program with explicit batching and sharding options.
+///
+/// With a batch size of zero, or when the program fits in a single batch and
+/// [ProveOptions::reuse_single_batch] is set, the program is executed and proven in one go without
+/// saving checkpoints.
+pub fn prove_with_options<SC: StarkGenericConfig + Send + Sync + 'static>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: ProveOptions,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    match options.determinism {
+        Determinism::Relaxed => prove_with_progress(program, stdin, config, options, |_| {}),
+        Determinism::Strict => prove_strict(|| {
+            prove_with_progress(
+                program.clone(),
+                stdin,
+                config.clone(),
+                options.clone(),
+                |_| {},
+            )
+        }),
     }
-    let proof = MachineProof::<SC> { shard_proofs };
+}
 
-    // Print the summary.
-    let proving_time = proving_start.elapsed().as_secs_f64();
-    tracing::info!(
-        "summary: cycles={}, e2e={}, khz={:.2}, proofSize={}",
-        runtime.state.global_clk,
-        proving_time,
-        (runtime.state.global_clk as f64 / proving_time as f64),
-        bincode::serialize(&proof).unwrap().len(),
-    );
+/// Proves a program, calling `progress` after each checkpoint is executed, after each checkpoint
+/// is committed and after each shard is proven.
+pub fn prove_with_progress<
+    SC: StarkGenericConfig + Send + Sync + 'static,
+    F: FnMut(ProveProgress),
+>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: ProveOptions,
+    progress: F,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    prove_checkpoints(
+        program,
+        stdin,
+        config,
+        options,
+        progress,
+        &CancellationToken::new(),
+        &SetupCache::new(),
+        &[],
+    )
+}
 
-    Ok((proof, public_values_stream))
+/// Proves a program like [prove], with each `(address, bytes)` region of `preload` loaded into
+/// memory before execution.
+///
+/// This lets the program read large inputs, such as a read-only dataset, without deserializing
+/// them from stdin. Proving fails with [SP1CoreProverError::InvalidMemoryPreload] if a region is
+/// rejected by [Runtime::preload_memory], for instance because it overlaps the program image.
+pub fn prove_with_memory<SC: StarkGenericConfig + Send + Sync + 'static>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    opts: SP1CoreOpts,
+    preload: Vec<(u32, Vec<u8>)>,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    prove_checkpoints(
+        program,
+        stdin,
+        config,
+        ProveOptions::from(opts),
+        |_| {},
+        &CancellationToken::new(),
+        &SetupCache::new(),
+        &preload,
+    )
 }
 
-/// Runs a program and returns the public values stream.
-pub fn run_test_io(
+/// Proves a program, aborting with [SP1CoreProverError::Cancelled] once `cancel` is cancelled.
+///
+/// The token is checked between checkpoints and between shard proofs. Checkpoints are kept in
+/// anonymous temp files, which are removed when proving stops.
+pub fn prove_cancellable<SC: StarkGenericConfig + Send + Sync + 'static>(
     program: Program,
-    inputs: SP1Stdin,
-) -> Result<SP1PublicValues, crate::stark::MachineVerificationError<BabyBearPoseidon2>> {
-    let runtime = tracing::info_span!("runtime.run(...)").in_scope(|| {
-        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
-        runtime.write_vecs(&inputs.buffer);
-        runtime.run().unwrap();
-        runtime
-    });
-    let public_values = SP1PublicValues::from(&runtime.state.public_values_stream);
-    let _ = run_test_core(runtime)?;
-    Ok(public_values)
+    stdin: &SP1Stdin,
+    config: SC,
+    options: ProveOptions,
+    cancel: &CancellationToken,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    prove_checkpoints(
+        program,
+        stdin,
+        config,
+        options,
+        |_| {},
+        cancel,
+        &SetupCache::new(),
+        &[],
+    )
 }
 
-pub fn run_test(
+/// Proves a program like [prove_with_options], taking the program's keys from `setup_cache` and
+/// only running the setup if they are not already cached.
+///
+/// The program is proven once, so [Determinism::Strict] is not checked.
+pub fn prove_with_setup_cache<SC: StarkGenericConfig + Send + Sync + 'static>(
     program: Program,
-) -> Result<
-    crate::stark::MachineProof<BabyBearPoseidon2>,
-    crate::stark::MachineVerificationError<BabyBearPoseidon2>,
-> {
-    let runtime = tracing::info_span!("runtime.run(...)").in_scope(|| {
-        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
-        runtime.run().unwrap();
-        runtime
-    });
-    run_test_core(runtime)
+    stdin: &SP1Stdin,
+    config: SC,
+    options: ProveOptions,
+    setup_cache: &SetupCache<SC>,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    prove_checkpoints(
+        program,
+        stdin,
+        config,
+        options,
+        |_| {},
+        &CancellationToken::new(),
+        setup_cache,
+        &[],
+    )
 }
 
-#[allow(unused_variables)]
-pub fn run_test_core(
-    runtime: Runtime,
-) -> Result<
-    crate::stark::MachineProof<BabyBearPoseidon2>,
-    crate::stark::MachineVerificationError<BabyBearPoseidon2>,
-> {
-    let config = BabyBearPoseidon2::new();
-    let machine = RiscvAir::machine(config);
-    let (pk, vk) = machine.setup(runtime.program.as_ref());
+/// Executes a program in one go and proves the shards of its record, without checkpoints.
+fn prove_unbatched<SC: StarkGenericConfig + Send + Sync, F: FnMut(ProveProgress)>(
+    machine: &StarkMachine<SC, RiscvAir<SC::Val>>,
+    pk: &StarkProvingKey<SC>,
+    mut runtime: Runtime,
+    options: &ProveOptions,
+    reference_stream: Option<&[u8]>,
+    progress: &mut F,
+    cancel: &CancellationToken,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    // Execute the runtime and collect all the events..
+    runtime.run().map_err(SP1CoreProverError::ExecutionError)?;
+    prove_executed(
+        machine,
+        pk,
+        runtime,
+        options,
+        reference_stream,
+        progress,
+        cancel,
+    )
+}
+
+/// Proves the shards of the record of a runtime that has executed the whole program with events.
+fn prove_executed<SC: StarkGenericConfig + Send + Sync, F: FnMut(ProveProgress)>(
+    machine: &StarkMachine<SC, RiscvAir<SC::Val>>,
+    pk: &StarkProvingKey<SC>,
+    mut runtime: Runtime,
+    options: &ProveOptions,
+    reference_stream: Option<&[u8]>,
+    progress: &mut F,
+    cancel: &CancellationToken,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    check_public_values(options, &runtime.record.public_values)?;
+    check_reexecution(reference_stream, &runtime.state.public_values_stream)?;
+    let cycles = runtime.state.global_clk;
+    progress(ProveProgress {
+        phase: ProvePhase::Execution,
+        shard_index: runtime.state.current_shard as usize,
+        total_shards: None,
+        cycles_done: cycles,
+    });
+
+    // If debugging is enabled, we will also debug the constraints.
+    #[cfg(feature = "debug")]
+    {
+        let mut challenger = machine.config().challenger();
+        machine.debug_constraints(pk, runtime.record.clone(), &mut challenger);
+    }
+
+    // Generate the proof and return the proof and public values.
+    cancel.check()?;
+    let public_values_stream = std::mem::take(&mut runtime.state.public_values_stream);
+    let public_values = runtime.record.public_values;
+    let shards = tracing::info_span!("shard_record")
+        .in_scope(|| machine.shard(runtime.record, &options.sharding_config()));
+    check_shard_public_values::<SC::Val>(&shards, 0, &public_values)?;
+    let proof = prove_shards_with_keys(machine, pk, shards, options.metrics_sink.as_deref());
+    let total_shards = proof.shard_proofs.len();
+    progress(ProveProgress {
+        phase: ProvePhase::Prove,
+        shard_index: total_shards,
+        total_shards: Some(total_shards),
+        cycles_done: cycles,
+    });
+    Ok((proof, public_values_stream))
+}
+
+/// Proves a program, reporting progress and stopping early when `cancel` is cancelled.
+#[allow(clippy::too_many_arguments)]
+fn prove_checkpoints<SC: StarkGenericConfig + Send + Sync + 'static, F: FnMut(ProveProgress)>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: ProveOptions,
+    mut progress: F,
+    cancel: &CancellationToken,
+    setup_cache: &SetupCache<SC>,
+    preload: &[(u32, Vec<u8>)],
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    cancel.check()?;
+    options
+        .sharding_config()
+        .validate(&config)
+        .map_err(SP1CoreProverError::InvalidShardingConfig)?;
+    let proving_start = Instant::now();
+    let opts = options.core_opts();
+
+    // Execute the program once without events to compare the public values of the execution that
+    // is proven against.
+    let reference_stream = match options.verify_reexecution {
+        true => Some(execute_public_values_stream(
+            program.clone(),
+            stdin,
+            &options,
+            preload,
+        )?),
+        false => None,
+    };
+
+    // Execute the program. Checkpoints are anonymous temp files, so any already written are
+    // removed when execution fails on the cycle limit.
+    let mut runtime = new_runtime(program.clone(), stdin, &options, preload)?;
+
+    // Setup the machine. It is shared with the threads that prove shards under a timeout.
+    let machine =
+        Arc::new(RiscvAir::machine(config).with_transcript_domain(options.transcript_domain));
+    let keys = setup_cache.get_or_setup(&machine, &program)?;
+    let (pk, vk) = &*keys;
+
+    // If we don't need to batch, we can just run the program normally and prove it.
+    if opts.shard_batch_size == 0 {
+        return prove_unbatched(
+            &machine,
+            pk,
+            runtime,
+            &options,
+            reference_stream.as_deref(),
+            &mut progress,
+            cancel,
+        );
+    }
+
+    // A program that fits in a single batch gains nothing from checkpoints, so if allowed, and
+    // unless its shards must each be timed, execute the first batch with events. If the program
+    // ends within it, prove it from that record like an unbatched one. Otherwise drop the events
+    // and save the state the batch started from as the first checkpoint.
+    let mut first_checkpoint = None;
+    if options.reuse_single_batch && options.per_shard_timeout.is_none() {
+        let prestate = runtime.state.clone();
+        let (record, done) = runtime
+            .execute_record()
+            .map_err(SP1CoreProverError::ExecutionError)?;
+        if done {
+            runtime.record = record;
+            return prove_executed(
+                &machine,
+                pk,
+                runtime,
+                &options,
+                reference_stream.as_deref(),
+                &mut progress,
+                cancel,
+            );
+        }
+        // Checkpoints do not carry the values committed so far, so keep them in the runtime.
+        runtime.record.public_values = record.public_values;
+        first_checkpoint = Some(Ok(prestate));
+    }
+
+    // Execute the program, saving checkpoints at the start of every `shard_batch_size` cycle range.
+    // Checkpoints are pulled lazily and written straight to disk, so only one is resident at a time.
+    let codec = options.checkpoint_codec;
+    let mut checkpoints = Vec::new();
+    let mut checkpoint_bytes = 0;
+    let mut checkpoint_uncompressed_bytes = 0;
+    let mut checkpoint_iter = runtime.checkpoint_iter();
+    while let Some(checkpoint) = first_checkpoint.take().or_else(|| checkpoint_iter.next()) {
+        let checkpoint = checkpoint.map_err(SP1CoreProverError::ExecutionError)?;
+        let checkpoint_file = save_checkpoint_with_codec(&program, &checkpoint, codec)?;
+        checkpoint_bytes += checkpoint_file
+            .metadata()
+            .map_err(SP1CoreProverError::IoError)?
+            .len();
+        checkpoint_uncompressed_bytes += bincode::serialized_size(&checkpoint)
+            .map_err(SP1CoreProverError::SerializationError)?;
+        checkpoints.push(checkpoint_file);
+        cancel.check()?;
+
+        let state = checkpoint_iter.state();
+        progress(ProveProgress {
+            phase: ProvePhase::Execution,
+            shard_index: state.current_shard as usize,
+            total_shards: None,
+            cycles_done: state.global_clk,
+        });
+    }
+    let public_values_stream = std::mem::take(&mut runtime.state.public_values_stream);
+    let public_values = runtime.record.public_values;
+    check_public_values(&options, &public_values)?;
+    check_reexecution(reference_stream.as_deref(), &public_values_stream)?;
+    let memory_after_execution = sample_memory();
+
+    // For each checkpoint, generate events, shard them, commit shards, and observe in challenger.
+    let sharding_config = options.sharding_config();
+    let mut shard_main_datas = Vec::new();
+    let mut traced_checkpoints = Vec::new();
+    let mut challenger = machine.config().challenger();
+    vk.observe_into(&mut challenger);
+    let mut shards_committed = 0;
+    let mut cycles_committed = 0;
+    for (checkpoint_index, checkpoint_file) in checkpoints.iter_mut().enumerate() {
+        cancel.check()?;
+        let checkpoint_shards = tracing::info_span!("trace", checkpoint_index).in_scope(|| {
+            trace_and_shard(
+                &machine,
+                program.clone(),
+                checkpoint_file,
+                opts,
+                public_values,
+                &sharding_config,
+                codec,
+            )
+        })?;
+        check_shard_public_values::<SC::Val>(&checkpoint_shards, shards_committed, &public_values)?;
+        cycles_committed += checkpoint_shards
+            .iter()
+            .map(|shard| shard.cpu_events.len() as u64)
+            .sum::<u64>();
+
+        // Commit to each shard.
+        let (commitments, commit_data) = tracing::info_span!("commit", checkpoint_index)
+            .in_scope(|| LocalProver::commit_shards(&machine, &checkpoint_shards, opts));
+        shard_main_datas.push(commit_data);
+
+        // Observe the commitments.
+        for (commitment, shard) in commitments.into_iter().zip(checkpoint_shards.iter()) {
+            challenger.observe(commitment);
+            machine.observe_public_values(&mut challenger, &shard.public_values::<SC::Val>());
+        }
+
+        shards_committed += checkpoint_shards.len();
+        progress(ProveProgress {
+            phase: ProvePhase::Commit,
+            shard_index: shards_committed,
+            total_shards: None,
+            cycles_done: cycles_committed,
+        });
+        if options.strategy == ProveStrategy::Interleaved {
+            traced_checkpoints.push(checkpoint_shards);
+        }
+    }
+
+    let memory_after_commit = sample_memory();
+
+    // For each checkpoint, generate events and shard again unless they were kept from the commit
+    // phase, then prove the shards. Each shard is proven against its own copy of the challenger,
+    // so shards can be proven in any order.
+    let prove_shard = |shard: &ExecutionRecord| {
+        prove_shard::<SC, LocalProver<_, _>>(&machine, pk, shard, &challenger)
+    };
+    let total_shards = shards_committed;
+    let mut shards_proved = 0;
+    let mut cycles_proved = 0;
+    let mut report_proved = |shard_cycles: u64| {
+        shards_proved += 1;
+        cycles_proved += shard_cycles;
+        progress(ProveProgress {
+            phase: ProvePhase::Prove,
+            shard_index: shards_proved,
+            total_shards: Some(total_shards),
+            cycles_done: cycles_proved,
+        });
+    };
+    let mut shard_proofs = Vec::<ShardProof<SC>>::new();
+    let mut shard_timings = Vec::new();
+    let mut traced_checkpoints = traced_checkpoints.into_iter();
+    for (checkpoint_index, mut checkpoint_file) in checkpoints.into_iter().enumerate() {
+        let checkpoint_shards = match traced_checkpoints.next() {
+            Some(checkpoint_shards) => checkpoint_shards,
+            None => tracing::info_span!("trace", checkpoint_index).in_scope(|| {
+                trace_and_shard(
+                    &machine,
+                    program.clone(),
+                    &mut checkpoint_file,
+                    opts,
+                    public_values,
+                    &sharding_config,
+                    codec,
+                )
+            })?,
+        };
+        if let Some(timeout) = options.per_shard_timeout {
+            // Shards are proven on detached threads so that a hung one can be abandoned. With
+            // parallel proving, every shard of the checkpoint is started at once.
+            let batch_size = if options.parallel_shard_proving {
+                checkpoint_shards.len().max(1)
+            } else {
+                1
+            };
+            let mut checkpoint_shards = checkpoint_shards.into_iter().peekable();
+            while checkpoint_shards.peek().is_some() {
+                cancel.check()?;
+                let pending = checkpoint_shards
+                    .by_ref()
+                    .take(batch_size)
+                    .map(|shard| {
+                        let shard_cycles = shard.cpu_events.len() as u64;
+                        let receiver = spawn_shard_prover(&machine, &keys, shard, &challenger);
+                        (shard_cycles, receiver, Instant::now())
+                    })
+                    .collect::<Vec<_>>();
+                for (shard_cycles, receiver, start) in pending {
+                    let index = shard_proofs.len();
+                    let remaining = timeout.saturating_sub(start.elapsed());
+                    let (shard_proof, timing) = match receiver.recv_timeout(remaining) {
+                        Ok(result) => result,
+                        Err(RecvTimeoutError::Timeout) => {
+                            return Err(SP1CoreProverError::ShardTimeout { index })
+                        }
+                        Err(RecvTimeoutError::Disconnected) => {
+                            return Err(SP1CoreProverError::ShardProverPanicked { index })
+                        }
+                    };
+                    shard_proofs.push(shard_proof);
+                    shard_timings.push(timing);
+                    report_proved(shard_cycles);
+                }
+            }
+        } else if options.parallel_shard_proving {
+            // `collect` on an indexed parallel iterator preserves the order of the shards.
+            let (mut checkpoint_proofs, mut checkpoint_timings): (Vec<_>, Vec<_>) =
+                checkpoint_shards
+                    .par_iter()
+                    .map(|shard| cancel.check().map(|_| prove_shard(shard)))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .unzip();
+            shard_proofs.append(&mut checkpoint_proofs);
+            shard_timings.append(&mut checkpoint_timings);
+            checkpoint_shards
+                .iter()
+                .for_each(|shard| report_proved(shard.cpu_events.len() as u64));
+        } else {
+            for shard in checkpoint_shards.iter() {
+                cancel.check()?;
+                let (shard_proof, timing) = prove_shard(shard);
+                shard_proofs.push(shard_proof);
+                shard_timings.push(timing);
+                report_proved(shard.cpu_events.len() as u64);
+            }
+        }
+    }
+    let proof = MachineProof::<SC> { shard_proofs };
+
+    // Print the summary.
+    let mut stats = ProveStats::new(
+        machine.config(),
+        runtime.state.global_clk,
+        proving_start.elapsed(),
+        &proof,
+    );
+    stats.shard_timings = shard_timings;
+    stats.checkpoint_bytes = checkpoint_bytes;
+    stats.checkpoint_uncompressed_bytes = checkpoint_uncompressed_bytes;
+    stats.memory.after_execution = memory_after_execution;
+    stats.memory.after_commit = memory_after_commit;
+    tracing::debug!(
+        "checkpoint size: written={}, uncompressed={}",
+        Size::from_bytes(checkpoint_bytes),
+        Size::from_bytes(checkpoint_uncompressed_bytes),
+    );
+    log_summary(&stats, options.metrics_sink.as_deref());
+
+    Ok((proof, public_values_stream))
+}
+
+/// The commitment to a shard's main trace, together with the public values observed after it.
+pub type ShardCommitment<SC> = (Com<SC>, Vec<<SC as StarkGenericConfig>::Val>);
+
+/// Commits to the shards of a set of checkpoints saved by [save_checkpoint_with_codec] with the
+/// codec of `options`, returning the commitment and public values of every shard in shard order.
+///
+/// Checkpoints are committed independently of each other, so workers can each commit a disjoint
+/// range of a program's checkpoints in parallel. The coordinator concatenates the results in
+/// checkpoint order and absorbs them with [observe_commitments].
+pub fn commit_checkpoints<SC: StarkGenericConfig + Send + Sync>(
+    machine: &StarkMachine<SC, RiscvAir<SC::Val>>,
+    program: Program,
+    checkpoints: &mut [File],
+    options: &ProveOptions,
+    public_values: PublicValues<u32, u32>,
+) -> Result<Vec<ShardCommitment<SC>>, SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let opts = options.core_opts();
+    let sharding_config = options.sharding_config();
+    let mut shard_commitments = Vec::new();
+    for (checkpoint_index, checkpoint_file) in checkpoints.iter_mut().enumerate() {
+        let checkpoint_shards = tracing::info_span!("trace", checkpoint_index).in_scope(|| {
+            trace_and_shard(
+                machine,
+                program.clone(),
+                checkpoint_file,
+                opts,
+                public_values,
+                &sharding_config,
+                options.checkpoint_codec,
+            )
+        })?;
+        check_shard_public_values::<SC::Val>(
+            &checkpoint_shards,
+            shard_commitments.len(),
+            &public_values,
+        )?;
+        let (commitments, _) = tracing::info_span!("commit", checkpoint_index)
+            .in_scope(|| LocalProver::commit_shards(machine, &checkpoint_shards, opts));
+        for (commitment, shard) in commitments.into_iter().zip(checkpoint_shards.iter()) {
+            let shard_public_values =
+                shard.public_values::<SC::Val>()[0..machine.num_pv_elts()].to_vec();
+            shard_commitments.push((commitment, shard_public_values));
+        }
+    }
+    Ok(shard_commitments)
+}
+
+/// Observes shard commitments returned by [commit_checkpoints] in the challenger, in order.
+///
+/// The public values are observed with [StarkMachine::observe_public_values], so the challenger
+/// matches the one of [StarkMachine::verify] for a machine with a transcript domain too.
+pub fn observe_commitments<SC: StarkGenericConfig>(
+    machine: &StarkMachine<SC, RiscvAir<SC::Val>>,
+    challenger: &mut SC::Challenger,
+    shard_commitments: &[ShardCommitment<SC>],
+) where
+    Com<SC>: Clone,
+{
+    for (commitment, public_values) in shard_commitments {
+        challenger.observe(commitment.clone());
+        machine.observe_public_values(challenger, public_values);
+    }
+}
+
+/// Executes a program, commits to every shard and returns the challenger after observing the
+/// verifying key and all commitments, together with the program's final public values.
+///
+/// This is the coordinator side of proving checkpoints independently: the returned challenger
+/// and public values are everything [prove_checkpoint_with_challenger] needs to prove one
+/// checkpoint's shards without re-observing the commitments of the others.
+pub fn observe_checkpoints<SC: StarkGenericConfig + Send + Sync>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: &ProveOptions,
+) -> Result<(SC::Challenger, PublicValues<u32, u32>), SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let mut checkpoints = count_checkpoints(&program, stdin, options)?;
+    let public_values = checkpoints.public_values;
+
+    let machine = RiscvAir::machine(config);
+    let (_, vk) = machine.setup(&program);
+
+    let shard_commitments = commit_checkpoints(
+        &machine,
+        program,
+        &mut checkpoints.files,
+        options,
+        public_values,
+    )?;
+    let mut challenger = machine.config().challenger();
+    vk.observe_into(&mut challenger);
+    observe_commitments(&machine, &mut challenger, &shard_commitments);
+
+    Ok((challenger, public_values))
+}
+
+/// Proves the shards of a single checkpoint against a challenger produced by
+/// [observe_checkpoints].
+///
+/// The program is only executed up to the requested checkpoint. The returned shard proofs, in
+/// checkpoint order across all checkpoints, form the same [MachineProof] as [prove_with_options]
+/// with the same `options`. The program's keys are taken from `setup_cache`, so proving each
+/// checkpoint of a program with the same cache only runs the setup once.
+#[allow(clippy::too_many_arguments)]
+pub fn prove_checkpoint_with_challenger<SC: StarkGenericConfig + Send + Sync>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: &ProveOptions,
+    checkpoint_num: usize,
+    public_values: PublicValues<u32, u32>,
+    challenger: &SC::Challenger,
+    setup_cache: &SetupCache<SC>,
+) -> Result<Vec<ShardProof<SC>>, SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    prove_checkpoint_with_shard_prover::<SC, LocalProver<_, _>>(
+        program,
+        stdin,
+        config,
+        options,
+        checkpoint_num,
+        public_values,
+        challenger,
+        setup_cache,
+    )
+}
+
+/// Proves the shards of a single checkpoint like [prove_checkpoint_with_challenger], committing
+/// to and proving each shard with `P`.
+#[allow(clippy::too_many_arguments)]
+pub fn prove_checkpoint_with_shard_prover<SC, P>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: &ProveOptions,
+    checkpoint_num: usize,
+    public_values: PublicValues<u32, u32>,
+    challenger: &SC::Challenger,
+    setup_cache: &SetupCache<SC>,
+) -> Result<Vec<ShardProof<SC>>, SP1CoreProverError>
+where
+    SC: StarkGenericConfig + Send + Sync,
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+    P: ShardProver<SC, RiscvAir<SC::Val>>,
+{
+    prove_checkpoint_range_with_shard_prover::<SC, P>(
+        program,
+        stdin,
+        config,
+        options,
+        checkpoint_num..checkpoint_num + 1,
+        public_values,
+        challenger,
+        setup_cache,
+    )
+}
+
+/// Proves the shards of a contiguous range of checkpoints like [prove_checkpoint_with_challenger],
+/// returning their shard proofs in checkpoint order.
+///
+/// The program is executed once up to the end of the range, so proving several checkpoints at once
+/// only pays for that execution once. Fails with [SP1CoreProverError::InvalidCheckpointRange] if
+/// the range is empty, and with [SP1CoreProverError::CheckpointOutOfRange] if the program has
+/// fewer checkpoints than the end of the range.
+#[allow(clippy::too_many_arguments)]
+pub fn prove_checkpoint_range_with_challenger<SC: StarkGenericConfig + Send + Sync>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: &ProveOptions,
+    range: Range<usize>,
+    public_values: PublicValues<u32, u32>,
+    challenger: &SC::Challenger,
+    setup_cache: &SetupCache<SC>,
+) -> Result<Vec<ShardProof<SC>>, SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    prove_checkpoint_range_with_shard_prover::<SC, LocalProver<_, _>>(
+        program,
+        stdin,
+        config,
+        options,
+        range,
+        public_values,
+        challenger,
+        setup_cache,
+    )
+}
+
+/// Proves the shards of a contiguous range of checkpoints like
+/// [prove_checkpoint_range_with_challenger], committing to and proving each shard with `P`.
+#[allow(clippy::too_many_arguments)]
+pub fn prove_checkpoint_range_with_shard_prover<SC, P>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: &ProveOptions,
+    range: Range<usize>,
+    public_values: PublicValues<u32, u32>,
+    challenger: &SC::Challenger,
+    setup_cache: &SetupCache<SC>,
+) -> Result<Vec<ShardProof<SC>>, SP1CoreProverError>
+where
+    SC: StarkGenericConfig + Send + Sync,
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+    P: ShardProver<SC, RiscvAir<SC::Val>>,
+{
+    if range.is_empty() {
+        return Err(SP1CoreProverError::InvalidCheckpointRange(range));
+    }
+
+    let mut runtime = new_runtime(program.clone(), stdin, options, &[])?;
+
+    let machine = RiscvAir::machine(config);
+    let keys = setup_cache.get_or_setup(&machine, &program)?;
+    let pk = &keys.0;
+
+    // Execute up to the end of the range, only saving the checkpoints within it.
+    let mut checkpoint_files = Vec::with_capacity(range.len());
+    let mut checkpoint_iter = runtime.checkpoint_iter();
+    for checkpoint_num in 0..range.end {
+        let checkpoint = checkpoint_iter
+            .next()
+            .ok_or(SP1CoreProverError::CheckpointOutOfRange(checkpoint_num))?
+            .map_err(SP1CoreProverError::ExecutionError)?;
+        if range.contains(&checkpoint_num) {
+            checkpoint_files.push(save_checkpoint_with_codec(
+                &program,
+                &checkpoint,
+                options.checkpoint_codec,
+            )?);
+        }
+    }
+
+    let mut shard_proofs = Vec::new();
+    for mut checkpoint_file in checkpoint_files {
+        shard_proofs.extend(prove_checkpoint_file::<SC, P>(
+            &machine,
+            pk,
+            program.clone(),
+            &mut checkpoint_file,
+            options,
+            public_values,
+            challenger,
+        )?);
+    }
+    Ok(shard_proofs)
+}
+
+/// The checkpoints of a single execution of a program, saved by [count_checkpoints].
+pub struct SavedCheckpoints {
+    /// The saved checkpoints in execution order, each rewound so that it can be read back.
+    pub files: Vec<File>,
+    /// The public values at the end of the execution.
+    pub public_values: PublicValues<u32, u32>,
+    /// The public values stream written by the program.
+    pub public_values_stream: Vec<u8>,
+    /// The number of cycles of the execution.
+    pub cycles: u64,
+    /// The size of the saved checkpoints, as written to disk, in bytes.
+    pub bytes: u64,
+    /// The size of the saved checkpoints before any compression, in bytes.
+    pub uncompressed_bytes: u64,
+}
+
+impl SavedCheckpoints {
+    /// The number of checkpoints the program produced.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Executes a program once, saving every checkpoint to a temp file with the codec of `options`.
+///
+/// The number of checkpoints tells a coordinator how many jobs to dispatch, and each saved
+/// checkpoint can be proven with [prove_saved_checkpoint_with_challenger] without executing the
+/// program again.
+pub fn count_checkpoints(
+    program: &Program,
+    stdin: &SP1Stdin,
+    options: &ProveOptions,
+) -> Result<SavedCheckpoints, SP1CoreProverError> {
+    let mut runtime = new_runtime(program.clone(), stdin, options, &[])?;
+
+    let mut files = Vec::new();
+    let mut bytes = 0;
+    let mut uncompressed_bytes = 0;
+    for checkpoint in runtime.checkpoint_iter() {
+        let checkpoint = checkpoint.map_err(SP1CoreProverError::ExecutionError)?;
+        let file = save_checkpoint_with_codec(program, &checkpoint, options.checkpoint_codec)?;
+        bytes += file.metadata().map_err(SP1CoreProverError::IoError)?.len();
+        uncompressed_bytes += bincode::serialized_size(&checkpoint)
+            .map_err(SP1CoreProverError::SerializationError)?;
+        files.push(file);
+    }
+    Ok(SavedCheckpoints {
+        files,
+        public_values: runtime.record.public_values,
+        public_values_stream: std::mem::take(&mut runtime.state.public_values_stream),
+        cycles: runtime.state.global_clk,
+        bytes,
+        uncompressed_bytes,
+    })
+}
+
+/// Executes a program without tracing or proving it, returning the number of cycles in each
+/// checkpoint.
+///
+/// Each checkpoint is one job of [prove_with_worker_pool] or [prove_checkpoint_with_challenger],
+/// so its cycle count predicts the cost of proving that job.
+pub fn checkpoint_cycle_counts(
+    program: Program,
+    stdin: &SP1Stdin,
+    options: &ProveOptions,
+) -> Result<Vec<u64>, SP1CoreProverError> {
+    let mut runtime = new_runtime(program, stdin, options, &[])?;
+
+    let mut cycle_counts = Vec::new();
+    let mut checkpoint_iter = runtime.checkpoint_iter();
+    while let Some(checkpoint) = checkpoint_iter.next() {
+        let checkpoint = checkpoint.map_err(SP1CoreProverError::ExecutionError)?;
+        cycle_counts.push(checkpoint_iter.state().global_clk - checkpoint.global_clk);
+    }
+    Ok(cycle_counts)
+}
+
+/// Proves the shards of a checkpoint saved by [count_checkpoints] against a challenger produced
+/// by [observe_checkpoints], like [prove_checkpoint_with_challenger] but without executing the
+/// program up to the checkpoint.
+pub fn prove_saved_checkpoint_with_challenger<SC: StarkGenericConfig + Send + Sync>(
+    program: Program,
+    checkpoint: &mut File,
+    config: SC,
+    options: &ProveOptions,
+    public_values: PublicValues<u32, u32>,
+    challenger: &SC::Challenger,
+    setup_cache: &SetupCache<SC>,
+) -> Result<Vec<ShardProof<SC>>, SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let machine = RiscvAir::machine(config);
+    let keys = setup_cache.get_or_setup(&machine, &program)?;
+    prove_checkpoint_file::<SC, LocalProver<_, _>>(
+        &machine,
+        &keys.0,
+        program,
+        checkpoint,
+        options,
+        public_values,
+        challenger,
+    )
+}
+
+/// Re-executes a saved checkpoint and proves each of its shards with `P`.
+fn prove_checkpoint_file<SC, P>(
+    machine: &StarkMachine<SC, RiscvAir<SC::Val>>,
+    pk: &StarkProvingKey<SC>,
+    program: Program,
+    checkpoint: &mut File,
+    options: &ProveOptions,
+    public_values: PublicValues<u32, u32>,
+    challenger: &SC::Challenger,
+) -> Result<Vec<ShardProof<SC>>, SP1CoreProverError>
+where
+    SC: StarkGenericConfig + Send + Sync,
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+    P: ShardProver<SC, RiscvAir<SC::Val>>,
+{
+    let checkpoint_shards = trace_and_shard(
+        machine,
+        program,
+        checkpoint,
+        options.core_opts(),
+        public_values,
+        &options.sharding_config(),
+        options.checkpoint_codec,
+    )?;
+    Ok(checkpoint_shards
+        .iter()
+        .map(|shard| prove_shard::<SC, P>(machine, pk, shard, challenger).0)
+        .collect())
+}
+
+/// The manifest of a resumable proving run, stored in its checkpoint directory.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+struct ResumeManifest<SC: StarkGenericConfig> {
+    /// A digest of the program, its verifying key, its inputs, its checkpoint size, its
+    /// sharding config and its checkpoint codec.
+    run_digest: [u8; 32],
+    /// The number of checkpoints written to the checkpoint directory.
+    num_checkpoints: usize,
+    /// The number of cycles of the execution.
+    cycles: u64,
+    /// The public values at the end of the execution.
+    public_values: PublicValues<u32, u32>,
+    /// The public values stream written by the program.
+    public_values_stream: Vec<u8>,
+    /// The commitment and public values of every shard, in shard order.
+    shard_commitments: Vec<ShardCommitment<SC>>,
+    /// The checkpoints whose shard proofs have been written to the checkpoint directory.
+    proved: Vec<usize>,
+}
+
+/// Proves a program like [prove], persisting its progress to `checkpoint_dir` so that an
+/// interrupted run can be resumed.
+///
+/// Checkpoints are written as `checkpoint_{i}.bin` and the shard proofs of each checkpoint as
+/// `proof_{i}.bin`, next to a `manifest.bin` recording the shard commitments and which checkpoints
+/// are proven. A resumed run neither executes the program nor commits to its shards again: it
+/// reads the checkpoints back from the directory and only proves the ones that the manifest does
+/// not list. The directory is left in place when proving fails, so that it can be resumed. If it
+/// holds the run of a different program, verifying key, input, checkpoint size, sharding config or
+/// checkpoint codec, proving fails with [SP1CoreProverError::ResumeMismatch].
+pub fn prove_resumable<SC: StarkGenericConfig + Send + Sync>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: ProveOptions,
+    checkpoint_dir: &Path,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    Dom<SC>: Serialize,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    options
+        .sharding_config()
+        .validate(&config)
+        .map_err(SP1CoreProverError::InvalidShardingConfig)?;
+    let proving_start = Instant::now();
+    std::fs::create_dir_all(checkpoint_dir).map_err(SP1CoreProverError::IoError)?;
+    let opts = options.core_opts();
+    let sharding_config = options.sharding_config();
+    let codec = options.checkpoint_codec;
+    let machine = RiscvAir::machine(config);
+    let (pk, vk) = machine.setup(&program);
+
+    // Identify the run by everything that determines its checkpoints and shard commitments.
+    let digest = program_digest(&program)?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&digest);
+    hasher.update(&vk.fingerprint());
+    bincode::serialize_into(&mut hasher, stdin).map_err(SP1CoreProverError::SerializationError)?;
+    hasher.update(&(opts.shard_size as u64).to_le_bytes());
+    hasher.update(&(opts.shard_batch_size as u64).to_le_bytes());
+    for (_, len, _) in sharding_config.lengths() {
+        hasher.update(&(len as u64).to_le_bytes());
+    }
+    hasher.update(format!("{:?}", codec).as_bytes());
+    let run_digest = *hasher.finalize().as_bytes();
+
+    // Load the manifest of a previous run, or execute the program, persisting every checkpoint,
+    // and commit to its shards.
+    let manifest_path = checkpoint_dir.join("manifest.bin");
+    let checkpoint_path = |i: usize| checkpoint_dir.join(format!("checkpoint_{}.bin", i));
+    let mut manifest = if manifest_path.exists() {
+        let manifest: ResumeManifest<SC> = read_bincode(&manifest_path)?;
+        if manifest.run_digest != run_digest {
+            return Err(SP1CoreProverError::ResumeMismatch);
+        }
+        manifest
+    } else {
+        let mut runtime = new_runtime(program.clone(), stdin, &options, &[])?;
+        let mut num_checkpoints = 0;
+        for checkpoint in runtime.checkpoint_iter() {
+            let checkpoint = checkpoint.map_err(SP1CoreProverError::ExecutionError)?;
+            write_atomic_with_codec(
+                &checkpoint_path(num_checkpoints),
+                &(digest, &checkpoint),
+                codec,
+            )?;
+            num_checkpoints += 1;
+        }
+        let public_values = runtime.record.public_values;
+        let mut checkpoints = (0..num_checkpoints)
+            .map(|i| File::open(checkpoint_path(i)).map_err(SP1CoreProverError::IoError))
+            .collect::<Result<Vec<_>, _>>()?;
+        let shard_commitments = commit_checkpoints(
+            &machine,
+            program.clone(),
+            &mut checkpoints,
+            &options,
+            public_values,
+        )?;
+        let manifest = ResumeManifest {
+            run_digest,
+            num_checkpoints,
+            cycles: runtime.state.global_clk,
+            public_values,
+            public_values_stream: std::mem::take(&mut runtime.state.public_values_stream),
+            shard_commitments,
+            proved: Vec::new(),
+        };
+        write_atomic(&manifest_path, &manifest)?;
+        manifest
+    };
+    let public_values = manifest.public_values;
+    let mut challenger = machine.config().challenger();
+    vk.observe_into(&mut challenger);
+    observe_commitments(&machine, &mut challenger, &manifest.shard_commitments);
+
+    // Prove the checkpoints that have not been proven yet.
+    let mut shard_proofs = Vec::<ShardProof<SC>>::new();
+    let mut shard_timings = Vec::new();
+    for i in 0..manifest.num_checkpoints {
+        let proof_path = checkpoint_dir.join(format!("proof_{}.bin", i));
+        if manifest.proved.contains(&i) {
+            let mut checkpoint_proofs: Vec<ShardProof<SC>> = read_bincode(&proof_path)?;
+            shard_proofs.append(&mut checkpoint_proofs);
+            continue;
+        }
+
+        let mut checkpoint_file =
+            File::open(checkpoint_path(i)).map_err(SP1CoreProverError::IoError)?;
+        let checkpoint_shards = trace_and_shard(
+            &machine,
+            program.clone(),
+            &mut checkpoint_file,
+            opts,
+            public_values,
+            &sharding_config,
+            codec,
+        )?;
+        let (mut checkpoint_proofs, mut checkpoint_timings): (Vec<_>, Vec<_>) = checkpoint_shards
+            .iter()
+            .map(|shard| prove_shard::<SC, LocalProver<_, _>>(&machine, &pk, shard, &challenger))
+            .unzip();
+        write_atomic(&proof_path, &checkpoint_proofs)?;
+        manifest.proved.push(i);
+        write_atomic(&manifest_path, &manifest)?;
+        shard_proofs.append(&mut checkpoint_proofs);
+        shard_timings.append(&mut checkpoint_timings);
+    }
+    let proof = MachineProof::<SC> { shard_proofs };
+
+    // Print the summary.
+    let mut stats = ProveStats::new(
+        machine.config(),
+        manifest.cycles,
+        proving_start.elapsed(),
+        &proof,
+    );
+    stats.shard_timings = shard_timings;
+    log_summary(&stats, options.metrics_sink.as_deref());
+
+    Ok((proof, manifest.public_values_stream))
+}
+
+/// Removes the checkpoint files written while proving when it is dropped, unless it is
+/// committed, so that a panic or an early return does not leave stale checkpoints behind.
+#[derive(Debug, Default)]
+pub struct CheckpointGuard {
+    paths: Vec<PathBuf>,
+}
+
+impl CheckpointGuard {
+    pub const fn new() -> Self {
+        Self { paths: Vec::new() }
+    }
+
+    /// Tracks a checkpoint file that has been written.
+    pub fn track(&mut self, path: PathBuf) {
+        self.paths.push(path);
+    }
+
+    /// The tracked checkpoint files, in the order they were tracked.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Keeps the tracked checkpoint files on disk.
+    pub fn commit(mut self) {
+        self.paths.clear();
+    }
+}
+
+impl Drop for CheckpointGuard {
+    fn drop(&mut self) {
+        for path in self.paths.drain(..) {
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => tracing::warn!("failed to remove checkpoint {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+/// A directory holding the shard proofs of each checkpoint of a program that is proven one
+/// checkpoint at a time, such as by a coordinator dispatching checkpoints to workers with
+/// [prove_checkpoint_range_with_challenger].
+///
+/// The shard proofs of checkpoint `i` are written to `part_{i}.bin` as soon as they are saved, so
+/// a coordinator that crashes can reopen the store, load the checkpoints proven so far and only
+/// dispatch the [missing](Self::missing) ones. A `parts.bin` records the
+/// [fingerprint](StarkVerifyingKey::fingerprint) of the verifying key of the program and the
+/// number of checkpoints of the run.
+pub struct PartialProofStore<SC> {
+    dir: PathBuf,
+    num_checkpoints: usize,
+    _config: PhantomData<SC>,
+}
+
+impl<SC: StarkGenericConfig> PartialProofStore<SC>
+where
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    /// Opens the store in `dir` for the program of `vk` with `num_checkpoints` checkpoints,
+    /// creating it if needed. Fails with [SP1CoreProverError::ResumeMismatch] if `dir` holds the
+    /// parts of another program or of a run with a different number of checkpoints.
+    pub fn open(
+        dir: &Path,
+        vk: &StarkVerifyingKey<SC>,
+        num_checkpoints: usize,
+    ) -> Result<Self, SP1CoreProverError>
+    where
+        Dom<SC>: Serialize,
+    {
+        std::fs::create_dir_all(dir).map_err(SP1CoreProverError::IoError)?;
+        let manifest_path = dir.join("parts.bin");
+        let manifest = (vk.fingerprint(), num_checkpoints);
+        if manifest_path.exists() {
+            let recorded: ([u8; 32], usize) = read_bincode(&manifest_path)?;
+            if recorded != manifest {
+                return Err(SP1CoreProverError::ResumeMismatch);
+            }
+        } else {
+            write_atomic(&manifest_path, &manifest)?;
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            num_checkpoints,
+            _config: PhantomData,
+        })
+    }
+
+    pub const fn num_checkpoints(&self) -> usize {
+        self.num_checkpoints
+    }
+
+    fn part_path(&self, checkpoint: usize) -> PathBuf {
+        self.dir.join(format!("part_{}.bin", checkpoint))
+    }
+
+    fn check_checkpoint(&self, checkpoint: usize) -> Result<(), SP1CoreProverError> {
+        if checkpoint >= self.num_checkpoints {
+            return Err(SP1CoreProverError::CheckpointOutOfRange(checkpoint));
+        }
+        Ok(())
+    }
+
+    /// Persists the shard proofs of a checkpoint, replacing any saved before.
+    pub fn save(
+        &self,
+        checkpoint: usize,
+        shard_proofs: &[ShardProof<SC>],
+    ) -> Result<(), SP1CoreProverError> {
+        self.check_checkpoint(checkpoint)?;
+        write_atomic(&self.part_path(checkpoint), &shard_proofs)
+    }
+
+    /// Loads the shard proofs of a checkpoint, if they have been saved.
+    pub fn load(
+        &self,
+        checkpoint: usize,
+    ) -> Result<Option<Vec<ShardProof<SC>>>, SP1CoreProverError> {
+        self.check_checkpoint(checkpoint)?;
+        let path = self.part_path(checkpoint);
+        if !path.exists() {
+            return Ok(None);
+        }
+        read_bincode(&path).map(Some)
+    }
+
+    /// The checkpoints whose shard proofs have been saved, in order.
+    pub fn completed(&self) -> Vec<usize> {
+        (0..self.num_checkpoints)
+            .filter(|&i| self.part_path(i).exists())
+            .collect()
+    }
+
+    /// The checkpoints whose shard proofs have not been saved yet, in order.
+    pub fn missing(&self) -> Vec<usize> {
+        (0..self.num_checkpoints)
+            .filter(|&i| !self.part_path(i).exists())
+            .collect()
+    }
+
+    /// Assembles the saved shard proofs of every checkpoint into a proof of the whole program.
+    ///
+    /// Fails with [SP1CoreProverError::MissingCheckpoints] if any checkpoint has not been saved,
+    /// and with [SP1CoreProverError::InvalidPartialProofs] if the shards of the parts are not the
+    /// contiguous sequence of shards of one program.
+    pub fn finalize(&self) -> Result<MachineProof<SC>, SP1CoreProverError> {
+        let missing = self.missing();
+        if !missing.is_empty() {
+            return Err(SP1CoreProverError::MissingCheckpoints(missing));
+        }
+        let parts = (0..self.num_checkpoints)
+            .map(|i| {
+                let shard_proofs = read_bincode(&self.part_path(i))?;
+                Ok(MachineProof { shard_proofs })
+            })
+            .collect::<Result<Vec<_>, SP1CoreProverError>>()?;
+        MachineProof::merge(parts).map_err(SP1CoreProverError::InvalidPartialProofs)
+    }
+}
+
+/// Executes and shards a program, reporting the trace height of every chip in every shard.
+///
+/// Traces are generated but never committed to, which makes this a cheap way to tune a
+/// [ShardingConfig] before proving.
+pub fn analyze_sharding<SC: StarkGenericConfig>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    sharding_config: &ShardingConfig,
+) -> Result<ShardingReport, SP1CoreProverError>
+where
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+    write_stdin(&mut runtime, stdin);
+    runtime.run().map_err(SP1CoreProverError::ExecutionError)?;
+    let total_cycles = runtime.state.global_clk;
+
+    let machine = RiscvAir::machine(config);
+    let shards =
+        tracing::debug_span!("shard").in_scope(|| machine.shard(runtime.record, sharding_config));
+    let per_shard_chip_heights = shards
+        .iter()
+        .map(|shard| {
+            machine
+                .shard_chips(shard)
+                .map(|chip| {
+                    let trace = chip.generate_trace(shard, &mut ExecutionRecord::default());
+                    (chip.name(), trace.height())
+                })
+                .collect::<BTreeMap<_, _>>()
+        })
+        .collect::<Vec<_>>();
+
+    Ok(ShardingReport {
+        shard_count: shards.len(),
+        per_shard_chip_heights,
+        total_cycles,
+    })
+}
+
+/// The size in bytes of a Merkle digest in the configs of this crate.
+const ESTIMATED_DIGEST_BYTES: usize = 32;
+
+/// Estimates the size in bytes of a proof of a program with the shard layout of `shard_report`,
+/// without proving it.
+///
+/// Each shard is estimated from the widths and heights of its chips and the FRI parameters of
+/// `config`: its commitments, its opened values, and a Merkle opening of every committed batch and
+/// FRI folding round for each query. Length prefixes and public values are not counted. Returns
+/// `None` if `config` does not report the shape of its FRI proofs.
+pub fn estimate_proof_size<SC: StarkGenericConfig>(
+    shard_report: &ShardingReport,
+    config: &SC,
+) -> Option<usize>
+where
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let (log_blowup, num_queries) = config.fri_query_shape()?;
+    let machine = RiscvAir::machine(config.clone());
+    let val_bytes = std::mem::size_of::<SC::Val>();
+    let challenge_bytes = std::mem::size_of::<SC::Challenge>();
+    let ext_degree = <SC::Challenge as AbstractExtensionField<SC::Val>>::D;
+
+    let size = shard_report
+        .per_shard_chip_heights
+        .iter()
+        .map(|heights| {
+            // The widths of the preprocessed, main, permutation and quotient batches, in base
+            // field columns.
+            let mut batch_widths = [0; 4];
+            let mut opened_values = 0;
+            let mut log_max_height = 0;
+            for chip in machine.chips() {
+                let Some(&height) = heights.get(&chip.name()) else {
+                    continue;
+                };
+                let widths = [
+                    chip.preprocessed_width(),
+                    chip.width(),
+                    chip.permutation_width() * ext_degree,
+                    chip.quotient_width() * ext_degree,
+                ];
+                for (batch_width, width) in batch_widths.iter_mut().zip(widths) {
+                    *batch_width += width;
+                }
+                // The traces are opened at two points, the quotient chunks at one.
+                opened_values += 2 * (widths[0] + widths[1] + widths[2]) + widths[3] + 1;
+                log_max_height = log_max_height.max(log2_ceil_usize(height));
+            }
+            let log_lde_height = log_max_height + log_blowup;
+
+            // Every committed batch is opened along a Merkle path of the tallest LDE.
+            let batch_openings = batch_widths
+                .iter()
+                .filter(|&&width| width > 0)
+                .map(|width| width * val_bytes + log_lde_height * ESTIMATED_DIGEST_BYTES)
+                .sum::<usize>();
+            // Every folding round opens a sibling along a Merkle path one level shorter.
+            let fri_openings = (0..log_max_height)
+                .map(|round| {
+                    challenge_bytes + (log_lde_height - round - 1) * ESTIMATED_DIGEST_BYTES
+                })
+                .sum::<usize>();
+            let commitments = (3 + log_max_height) * ESTIMATED_DIGEST_BYTES;
+
+            commitments
+                + opened_values * challenge_bytes
+                + num_queries * (batch_openings + fri_openings)
+                + challenge_bytes
+                + val_bytes
+        })
+        .sum::<usize>();
+    Some(size)
+}
+
+/// Verifies a RISC-V machine proof against a verifying key with a fresh challenger.
+pub fn verify_machine_proof<SC: StarkGenericConfig>(
+    vk: &StarkVerifyingKey<SC>,
+    proof: &MachineProof<SC>,
+    config: SC,
+) -> Result<(), crate::stark::MachineVerificationError<SC>>
+where
+    SC::Challenger: Clone,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let machine = RiscvAir::machine(config);
+    let mut challenger = machine.config().challenger();
+    machine.verify(vk, proof, &mut challenger)
+}
+
+/// Checks that every shard of a RISC-V proof committed to the digests of `public_values`, the
+/// public values of the whole execution.
+///
+/// The public values of each shard are observed separately, so a sharding bug could leave the
+/// shards disagreeing on the digests even though every shard proof verifies.
+pub fn verify_public_values_digest<SC: StarkGenericConfig>(
+    proof: &MachineProof<SC>,
+    public_values: &PublicValues<u32, u32>,
+) -> Result<(), crate::stark::MachineVerificationError<SC>>
+where
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let shard_public_values = proof
+        .shard_proofs
+        .iter()
+        .map(|shard_proof| shard_proof.public_values.clone());
+    match find_public_values_digest_mismatch(shard_public_values, public_values) {
+        Some(shard) => {
+            Err(crate::stark::MachineVerificationError::PublicValuesDigestMismatch(shard))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Reads the public values of a RISC-V proof from its last shard, without executing the program.
+///
+/// The digests are shared by every shard, while the exit code and next program counter are those
+/// of the end of execution. The public values stream itself is not part of the proof, only its
+/// SHA-256 digest, against which a claimed stream can be checked with
+/// [PublicValues::commit_digest_bytes]. Returns `None` for a proof without shards.
+pub fn extract_public_values<SC: StarkGenericConfig>(
+    proof: &MachineProof<SC>,
+    machine: &StarkMachine<SC, RiscvAir<Val<SC>>>,
+) -> Option<PublicValues<Word<Val<SC>>, Val<SC>>>
+where
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let last = proof.shard_proofs.last()?;
+    Some(PublicValues::from_vec(
+        last.public_values[0..machine.num_pv_elts()].to_vec(),
+    ))
+}
+
+/// Returns the position of the first shard, given by its public values, whose committed value
+/// digest or deferred proofs digest differs from those of `public_values`.
+fn find_public_values_digest_mismatch<F: PrimeField32>(
+    shard_public_values: impl IntoIterator<Item = Vec<F>>,
+    public_values: &PublicValues<u32, u32>,
+) -> Option<usize> {
+    let expected = PublicValues::<Word<F>, F>::from_vec(public_values.to_vec());
+    shard_public_values
+        .into_iter()
+        .position(|shard_public_values| {
+            let got = PublicValues::<Word<F>, F>::from_vec(shard_public_values);
+            got.committed_value_digest != expected.committed_value_digest
+                || got.deferred_proofs_digest != expected.deferred_proofs_digest
+        })
+}
+
+/// Checks, before they are committed to, that the shards of an execution carry the digests of
+/// `public_values`, the public values of the whole execution. `first_shard` is the position of the
+/// first of `shards` among all the shards of the execution.
+fn check_shard_public_values<F: PrimeField32>(
+    shards: &[ExecutionRecord],
+    first_shard: usize,
+    public_values: &PublicValues<u32, u32>,
+) -> Result<(), SP1CoreProverError> {
+    let shard_public_values = shards.iter().map(|shard| shard.public_values::<F>());
+    match find_public_values_digest_mismatch(shard_public_values, public_values) {
+        Some(shard) => Err(SP1CoreProverError::PublicValuesDigestMismatch {
+            shard: first_shard + shard,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Runs a program and returns the public values stream.
+pub fn run_test_io(
+    program: Program,
+    inputs: SP1Stdin,
+) -> Result<SP1PublicValues, crate::stark::MachineVerificationError<BabyBearPoseidon2>> {
+    run_test_with_config(program, inputs, BabyBearPoseidon2::new())
+}
+
+/// Runs a program like [run_test_io], also returning the report of its execution, with the number
+/// of events of each kind in [ExecutionReport::event_counts].
+pub fn run_test_io_with_report(
+    program: Program,
+    inputs: SP1Stdin,
+) -> Result<
+    (SP1PublicValues, ExecutionReport),
+    crate::stark::MachineVerificationError<BabyBearPoseidon2>,
+> {
+    let runtime = tracing::info_span!("runtime.run(...)").in_scope(|| {
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.should_report = true;
+        write_stdin(&mut runtime, &inputs);
+        runtime.run().unwrap();
+        runtime
+    });
+    let public_values = SP1PublicValues::from(&runtime.state.public_values_stream);
+    let mut report = runtime.report.clone();
+    report.event_counts = runtime.record.stats();
+    let _ = run_test_core_with_config(runtime, BabyBearPoseidon2::new())?;
+    Ok((public_values, report))
+}
+
+/// Runs a program like [run_test_io], proving and verifying it with `config`.
+///
+/// Tests that do not need secure proofs can pass [BabyBearPoseidon2::fast_test] to skip
+/// proof-of-work grinding.
+pub fn run_test_with_config<SC>(
+    program: Program,
+    stdin: SP1Stdin,
+    config: SC,
+) -> Result<SP1PublicValues, crate::stark::MachineVerificationError<SC>>
+where
+    SC: StarkGenericConfig,
+    SC::Val: p3_field::PrimeField32,
+    SC::Challenger: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    OpeningProof<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+{
+    let runtime = tracing::info_span!("runtime.run(...)").in_scope(|| {
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        write_stdin(&mut runtime, &stdin);
+        runtime.run().unwrap();
+        runtime
+    });
+    let public_values = SP1PublicValues::from(&runtime.state.public_values_stream);
+    let _ = run_test_core_with_config(runtime, config)?;
+    Ok(public_values)
+}
+
+/// Runs a program on raw input buffers and returns the public values stream.
+///
+/// Each buffer is written to the runtime as is. Deferred proofs cannot be passed this way.
+pub fn run_test_io_raw(
+    program: Program,
+    buffers: Vec<Vec<u8>>,
+) -> Result<SP1PublicValues, crate::stark::MachineVerificationError<BabyBearPoseidon2>> {
+    let runtime = tracing::info_span!("runtime.run(...)").in_scope(|| {
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.write_vecs(&buffers);
+        runtime.run().unwrap();
+        runtime
+    });
+    let public_values = SP1PublicValues::from(&runtime.state.public_values_stream);
+    let _ = run_test_core(runtime)?;
+    Ok(public_values)
+}
+
+pub fn run_test(
+    program: Program,
+) -> Result<
+    crate::stark::MachineProof<BabyBearPoseidon2>,
+    crate::stark::MachineVerificationError<BabyBearPoseidon2>,
+> {
+    let runtime = tracing::info_span!("runtime.run(...)").in_scope(|| {
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+        runtime
+    });
+    run_test_core(runtime)
+}
+
+pub fn run_test_core(
+    runtime: Runtime,
+) -> Result<
+    crate::stark::MachineProof<BabyBearPoseidon2>,
+    crate::stark::MachineVerificationError<BabyBearPoseidon2>,
+> {
+    run_test_core_with_config(runtime, BabyBearPoseidon2::new())
+}
+
+/// Proves and verifies the record of an executed runtime like [run_test_core], with `config`.
+pub fn run_test_core_with_config<SC>(
+    runtime: Runtime,
+    config: SC,
+) -> Result<crate::stark::MachineProof<SC>, crate::stark::MachineVerificationError<SC>>
+where
+    SC: StarkGenericConfig,
+    SC::Val: p3_field::PrimeField32,
+    SC::Challenger: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    OpeningProof<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+{
+    let machine = RiscvAir::machine(config);
+    let (pk, vk) = machine.setup(runtime.program.as_ref());
+
+    let record = runtime.record;
+    let public_values = record.public_values;
+    let proof = run_test_machine(record, machine, pk, vk)?;
+    verify_public_values_digest(&proof, &public_values)?;
+    Ok(proof)
+}
+
+pub fn run_test_machine<SC, A>(
+    record: A::Record,
+    machine: StarkMachine<SC, A>,
+    pk: StarkProvingKey<SC>,
+    vk: StarkVerifyingKey<SC>,
+) -> Result<crate::stark::MachineProof<SC>, crate::stark::MachineVerificationError<SC>>
+where
+    A: MachineAir<SC::Val>
+        + for<'a> Air<ProverConstraintFolder<'a, SC>>
+        + Air<InteractionBuilder<Val<SC>>>
+        + for<'a> Air<VerifierConstraintFolder<'a, SC>>
+        + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+    SC: StarkGenericConfig,
+    SC::Val: p3_field::PrimeField32,
+    SC::Challenger: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    OpeningProof<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+{
+    run_test_machine_with_stats(record, machine, pk, vk).map(|(proof, _)| proof)
+}
+
+/// Proves and verifies a record like [run_test_machine], checking that proving is reproducible
+/// when `determinism` is [Determinism::Strict].
+pub fn run_test_machine_with_determinism<SC, A>(
+    record: A::Record,
+    machine: StarkMachine<SC, A>,
+    pk: StarkProvingKey<SC>,
+    vk: StarkVerifyingKey<SC>,
+    determinism: Determinism,
+) -> Result<crate::stark::MachineProof<SC>, crate::stark::MachineVerificationError<SC>>
+where
+    A: MachineAir<SC::Val>
+        + Sync
+        + for<'a> Air<ProverConstraintFolder<'a, SC>>
+        + Air<InteractionBuilder<Val<SC>>>
+        + for<'a> Air<VerifierConstraintFolder<'a, SC>>
+        + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+    SC: StarkGenericConfig,
+    SC::Val: p3_field::PrimeField32,
+    SC::Challenger: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    OpeningProof<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+{
+    if determinism == Determinism::Relaxed {
+        return run_test_machine(record, machine, pk, vk);
+    }
+
+    let proof = prove_strict(|| {
+        let mut challenger = machine.config().challenger();
+        Ok::<_, Infallible>(machine.prove::<LocalProver<SC, A>>(
+            &pk,
+            record.clone(),
+            &mut challenger,
+            SP1CoreOpts::default(),
+        ))
+    })
+    .unwrap_or_else(|e| match e {});
+
+    let mut challenger = machine.config().challenger();
+    machine.verify(&vk, &proof, &mut challenger)?;
+
+    Ok(proof)
+}
+
+/// Proves and verifies a record like [run_test_machine], also returning the proof statistics.
+pub fn run_test_machine_with_stats<SC, A>(
+    record: A::Record,
+    machine: StarkMachine<SC, A>,
+    pk: StarkProvingKey<SC>,
+    vk: StarkVerifyingKey<SC>,
+) -> Result<(crate::stark::MachineProof<SC>, ProveStats), crate::stark::MachineVerificationError<SC>>
+where
+    A: MachineAir<SC::Val>
+        + for<'a> Air<ProverConstraintFolder<'a, SC>>
+        + Air<InteractionBuilder<Val<SC>>>
+        + for<'a> Air<VerifierConstraintFolder<'a, SC>>
+        + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+    SC: StarkGenericConfig,
+    SC::Val: p3_field::PrimeField32,
+    SC::Challenger: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    OpeningProof<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+{
+    let (proof, stats) = prove_test_machine(record, &machine, &pk);
+
+    let mut challenger = machine.config().challenger();
+    machine.verify(&vk, &proof, &mut challenger)?;
+
+    log_summary(&stats, None);
+
+    Ok((proof, stats))
+}
+
+/// Proves a record like [run_test_machine] without verifying the proof, for tests that only check
+/// that proving succeeds.
+pub fn run_test_machine_no_verify<SC, A>(
+    record: A::Record,
+    machine: StarkMachine<SC, A>,
+    pk: StarkProvingKey<SC>,
+) -> crate::stark::MachineProof<SC>
+where
+    A: MachineAir<SC::Val>
+        + for<'a> Air<ProverConstraintFolder<'a, SC>>
+        + Air<InteractionBuilder<Val<SC>>>
+        + for<'a> Air<VerifierConstraintFolder<'a, SC>>
+        + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+    SC: StarkGenericConfig,
+    SC::Val: p3_field::PrimeField32,
+    SC::Challenger: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    OpeningProof<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+{
+    let (proof, stats) = prove_test_machine(record, &machine, &pk);
+    log_summary(&stats, None);
+    proof
+}
+
+/// Proves a record for the `run_test_machine` helpers, checking its constraints first when the
+/// `debug` feature is enabled.
+#[allow(unused_variables)]
+fn prove_test_machine<SC, A>(
+    record: A::Record,
+    machine: &StarkMachine<SC, A>,
+    pk: &StarkProvingKey<SC>,
+) -> (crate::stark::MachineProof<SC>, ProveStats)
+where
+    A: MachineAir<SC::Val>
+        + for<'a> Air<ProverConstraintFolder<'a, SC>>
+        + Air<InteractionBuilder<Val<SC>>>
+        + for<'a> Air<VerifierConstraintFolder<'a, SC>>
+        + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+    SC: StarkGenericConfig,
+    SC::Val: p3_field::PrimeField32,
+    SC::Challenger: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    OpeningProof<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+{
+    #[cfg(feature = "debug")]
+    {
+        let mut challenger_clone = machine.config().challenger();
+        let record_clone = record.clone();
+        machine.debug_constraints(pk, record_clone, &mut challenger_clone);
+    }
+    let stats = record.stats().clone();
+    let cycles = stats.get("cpu_events").unwrap();
+
+    let start = Instant::now();
+    let mut challenger = machine.config().challenger();
+    let proof =
+        machine.prove::<LocalProver<SC, A>>(pk, record, &mut challenger, SP1CoreOpts::default());
+    let time = start.elapsed();
+
+    let stats = ProveStats::new(machine.config(), *cycles as u64, time, &proof);
+    (proof, stats)
+}
+
+/// Proves and verifies a record like [run_test_machine] with a config whose challenger is a
+/// [RecordingChallenger]. When verification fails, the prover's and verifier's transcripts are
+/// logged side by side from the first call at which they diverge, and the failure is reported as
+/// [crate::stark::MachineVerificationError::TranscriptDivergence] if they do.
+///
+/// Only the calls made before the shard proofs are recorded, since each shard is proven and
+/// verified against its own copy of the challenger.
+pub fn run_test_machine_with_transcripts<SC, A, C>(
+    record: A::Record,
+    machine: StarkMachine<SC, A>,
+    pk: StarkProvingKey<SC>,
+    vk: StarkVerifyingKey<SC>,
+) -> Result<crate::stark::MachineProof<SC>, crate::stark::MachineVerificationError<SC>>
+where
+    A: MachineAir<SC::Val>
+        + for<'a> Air<ProverConstraintFolder<'a, SC>>
+        + Air<InteractionBuilder<Val<SC>>>
+        + for<'a> Air<VerifierConstraintFolder<'a, SC>>
+        + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
+    SC: StarkGenericConfig<Challenger = RecordingChallenger<C>>,
+    SC::Val: p3_field::PrimeField32,
+    C: Clone,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    OpeningProof<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+{
+    let mut prover_challenger = machine.config().challenger();
+    let proof = machine.prove::<LocalProver<SC, A>>(
+        &pk,
+        record,
+        &mut prover_challenger,
+        SP1CoreOpts::default(),
+    );
+
+    let mut verifier_challenger = machine.config().challenger();
+    let result = machine.verify(&vk, &proof, &mut verifier_challenger);
+    if result.is_err() {
+        if let Some(event) = log_transcript_divergence(
+            prover_challenger.transcript(),
+            verifier_challenger.transcript(),
+        ) {
+            return Err(crate::stark::MachineVerificationError::TranscriptDivergence(event));
+        }
+    }
+    result.map(|_| proof)
+}
+
+/// Runs `prove` twice on a single-threaded thread pool and asserts that both outputs serialize to
+/// the same bytes.
+fn prove_strict<T, E, P>(prove: P) -> Result<T, E>
+where
+    T: Serialize + Send,
+    E: Send,
+    P: Fn() -> Result<T, E> + Send + Sync,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .expect("failed to build single-threaded thread pool");
+    pool.install(|| {
+        let first = prove()?;
+        let second = prove()?;
+        assert_eq!(
+            bincode::serialize(&first).unwrap(),
+            bincode::serialize(&second).unwrap(),
+            "proving is not deterministic"
+        );
+        Ok(first)
+    })
+}
+
+/// Creates a runtime for `program` with `stdin` written to it and `preload` loaded into its memory.
+fn new_runtime(
+    program: Program,
+    stdin: &SP1Stdin,
+    options: &ProveOptions,
+    preload: &[(u32, Vec<u8>)],
+) -> Result<Runtime, SP1CoreProverError> {
+    if let Some(max_bytes) = options.max_stdin_bytes {
+        stdin
+            .check_len(max_bytes)
+            .map_err(SP1CoreProverError::StdinTooLarge)?;
+    }
+    let mut runtime = Runtime::new(program, options.core_opts());
+    runtime.max_cycles = options.max_cycles;
+    write_stdin(&mut runtime, stdin);
+    for (addr, bytes) in preload {
+        runtime
+            .preload_memory(*addr, bytes)
+            .map_err(SP1CoreProverError::InvalidMemoryPreload)?;
+    }
+    Ok(runtime)
+}
+
+/// Executes a program from scratch without emitting events and returns its public values stream.
+fn execute_public_values_stream(
+    program: Program,
+    stdin: &SP1Stdin,
+    options: &ProveOptions,
+    preload: &[(u32, Vec<u8>)],
+) -> Result<Vec<u8>, SP1CoreProverError> {
+    let mut runtime = new_runtime(program, stdin, options, preload)?;
+    runtime
+        .run_untraced()
+        .map_err(SP1CoreProverError::ExecutionError)?;
+    Ok(runtime.state.public_values_stream)
+}
+
+/// Checks that the public values stream of the execution being proven matches the one of the
+/// reference execution of [ProveOptions::verify_reexecution], if there is one.
+fn check_reexecution(
+    reference_stream: Option<&[u8]>,
+    stream: &[u8],
+) -> Result<(), SP1CoreProverError> {
+    match reference_stream {
+        Some(reference) if reference != stream => {
+            Err(SP1CoreProverError::NonDeterministicExecution)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes the inputs and deferred proofs of `stdin` to the runtime.
+pub(crate) fn write_stdin(runtime: &mut Runtime, stdin: &SP1Stdin) {
+    runtime.write_vecs(&stdin.buffer);
+    runtime.write_proofs(&stdin.proofs);
+}
+
+/// Saves a checkpoint to a temp file, rewound so that it can be read back.
+///
+/// The checkpoint is tagged with the digest of the program that produced it, which is checked
+/// before the checkpoint is re-executed.
+pub fn save_checkpoint(
+    program: &Program,
+    checkpoint: &ExecutionState,
+) -> Result<File, SP1CoreProverError> {
+    save_checkpoint_with_codec(program, checkpoint, CheckpointCodec::Plain)
+}
+
+/// Saves a checkpoint like [save_checkpoint], encoded with `codec`.
+pub fn save_checkpoint_with_codec(
+    program: &Program,
+    checkpoint: &ExecutionState,
+    codec: CheckpointCodec,
+) -> Result<File, SP1CoreProverError> {
+    let mut tempfile = tempfile::tempfile().map_err(SP1CoreProverError::IoError)?;
+    let mut writer = std::io::BufWriter::new(&mut tempfile);
+    codec
+        .encode_into(&mut writer, &(program_digest(program)?, checkpoint))
+        .map_err(SP1CoreProverError::SerializationError)?;
+    writer.flush().map_err(SP1CoreProverError::IoError)?;
+    drop(writer);
+    reset_seek(&mut tempfile)?;
+    Ok(tempfile)
+}
+
+/// A digest identifying a program, used to tag its checkpoints.
+pub(crate) fn program_digest(program: &Program) -> Result<[u8; 32], SP1CoreProverError> {
+    let bytes = bincode::serialize(program).map_err(SP1CoreProverError::SerializationError)?;
+    Ok(*blake3::hash(&bytes).as_bytes())
+}
+
+/// Writes a value to `path` through a temporary file, so that `path` is never left partially
+/// written.
+fn write_atomic<T: Serialize>(path: &Path, value: &T) -> Result<(), SP1CoreProverError> {
+    write_atomic_with_codec(path, value, CheckpointCodec::Plain)
+}
+
+/// Writes a value like [write_atomic], encoded with `codec`.
+fn write_atomic_with_codec<T: Serialize>(
+    path: &Path,
+    value: &T,
+    codec: CheckpointCodec,
+) -> Result<(), SP1CoreProverError> {
+    let tmp_path = path.with_extension("tmp");
+    let mut writer =
+        std::io::BufWriter::new(File::create(&tmp_path).map_err(SP1CoreProverError::IoError)?);
+    codec
+        .encode_into(&mut writer, value)
+        .map_err(SP1CoreProverError::SerializationError)?;
+    writer.flush().map_err(SP1CoreProverError::IoError)?;
+    drop(writer);
+    std::fs::rename(&tmp_path, path).map_err(SP1CoreProverError::IoError)
+}
+
+/// Reads a value written by [write_atomic].
+fn read_bincode<T: DeserializeOwned>(path: &Path) -> Result<T, SP1CoreProverError> {
+    let file = File::open(path).map_err(SP1CoreProverError::IoError)?;
+    bincode::deserialize_from(std::io::BufReader::new(file))
+        .map_err(SP1CoreProverError::SerializationError)
+}
+
+/// Re-executes a checkpoint to generate its events and splits them into shards.
+fn trace_and_shard<SC: StarkGenericConfig<Val = F>, F: PrimeField32>(
+    machine: &StarkMachine<SC, RiscvAir<F>>,
+    program: Program,
+    file: &mut File,
+    opts: SP1CoreOpts,
+    public_values: PublicValues<u32, u32>,
+    sharding_config: &ShardingConfig,
+    codec: CheckpointCodec,
+) -> Result<Vec<ExecutionRecord>, SP1CoreProverError> {
+    let (mut record, done) = trace_checkpoint(program, file, opts, codec)?;
+
+    // The checkpoint that ends the program commits to the digests itself, which must agree with
+    // the execution that saved the checkpoints. Every shard then carries the final public values.
+    let traced = &record.public_values;
+    if done
+        && (traced.committed_value_digest != public_values.committed_value_digest
+            || traced.deferred_proofs_digest != public_values.deferred_proofs_digest)
+    {
+        return Err(SP1CoreProverError::NonDeterministicExecution);
+    }
+    record.public_values = public_values;
+    reset_seek(file)?;
+    let shards = tracing::debug_span!("shard").in_scope(|| machine.shard(record, sharding_config));
+
+    // Every event takes at least one row, so a shard whose largest event count does not fit in
+    // the PCS would fail deep inside the commitment.
+    if let Some(max_log_height) = machine.config().max_log_trace_height() {
+        for shard in shards.iter() {
+            let max_events = shard.stats().into_values().max().unwrap_or(0);
+            let log_height = log2_ceil_usize(max_events);
+            if log_height > max_log_height {
+                return Err(SP1CoreProverError::ShardTooLarge {
+                    shard: shard.index(),
+                    log_height,
+                    max_log_height,
+                });
+            }
+        }
+    }
+
+    Ok(shards)
+}
+
+/// Proves a program by proving its checkpoints on the workers of `pool`.
+///
+/// The program is executed and every shard is committed on the calling thread, as in
+/// [observe_checkpoints]. Each worker then proves one checkpoint at a time against its own copy
+/// of the resulting challenger. When the pool has an [AssignmentStrategy](super::AssignmentStrategy),
+/// each worker proves the checkpoints it assigns. The shard proofs are returned in checkpoint
+/// order.
+///
+/// Every shard proof a worker returns is checked with [StarkMachine::verify_shard]. A checkpoint
+/// with an invalid proof is requeued on another worker as described in
+/// [LocalWorkerPool::run_verified], and proving fails with [SP1CoreProverError::ShardProofInvalid]
+/// once its retries are exhausted.
+///
+/// `options` are applied as by [prove_with_options], and the summary is logged and emitted to
+/// its metrics sink the same way. Checkpoints are always saved, whatever the batch size, and
+/// `determinism`, `reuse_single_batch`, `parallel_shard_proving`, `per_shard_timeout` and
+/// `strategy` have no effect, since the pool decides how checkpoints are proven.
+pub fn prove_with_worker_pool<SC: StarkGenericConfig + Send + Sync>(
+    program: Program,
+    stdin: &SP1Stdin,
+    config: SC,
+    options: ProveOptions,
+    pool: &LocalWorkerPool,
+) -> Result<(MachineProof<SC>, Vec<u8>), SP1CoreProverError>
+where
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    options
+        .sharding_config()
+        .validate(&config)
+        .map_err(SP1CoreProverError::InvalidShardingConfig)?;
+    let proving_start = Instant::now();
+
+    // Execute the program once without events to compare the public values of the execution that
+    // is proven against.
+    let reference_stream = match options.verify_reexecution {
+        true => Some(execute_public_values_stream(
+            program.clone(),
+            stdin,
+            &options,
+            &[],
+        )?),
+        false => None,
+    };
+
+    let SavedCheckpoints {
+        files: mut checkpoints,
+        public_values,
+        public_values_stream,
+        cycles,
+        bytes: checkpoint_bytes,
+        uncompressed_bytes: checkpoint_uncompressed_bytes,
+    } = count_checkpoints(&program, stdin, &options)?;
+    check_public_values(&options, &public_values)?;
+    check_reexecution(reference_stream.as_deref(), &public_values_stream)?;
+    let memory_after_execution = sample_memory();
+
+    let machine = RiscvAir::machine(config).with_transcript_domain(options.transcript_domain);
+    let (pk, vk) = machine.setup(&program);
+
+    let shard_commitments = commit_checkpoints(
+        &machine,
+        program.clone(),
+        &mut checkpoints,
+        &options,
+        public_values,
+    )?;
+    let mut challenger = machine.config().challenger();
+    vk.observe_into(&mut challenger);
+    observe_commitments(&machine, &mut challenger, &shard_commitments);
+    let memory_after_commit = sample_memory();
+
+    let jobs = checkpoints
+        .into_iter()
+        .map(|checkpoint_file| (checkpoint_file, challenger.clone()))
+        .collect();
+    let run = pool.run_verified(
+        jobs,
+        |(checkpoint_file, challenger)| {
+            // A retried checkpoint is read again from the start of its file.
+            let mut checkpoint_file = checkpoint_file
+                .try_clone()
+                .map_err(SP1CoreProverError::IoError)?;
+            reset_seek(&mut checkpoint_file)?;
+            prove_checkpoint_file::<SC, LocalProver<_, _>>(
+                &machine,
+                &pk,
+                program.clone(),
+                &mut checkpoint_file,
+                &options,
+                public_values,
+                challenger,
+            )
+        },
+        |(_, challenger), result| {
+            // Errors come from tracing the checkpoint, which no other worker would do differently.
+            let Ok(shard_proofs) = result else {
+                return Ok(());
+            };
+            shard_proofs.iter().try_for_each(|shard_proof| {
+                machine
+                    .verify_shard(&vk, shard_proof, challenger)
+                    .map_err(|e| SP1CoreProverError::ShardProofInvalid {
+                        shard: shard_proof.shard_index(),
+                        error: format!("{:?}", e),
+                    })
+            })
+        },
+    );
+    if !run.blacklisted_workers.is_empty() {
+        tracing::warn!(
+            "workers {:?} returned invalid shard proofs",
+            run.blacklisted_workers
+        );
+    }
+
+    let mut shard_proofs = Vec::new();
+    for checkpoint_proofs in run.results {
+        let checkpoint_proofs = checkpoint_proofs.map_err(|e| match e {
+            VerifiedJobError::Rejected(e) => e,
+            VerifiedJobError::NoWorkers => SP1CoreProverError::NoWorkersLeft,
+        })?;
+        shard_proofs.extend(checkpoint_proofs?);
+    }
+    let proof = MachineProof::<SC> { shard_proofs };
+
+    // Print the summary.
+    let mut stats = ProveStats::new(machine.config(), cycles, proving_start.elapsed(), &proof);
+    stats.checkpoint_bytes = checkpoint_bytes;
+    stats.checkpoint_uncompressed_bytes = checkpoint_uncompressed_bytes;
+    stats.memory.after_execution = memory_after_execution;
+    stats.memory.after_commit = memory_after_commit;
+    log_summary(&stats, options.metrics_sink.as_deref());
+
+    Ok((proof, public_values_stream))
+}
 
-    let record = runtime.record;
-    run_test_machine(record, machine, pk, vk)
+/// Proves a shard on a detached thread, returning a receiver for its proof and timing.
+///
+/// The thread owns everything it needs, so the caller can stop waiting for it at any time.
+fn spawn_shard_prover<SC>(
+    machine: &Arc<StarkMachine<SC, RiscvAir<SC::Val>>>,
+    keys: &Arc<SetupKeys<SC>>,
+    shard: ExecutionRecord,
+    challenger: &SC::Challenger,
+) -> mpsc::Receiver<(ShardProof<SC>, ShardTiming)>
+where
+    SC: StarkGenericConfig + Send + Sync + 'static,
+    SC::Challenger: Clone + Send,
+    OpeningProof<SC>: Send + Sync,
+    Com<SC>: Send + Sync,
+    PcsProverData<SC>: Send + Sync,
+    ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
+{
+    let (machine, keys, challenger) = (machine.clone(), keys.clone(), challenger.clone());
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let result = prove_shard::<SC, LocalProver<_, _>>(&machine, &keys.0, &shard, &challenger);
+        // The receiver is gone if the caller stopped waiting for this shard.
+        let _ = sender.send(result);
+    });
+    receiver
 }
 
-#[allow(unused_variables)]
-pub fn run_test_machine<SC, A>(
-    record: A::Record,
-    machine: StarkMachine<SC, A>,
-    pk: StarkProvingKey<SC>,
-    vk: StarkVerifyingKey<SC>,
-) -> Result<crate::stark::MachineProof<SC>, crate::stark::MachineVerificationError<SC>>
+/// Proves a shard with `P` against its own copy of a challenger that has observed every
+/// commitment.
+#[instrument("prove_shard", level = "info", skip_all, fields(shard_index = shard.index()))]
+fn prove_shard<SC, P>(
+    machine: &StarkMachine<SC, RiscvAir<SC::Val>>,
+    pk: &StarkProvingKey<SC>,
+    shard: &ExecutionRecord,
+    challenger: &SC::Challenger,
+) -> (ShardProof<SC>, ShardTiming)
 where
-    A: MachineAir<SC::Val>
-        + for<'a> Air<ProverConstraintFolder<'a, SC>>
-        + Air<InteractionBuilder<Val<SC>>>
-        + for<'a> Air<VerifierConstraintFolder<'a, SC>>
-        + for<'a> Air<DebugConstraintBuilder<'a, Val<SC>, SC::Challenge>>,
-    SC: StarkGenericConfig,
-    SC::Val: p3_field::PrimeField32,
+    SC: StarkGenericConfig + Send + Sync,
+    P: ShardProver<SC, RiscvAir<SC::Val>>,
     SC::Challenger: Clone,
+    OpeningProof<SC>: Send + Sync,
     Com<SC>: Send + Sync,
     PcsProverData<SC>: Send + Sync,
-    OpeningProof<SC>: Send + Sync,
     ShardMainData<SC>: Serialize + DeserializeOwned,
+    <SC as StarkGenericConfig>::Val: PrimeField32,
 {
-    #[cfg(feature = "debug")]
-    {
-        let mut challenger_clone = machine.config().challenger();
-        let record_clone = record.clone();
-        machine.debug_constraints(&pk, record_clone, &mut challenger_clone);
-    }
-    let stats = record.stats().clone();
-    let cycles = stats.get("cpu_events").unwrap();
+    let config = machine.config();
+    let commit_start = Instant::now();
+    let shard_data = P::commit_main(config, machine, shard, shard.index() as usize);
+    let commit_ms = commit_start.elapsed().as_millis();
+    let chip_heights = shard_data
+        .chip_ordering
+        .iter()
+        .map(|(name, &i)| (name.clone(), shard_data.traces[i].height()))
+        .collect();
 
-    let start = Instant::now();
-    let mut challenger = machine.config().challenger();
-    let proof =
-        machine.prove::<LocalProver<SC, A>>(&pk, record, &mut challenger, SP1CoreOpts::default());
-    let time = start.elapsed().as_millis();
-    let nb_bytes = bincode::serialize(&proof).unwrap().len();
+    let chip_ordering = shard_data.chip_ordering.clone();
+    let ordered_chips = machine
+        .shard_chips_ordered(&chip_ordering)
+        .collect::<Vec<_>>()
+        .to_vec();
+    let prove_start = Instant::now();
+    let proof = P::prove_shard(
+        config,
+        pk,
+        &ordered_chips,
+        shard_data,
+        &mut challenger.clone(),
+    );
+    let timing = ShardTiming {
+        index: shard.index() as usize,
+        chip_heights,
+        commit_ms,
+        prove_ms: prove_start.elapsed().as_millis(),
+    };
+    tracing::debug!(
+        "shard {} timing: commit={}ms, prove={}ms",
+        timing.index,
+        timing.commit_ms,
+        timing.prove_ms
+    );
 
-    let mut challenger = machine.config().challenger();
-    machine.verify(&vk, &proof, &mut challenger)?;
+    (proof, timing)
+}
+
+/// Checks the public values produced by execution against [ProveOptions::expected_public_values].
+///
+/// Only the digests are compared, since the other values are set per shard.
+fn check_public_values(
+    options: &ProveOptions,
+    got: &PublicValues<u32, u32>,
+) -> Result<(), SP1CoreProverError> {
+    match options.expected_public_values {
+        Some(expected)
+            if expected.committed_value_digest != got.committed_value_digest
+                || expected.deferred_proofs_digest != got.deferred_proofs_digest =>
+        {
+            Err(SP1CoreProverError::PublicValuesMismatch {
+                expected,
+                got: *got,
+            })
+        }
+        _ => Ok(()),
+    }
+}
 
+/// Logs the cycle count, proving time, throughput and proof size of a proof, and emits them to
+/// `metrics_sink` if there is one.
+fn log_summary(stats: &ProveStats, metrics_sink: Option<&dyn MetricsSink>) {
+    let security = stats
+        .security_bits
+        .map_or_else(|| "unknown".to_string(), |bits| bits.to_string());
     tracing::info!(
-        "summary: cycles={}, e2e={}, khz={:.2}, proofSize={}",
-        cycles,
-        time,
-        (*cycles as f64 / time as f64),
-        Size::from_bytes(nb_bytes),
+        "summary: cycles={}, e2e={}, khz={:.2}, proofSize={}, securityBits={}",
+        stats.cycles,
+        stats.prove_time_ms,
+        stats.khz(),
+        Size::from_bytes(stats.proof_bytes),
+        security,
     );
-
-    Ok(proof)
+    let phases = [
+        ("execution", stats.memory.after_execution),
+        ("commit", stats.memory.after_commit),
+        ("prove", stats.memory.after_prove),
+    ];
+    for (phase, usage) in phases {
+        if let Some(usage) = usage {
+            tracing::debug!(
+                "memory after {}: rss={}, peakRss={}",
+                phase,
+                Size::from_bytes(usage.rss_bytes),
+                Size::from_bytes(usage.peak_rss_bytes),
+            );
+        }
+    }
+    if let Some(sink) = metrics_sink {
+        sink.emit(stats);
+    }
 }
 
-fn trace_checkpoint(program: Program, file: &File, opts: SP1CoreOpts) -> ExecutionRecord {
-    let mut reader = std::io::BufReader::new(file);
-    let state = bincode::deserialize_from(&mut reader).expect("failed to deserialize state");
+/// Re-executes a checkpoint, returning its events and whether the program ended within it.
+fn trace_checkpoint(
+    program: Program,
+    file: &File,
+    opts: SP1CoreOpts,
+    codec: CheckpointCodec,
+) -> Result<(ExecutionRecord, bool), SP1CoreProverError> {
+    let reader = std::io::BufReader::new(file);
+    let (digest, state): ([u8; 32], ExecutionState) = codec
+        .decode_from(reader)
+        .map_err(SP1CoreProverError::SerializationError)?;
+    if digest != program_digest(&program)? {
+        return Err(SP1CoreProverError::ProgramMismatch);
+    }
     let mut runtime = Runtime::recover(program.clone(), state, opts);
-    let (events, _) =
-        tracing::debug_span!("runtime.trace").in_scope(|| runtime.execute_record().unwrap());
-    events
+    tracing::debug_span!("runtime.trace")
+        .in_scope(|| runtime.execute_record())
+        .map_err(SP1CoreProverError::ExecutionError)
 }
 
-fn reset_seek(file: &mut File) {
+fn reset_seek(file: &mut File) -> Result<(), SP1CoreProverError> {
     file.seek(std::io::SeekFrom::Start(0))
-        .expect("failed to seek to start of tempfile");
+        .map_err(SP1CoreProverError::IoError)?;
+    Ok(())
 }
 
 #[cfg(debug_assertions)]
@@ -404,6 +3025,57 @@ where
     p3_uni_stark::verify(&UniConfig(config.clone()), air, challenger, proof, &vec![])
 }
 
+/// Proves and verifies a single chip in isolation over a synthetic execution record.
+///
+/// The trace is generated from `record` alone, so only the events consumed by `chip` need to be
+/// populated. Interactions with other chips are not checked and chips with preprocessed columns
+/// are not supported.
+#[cfg(debug_assertions)]
+#[cfg(not(doctest))]
+pub fn prove_single_chip<SC, A>(
+    chip: &A,
+    record: &A::Record,
+    config: &SC,
+) -> Result<Proof<UniConfig<SC>>, p3_uni_stark::VerificationError>
+where
+    SC: StarkGenericConfig,
+    A: MachineAir<SC::Val>
+        + Air<p3_uni_stark::SymbolicAirBuilder<SC::Val>>
+        + for<'a> Air<p3_uni_stark::ProverConstraintFolder<'a, UniConfig<SC>>>
+        + for<'a> Air<p3_uni_stark::VerifierConstraintFolder<'a, UniConfig<SC>>>
+        + for<'a> Air<p3_uni_stark::DebugConstraintBuilder<'a, SC::Val>>,
+{
+    let trace = chip.generate_trace(record, &mut A::Record::default());
+    let mut challenger = config.challenger();
+    let proof = uni_stark_prove(config, chip, &mut challenger, trace);
+
+    let mut challenger = config.challenger();
+    uni_stark_verify(config, chip, &mut challenger, &proof)?;
+    Ok(proof)
+}
+
+#[cfg(not(debug_assertions))]
+pub fn prove_single_chip<SC, A>(
+    chip: &A,
+    record: &A::Record,
+    config: &SC,
+) -> Result<Proof<UniConfig<SC>>, p3_uni_stark::VerificationError>
+where
+    SC: StarkGenericConfig,
+    A: MachineAir<SC::Val>
+        + Air<p3_uni_stark::SymbolicAirBuilder<SC::Val>>
+        + for<'a> Air<p3_uni_stark::ProverConstraintFolder<'a, UniConfig<SC>>>
+        + for<'a> Air<p3_uni_stark::VerifierConstraintFolder<'a, UniConfig<SC>>>,
+{
+    let trace = chip.generate_trace(record, &mut A::Record::default());
+    let mut challenger = config.challenger();
+    let proof = uni_stark_prove(config, chip, &mut challenger, trace);
+
+    let mut challenger = config.challenger();
+    uni_stark_verify(config, chip, &mut challenger, &proof)?;
+    Ok(proof)
+}
+
 pub use baby_bear_keccak::BabyBearKeccak;
 pub use baby_bear_poseidon2::BabyBearPoseidon2;
 use p3_air::Air;
@@ -412,10 +3084,12 @@ use p3_uni_stark::Proof;
 
 pub mod baby_bear_poseidon2 {
 
+    use std::sync::OnceLock;
+
     use p3_baby_bear::{BabyBear, DiffusionMatrixBabyBear};
     use p3_challenger::DuplexChallenger;
     use p3_commit::ExtensionMmcs;
-    use p3_dft::Radix2DitParallel;
+    use p3_dft::{Radix2DitParallel, TwoAdicSubgroupDft};
     use p3_field::{extension::BinomialExtensionField, Field};
     use p3_fri::{FriConfig, TwoAdicFriPcs};
     use p3_merkle_tree::FieldMerkleTreeMmcs;
@@ -424,8 +3098,9 @@ pub mod baby_bear_poseidon2 {
     use p3_symmetric::{PaddingFreeSponge, TruncatedPermutation};
     use serde::{Deserialize, Serialize};
     use sp1_primitives::RC_16_30;
+    use thiserror::Error;
 
-    use crate::stark::StarkGenericConfig;
+    use crate::stark::{ConfigCapabilities, StarkGenericConfig};
 
     pub type Val = BabyBear;
     pub type Challenge = BinomialExtensionField<Val, 4>;
@@ -443,114 +3118,366 @@ pub mod baby_bear_poseidon2 {
     pub type ChallengeMmcs = ExtensionMmcs<Val, Challenge, ValMmcs>;
     pub type Dft = Radix2DitParallel;
     pub type Challenger = DuplexChallenger<Val, Perm, 16, 8>;
-    type Pcs = TwoAdicFriPcs<Val, Dft, ValMmcs, ChallengeMmcs>;
+    type Pcs<D> = TwoAdicFriPcs<Val, D, ValMmcs, ChallengeMmcs>;
 
+    /// The Poseidon2 permutation used by [BabyBearPoseidon2].
+    ///
+    /// The permutation is built once and cached, so this only clones its round constants.
     pub fn my_perm() -> Perm {
+        static PERM: OnceLock<Perm> = OnceLock::new();
+        PERM.get_or_init(build_perm).clone()
+    }
+
+    fn build_perm() -> Perm {
         const ROUNDS_F: usize = 8;
         const ROUNDS_P: usize = 13;
-        let mut round_constants = RC_16_30.to_vec();
-        let internal_start = ROUNDS_F / 2;
-        let internal_end = (ROUNDS_F / 2) + ROUNDS_P;
+        my_perm_with(ROUNDS_F, ROUNDS_P, RC_16_30[..ROUNDS_F + ROUNDS_P].to_vec()).unwrap()
+    }
+
+    /// The number of round constants given to [my_perm_with] does not match its number of rounds.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+    #[error("expected {expected} round constants, got {got}")]
+    pub struct RoundConstantsMismatch {
+        pub expected: usize,
+        pub got: usize,
+    }
+
+    /// Creates a Poseidon2 permutation with `rounds_f` full rounds and `rounds_p` partial rounds.
+    ///
+    /// `constants` holds one row per round, in the order the rounds are applied: the first half of
+    /// the full rounds, the partial rounds, then the second half of the full rounds. Only the first
+    /// constant of a partial round's row is used.
+    pub fn my_perm_with(
+        rounds_f: usize,
+        rounds_p: usize,
+        constants: Vec<[Val; 16]>,
+    ) -> Result<Perm, RoundConstantsMismatch> {
+        if constants.len() != rounds_f + rounds_p {
+            return Err(RoundConstantsMismatch {
+                expected: rounds_f + rounds_p,
+                got: constants.len(),
+            });
+        }
+        let mut round_constants = constants;
+        let internal_start = rounds_f / 2;
+        let internal_end = (rounds_f / 2) + rounds_p;
         let internal_round_constants = round_constants
             .drain(internal_start..internal_end)
             .map(|vec| vec[0])
             .collect::<Vec<_>>();
         let external_round_constants = round_constants;
-        Perm::new(
-            ROUNDS_F,
+        Ok(Perm::new(
+            rounds_f,
             external_round_constants,
             Poseidon2ExternalMatrixGeneral,
-            ROUNDS_P,
+            rounds_p,
             internal_round_constants,
             DiffusionMatrixBabyBear,
-        )
+        ))
     }
 
-    pub fn default_fri_config() -> FriConfig<ChallengeMmcs> {
-        let perm = my_perm();
-        let hash = MyHash::new(perm.clone());
-        let compress = MyCompress::new(perm.clone());
-        let challenge_mmcs = ChallengeMmcs::new(ValMmcs::new(hash, compress));
-        let num_queries = match std::env::var("FRI_QUERIES") {
-            Ok(value) => value.parse().unwrap(),
-            Err(_) => 100,
-        };
-        FriConfig {
-            log_blowup: 1,
-            num_queries,
-            proof_of_work_bits: 16,
-            mmcs: challenge_mmcs,
+    /// The parameters of the FRI protocol used by a [BabyBearPoseidon2] config.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct FriParams {
+        pub log_blowup: usize,
+        pub num_queries: usize,
+        pub proof_of_work_bits: usize,
+    }
+
+    impl FriParams {
+        /// The parameters used for core proofs.
+        ///
+        /// The number of queries can be overridden with the `FRI_QUERIES` environment variable.
+        pub fn core() -> Self {
+            Self {
+                log_blowup: 1,
+                num_queries: num_queries_from_env().unwrap_or(100),
+                proof_of_work_bits: 16,
+            }
+        }
+
+        /// The parameters used for compressed proofs.
+        ///
+        /// The number of queries can be overridden with the `FRI_QUERIES` environment variable.
+        pub fn compressed() -> Self {
+            Self {
+                log_blowup: 3,
+                num_queries: num_queries_from_env().unwrap_or(33),
+                proof_of_work_bits: 16,
+            }
         }
     }
 
-    pub fn compressed_fri_config() -> FriConfig<ChallengeMmcs> {
-        let perm = my_perm();
+    impl FriParams {
+        /// Insecure parameters for fast tests, with few queries and no proof-of-work grinding.
+        ///
+        /// These give about 10 bits of conjectured security and must never be used to prove
+        /// anything that is relied upon.
+        pub const fn fast_test() -> Self {
+            Self {
+                log_blowup: 1,
+                num_queries: 10,
+                proof_of_work_bits: 0,
+            }
+        }
+    }
+
+    /// The conjectured bits of security that [FriParams] are expected to provide.
+    pub const TARGET_SECURITY_BITS: usize = 100;
+
+    impl FriParams {
+        /// Sets the number of proof-of-work bits required of the prover before querying.
+        ///
+        /// Raising it adds security at the cost of prover grinding time, while benchmarks can set
+        /// it to zero as long as the queries alone meet [TARGET_SECURITY_BITS].
+        pub const fn with_proof_of_work_bits(mut self, proof_of_work_bits: usize) -> Self {
+            self.proof_of_work_bits = proof_of_work_bits;
+            self
+        }
+
+        /// The conjectured bits of security of these parameters, as `proof_of_work_bits +
+        /// num_queries * log_blowup`.
+        pub const fn conjectured_security_bits(&self) -> usize {
+            self.proof_of_work_bits + self.num_queries * self.log_blowup
+        }
+
+        /// Checks in debug builds that these parameters meet [TARGET_SECURITY_BITS].
+        ///
+        /// Parameters whose number of queries comes from `FRI_QUERIES` are deliberately weakened
+        /// for testing and are not checked.
+        pub(crate) fn debug_assert_secure(&self) {
+            debug_assert!(
+                num_queries_from_env().is_some()
+                    || self.conjectured_security_bits() >= TARGET_SECURITY_BITS,
+                "FRI parameters {:?} give {} bits of conjectured security, below the target {}",
+                self,
+                self.conjectured_security_bits(),
+                TARGET_SECURITY_BITS,
+            );
+        }
+    }
+
+    /// The number of FRI queries needed for `target_bits` of conjectured security at a blowup of
+    /// `2^log_blowup`.
+    ///
+    /// Under the conjectured soundness of FRI, each query catches a cheating prover except with
+    /// probability `2^-log_blowup`, so `num_queries` queries give `num_queries * log_blowup` bits.
+    /// Proof-of-work bits are not counted and only add margin on top of the target.
+    pub const fn queries_for_security(target_bits: usize, log_blowup: usize) -> usize {
+        assert!(log_blowup > 0, "log_blowup must be positive");
+        target_bits.div_ceil(log_blowup)
+    }
+
+    impl FriParams {
+        /// The core parameters with the number of queries chosen to meet `target_bits` of
+        /// conjectured security with [queries_for_security], ignoring `FRI_QUERIES`.
+        pub const fn for_security_bits(target_bits: usize) -> Self {
+            let log_blowup = 1;
+            Self {
+                log_blowup,
+                num_queries: queries_for_security(target_bits, log_blowup),
+                proof_of_work_bits: 16,
+            }
+        }
+    }
+
+    impl Default for FriParams {
+        fn default() -> Self {
+            Self::core()
+        }
+    }
+
+    /// The stages of the prover pipeline that prove over a [BabyBearPoseidon2] config.
+    ///
+    /// The final wrap stage proves over the BN254 outer config of the recursion crate instead.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Preset {
+        /// Proofs of RISC-V execution.
+        Core,
+        /// Recursive proofs that reduce core and deferred proofs.
+        Compress,
+        /// The proof shrinking the final compressed proof before it is wrapped.
+        Shrink,
+    }
+
+    impl FriParams {
+        /// The parameters used by a stage of the prover pipeline.
+        pub fn preset(preset: Preset) -> Self {
+            match preset {
+                Preset::Core | Preset::Compress => Self::core(),
+                Preset::Shrink => Self::compressed(),
+            }
+        }
+    }
+
+    /// Reads the number of FRI queries from the `FRI_QUERIES` environment variable, ignoring
+    /// malformed values.
+    fn num_queries_from_env() -> Option<usize> {
+        let value = std::env::var("FRI_QUERIES").ok()?;
+        match value.parse() {
+            Ok(num_queries) => Some(num_queries),
+            Err(_) => {
+                tracing::warn!("ignoring malformed FRI_QUERIES value: {}", value);
+                None
+            }
+        }
+    }
+
+    pub fn fri_config(params: FriParams) -> FriConfig<ChallengeMmcs> {
+        params.debug_assert_secure();
+        fri_config_with_perm(my_perm(), params)
+    }
+
+    fn fri_config_with_perm(perm: Perm, params: FriParams) -> FriConfig<ChallengeMmcs> {
         let hash = MyHash::new(perm.clone());
-        let compress = MyCompress::new(perm.clone());
+        let compress = MyCompress::new(perm);
         let challenge_mmcs = ChallengeMmcs::new(ValMmcs::new(hash, compress));
-        let num_queries = match std::env::var("FRI_QUERIES") {
-            Ok(value) => value.parse().unwrap(),
-            Err(_) => 33,
-        };
         FriConfig {
-            log_blowup: 3,
-            num_queries,
-            proof_of_work_bits: 16,
+            log_blowup: params.log_blowup,
+            num_queries: params.num_queries,
+            proof_of_work_bits: params.proof_of_work_bits,
             mmcs: challenge_mmcs,
         }
     }
 
-    enum BabyBearPoseidon2Type {
-        Default,
-        Compressed,
+    pub fn default_fri_config() -> FriConfig<ChallengeMmcs> {
+        fri_config(FriParams::core())
+    }
+
+    pub fn compressed_fri_config() -> FriConfig<ChallengeMmcs> {
+        fri_config(FriParams::compressed())
     }
 
+    /// The default bound on the log-degree of the polynomials committed by the PCS.
+    pub const DEFAULT_LOG_DEGREE_BOUND: usize = 27;
+
+    /// A config over BabyBear and Poseidon2 whose PCS computes low-degree extensions with the DFT
+    /// `D`.
     #[derive(Deserialize)]
-    #[serde(from = "std::marker::PhantomData<BabyBearPoseidon2>")]
-    pub struct BabyBearPoseidon2 {
+    #[serde(from = "std::marker::PhantomData<BabyBearPoseidon2<D>>", bound = "")]
+    pub struct BabyBearPoseidon2<D: TwoAdicSubgroupDft<Val> = Dft> {
         pub perm: Perm,
-        pcs: Pcs,
-        config_type: BabyBearPoseidon2Type,
+        pcs: Pcs<D>,
+        dft: D,
+        fri_params: FriParams,
+        log_degree_bound: usize,
     }
 
     impl BabyBearPoseidon2 {
         pub fn new() -> Self {
-            let perm = my_perm();
-            let hash = MyHash::new(perm.clone());
-            let compress = MyCompress::new(perm.clone());
-            let val_mmcs = ValMmcs::new(hash, compress);
-            let dft = Dft {};
-            let fri_config = default_fri_config();
-            let pcs = Pcs::new(27, dft, val_mmcs, fri_config);
-            Self {
-                pcs,
-                perm,
-                config_type: BabyBearPoseidon2Type::Default,
-            }
+            Self::preset(Preset::Core)
         }
 
         pub fn compressed() -> Self {
-            let perm = my_perm();
+            Self::preset(Preset::Shrink)
+        }
+
+        /// Creates the config used by a stage of the prover pipeline.
+        pub fn preset(preset: Preset) -> Self {
+            Self::with_params(FriParams::preset(preset))
+        }
+
+        /// Creates a config with explicit FRI parameters, ignoring `FRI_QUERIES`.
+        pub fn with_fri_params(
+            log_blowup: usize,
+            num_queries: usize,
+            proof_of_work_bits: usize,
+        ) -> Self {
+            Self::with_params(FriParams {
+                log_blowup,
+                num_queries,
+                proof_of_work_bits,
+            })
+        }
+
+        /// Creates a config from a set of FRI parameters.
+        pub fn with_params(fri_params: FriParams) -> Self {
+            fri_params.debug_assert_secure();
+            Self::from_parts(my_perm(), Dft {}, fri_params, DEFAULT_LOG_DEGREE_BOUND)
+        }
+
+        /// Creates an insecure config with [FriParams::fast_test], for tests only.
+        pub fn fast_test() -> Self {
+            Self::from_parts(
+                my_perm(),
+                Dft {},
+                FriParams::fast_test(),
+                DEFAULT_LOG_DEGREE_BOUND,
+            )
+        }
+
+        /// Creates a config whose number of FRI queries meets `target_bits` of conjectured
+        /// security, as given by [FriParams::for_security_bits].
+        ///
+        /// Targets below [TARGET_SECURITY_BITS] are allowed, since they are asked for explicitly.
+        pub fn for_security_bits(target_bits: usize) -> Self {
+            Self::from_parts(
+                my_perm(),
+                Dft {},
+                FriParams::for_security_bits(target_bits),
+                DEFAULT_LOG_DEGREE_BOUND,
+            )
+        }
+
+        /// Creates a config with the core FRI parameters around a custom permutation, such as one
+        /// made by [my_perm_with].
+        ///
+        /// The permutation is used by the challenger and the PCS hashes. It is not serialized, so a
+        /// deserialized config uses [my_perm] again, and the recursion verifiers only support
+        /// [my_perm].
+        pub fn with_permutation(perm: Perm) -> Self {
+            Self::from_parts(perm, Dft {}, FriParams::core(), DEFAULT_LOG_DEGREE_BOUND)
+        }
+
+        /// Creates a config with the core FRI parameters and an explicit PCS log degree bound.
+        pub fn with_log_degree_bound(log_degree_bound: usize) -> Self {
+            Self::from_parts(my_perm(), Dft {}, FriParams::core(), log_degree_bound)
+        }
+    }
+
+    impl<D: TwoAdicSubgroupDft<Val>> BabyBearPoseidon2<D> {
+        /// Creates a config with the core FRI parameters whose PCS uses `dft` instead of
+        /// [Radix2DitParallel], for example a DFT that is faster on the hardware at hand.
+        ///
+        /// The DFT only changes how the prover computes low-degree extensions, so proofs remain
+        /// compatible with the default config.
+        pub fn with_dft(dft: D) -> Self {
+            Self::from_parts(my_perm(), dft, FriParams::core(), DEFAULT_LOG_DEGREE_BOUND)
+        }
+
+        /// Builds the PCS around an existing permutation, so that no state is lost or recomputed.
+        fn from_parts(perm: Perm, dft: D, fri_params: FriParams, log_degree_bound: usize) -> Self {
             let hash = MyHash::new(perm.clone());
             let compress = MyCompress::new(perm.clone());
             let val_mmcs = ValMmcs::new(hash, compress);
-            let dft = Dft {};
-            let fri_config = compressed_fri_config();
-            let pcs = Pcs::new(27, dft, val_mmcs, fri_config);
+            let fri_config = fri_config_with_perm(perm.clone(), fri_params);
+            let pcs = Pcs::new(log_degree_bound, dft.clone(), val_mmcs, fri_config);
             Self {
                 pcs,
                 perm,
-                config_type: BabyBearPoseidon2Type::Compressed,
+                dft,
+                fri_params,
+                log_degree_bound,
             }
         }
+
+        pub const fn fri_params(&self) -> FriParams {
+            self.fri_params
+        }
+
+        pub const fn log_degree_bound(&self) -> usize {
+            self.log_degree_bound
+        }
     }
 
-    impl Clone for BabyBearPoseidon2 {
+    impl<D: TwoAdicSubgroupDft<Val>> Clone for BabyBearPoseidon2<D> {
         fn clone(&self) -> Self {
-            match self.config_type {
-                BabyBearPoseidon2Type::Default => Self::new(),
-                BabyBearPoseidon2Type::Compressed => Self::compressed(),
-            }
+            Self::from_parts(
+                self.perm.clone(),
+                self.dft.clone(),
+                self.fri_params,
+                self.log_degree_bound,
+            )
         }
     }
 
@@ -561,34 +3488,232 @@ pub mod baby_bear_poseidon2 {
     }
 
     /// Implement serialization manually instead of using serde to avoid cloing the config.
-    impl Serialize for BabyBearPoseidon2 {
+    impl<D: TwoAdicSubgroupDft<Val>> Serialize for BabyBearPoseidon2<D> {
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: serde::Serializer,
         {
-            std::marker::PhantomData::<BabyBearPoseidon2>.serialize(serializer)
+            std::marker::PhantomData::<BabyBearPoseidon2<D>>.serialize(serializer)
         }
     }
 
-    impl From<std::marker::PhantomData<BabyBearPoseidon2>> for BabyBearPoseidon2 {
-        fn from(_: std::marker::PhantomData<BabyBearPoseidon2>) -> Self {
-            Self::new()
+    /// A deserialized config has the core FRI parameters and the default DFT of its type.
+    impl<D: TwoAdicSubgroupDft<Val>> From<std::marker::PhantomData<BabyBearPoseidon2<D>>>
+        for BabyBearPoseidon2<D>
+    {
+        fn from(_: std::marker::PhantomData<BabyBearPoseidon2<D>>) -> Self {
+            Self::with_dft(D::default())
+        }
+    }
+
+    impl<D: TwoAdicSubgroupDft<Val> + Send + Sync> StarkGenericConfig for BabyBearPoseidon2<D> {
+        type Val = BabyBear;
+        type Domain = <Pcs<D> as p3_commit::Pcs<Challenge, Challenger>>::Domain;
+        type Pcs = Pcs<D>;
+        type Challenge = Challenge;
+        type Challenger = Challenger;
+
+        fn pcs(&self) -> &Self::Pcs {
+            &self.pcs
+        }
+
+        fn challenger(&self) -> Self::Challenger {
+            Challenger::new(self.perm.clone())
+        }
+
+        fn max_log_trace_height(&self) -> Option<usize> {
+            // The low-degree extension of a trace must fit within the log degree bound.
+            Some(
+                self.log_degree_bound
+                    .saturating_sub(self.fri_params.log_blowup),
+            )
+        }
+
+        fn conjectured_security_bits(&self) -> Option<usize> {
+            Some(self.fri_params.conjectured_security_bits())
+        }
+
+        fn fri_query_shape(&self) -> Option<(usize, usize)> {
+            Some((self.fri_params.log_blowup, self.fri_params.num_queries))
+        }
+
+        fn capabilities() -> ConfigCapabilities {
+            ConfigCapabilities {
+                supports_recursion: true,
+                supports_wrapping: true,
+            }
+        }
+    }
+
+    /// A serializable snapshot of the sponge state of a [Challenger].
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct ChallengerState {
+        pub sponge_state: [Val; 16],
+        pub input_buffer: Vec<Val>,
+        pub output_buffer: Vec<Val>,
+    }
+
+    impl ChallengerState {
+        /// Captures the sponge state of a challenger.
+        pub fn capture(challenger: &Challenger) -> Self {
+            Self {
+                sponge_state: challenger.sponge_state,
+                input_buffer: challenger.input_buffer.clone(),
+                output_buffer: challenger.output_buffer.clone(),
+            }
+        }
+
+        /// Restores a challenger over the permutation of `config` from this state.
+        pub fn restore(&self, config: &BabyBearPoseidon2) -> Challenger {
+            let mut challenger = config.challenger();
+            self.restore_into(&mut challenger);
+            challenger
+        }
+
+        /// Overwrites the sponge state of `challenger` with this state, keeping its permutation.
+        ///
+        /// Restoring a challenger that is reused for every shard copies only the sponge, whereas
+        /// cloning a challenger also copies the round constants of its permutation.
+        pub fn restore_into(&self, challenger: &mut Challenger) {
+            challenger.sponge_state = self.sponge_state;
+            challenger.input_buffer.clone_from(&self.input_buffer);
+            challenger.output_buffer.clone_from(&self.output_buffer);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_clone_preserves_params() {
+            let params = FriParams {
+                log_blowup: 2,
+                num_queries: 50,
+                proof_of_work_bits: 8,
+            };
+            let config = BabyBearPoseidon2::with_params(params);
+            let cloned = config.clone();
+            assert_eq!(cloned.fri_params(), config.fri_params());
+            assert_eq!(cloned.log_degree_bound(), config.log_degree_bound());
+
+            let compressed = BabyBearPoseidon2::compressed();
+            assert_eq!(compressed.clone().fri_params(), compressed.fri_params());
+
+            let fast = BabyBearPoseidon2::fast_test();
+            assert_eq!(fast.clone().fri_params(), FriParams::fast_test());
+            assert_eq!(fast.fri_params().proof_of_work_bits, 0);
+        }
+
+        #[test]
+        fn test_my_perm_cached() {
+            use p3_field::AbstractField;
+            use p3_symmetric::Permutation;
+
+            let state: [Val; 16] = core::array::from_fn(Val::from_canonical_usize);
+            let expected = build_perm().permute(state);
+            assert_eq!(my_perm().permute(state), expected);
+            assert_eq!(my_perm().permute(state), expected);
+        }
+
+        #[test]
+        fn test_my_perm_with() {
+            use p3_field::AbstractField;
+            use p3_symmetric::Permutation;
+
+            let perm = my_perm_with(8, 13, RC_16_30[..21].to_vec()).unwrap();
+            let state: [Val; 16] = core::array::from_fn(Val::from_canonical_usize);
+            assert_eq!(perm.permute(state), my_perm().permute(state));
+
+            let result = my_perm_with(8, 13, RC_16_30.to_vec());
+            assert_eq!(
+                result.err(),
+                Some(RoundConstantsMismatch {
+                    expected: 21,
+                    got: 30
+                })
+            );
+
+            let config = BabyBearPoseidon2::with_permutation(perm);
+            assert_eq!(config.fri_params(), FriParams::core());
+        }
+
+        #[test]
+        fn test_conjectured_security_bits() {
+            let params = FriParams::compressed().with_proof_of_work_bits(0);
+            assert_eq!(params.proof_of_work_bits, 0);
+            assert_eq!(
+                params.conjectured_security_bits(),
+                params.num_queries * params.log_blowup
+            );
+
+            let config = BabyBearPoseidon2::with_params(FriParams::core());
+            assert_eq!(
+                config.conjectured_security_bits(),
+                Some(FriParams::core().conjectured_security_bits())
+            );
+        }
+
+        #[test]
+        fn test_queries_for_security() {
+            // Known (log_blowup, num_queries) pairs and the bits they give without grinding.
+            for (log_blowup, num_queries, bits) in [(1, 100, 100), (2, 50, 100), (3, 33, 99)] {
+                let params = FriParams {
+                    log_blowup,
+                    num_queries,
+                    proof_of_work_bits: 0,
+                };
+                assert_eq!(params.conjectured_security_bits(), bits);
+                assert_eq!(queries_for_security(bits, log_blowup), num_queries);
+            }
+            assert_eq!(queries_for_security(100, 3), 34);
+
+            let config = BabyBearPoseidon2::for_security_bits(100);
+            assert_eq!(
+                config.fri_params(),
+                FriParams {
+                    log_blowup: 1,
+                    num_queries: 100,
+                    proof_of_work_bits: 16,
+                }
+            );
+            assert!(config.conjectured_security_bits().unwrap() >= 100);
         }
-    }
 
-    impl StarkGenericConfig for BabyBearPoseidon2 {
-        type Val = BabyBear;
-        type Domain = <Pcs as p3_commit::Pcs<Challenge, Challenger>>::Domain;
-        type Pcs = Pcs;
-        type Challenge = Challenge;
-        type Challenger = Challenger;
+        #[test]
+        fn test_with_dft() {
+            use p3_dft::Radix2Dit;
 
-        fn pcs(&self) -> &Self::Pcs {
-            &self.pcs
+            let config = BabyBearPoseidon2::with_dft(Radix2Dit::default());
+            assert_eq!(config.fri_params(), FriParams::core());
+            let cloned = config.clone();
+            assert_eq!(cloned.fri_params(), config.fri_params());
+            assert_eq!(cloned.log_degree_bound(), DEFAULT_LOG_DEGREE_BOUND);
         }
 
-        fn challenger(&self) -> Self::Challenger {
-            Challenger::new(self.perm.clone())
+        #[test]
+        fn test_challenger_state_roundtrip() {
+            use p3_challenger::{CanObserve, CanSample};
+            use p3_field::AbstractField;
+
+            let config = BabyBearPoseidon2::new();
+            let mut challenger = config.challenger();
+            challenger.observe(Val::from_canonical_u32(7));
+            challenger.observe(Val::from_canonical_u32(11));
+
+            let state = ChallengerState::capture(&challenger);
+            let bytes = bincode::serialize(&state).unwrap();
+            let state: ChallengerState = bincode::deserialize(&bytes).unwrap();
+            let mut restored = state.restore(&config);
+
+            let expected: Val = challenger.sample();
+            let actual: Val = restored.sample();
+            assert_eq!(expected, actual);
+
+            // A challenger that has since diverged is rewound to the captured state.
+            state.restore_into(&mut restored);
+            let actual: Val = restored.sample();
+            assert_eq!(expected, actual);
         }
     }
 }
@@ -608,6 +3733,7 @@ pub(super) mod baby_bear_keccak {
 
     use crate::stark::StarkGenericConfig;
 
+    use super::baby_bear_poseidon2::FriParams;
     use super::LOG_DEGREE_BOUND;
 
     pub type Val = BabyBear;
@@ -632,6 +3758,7 @@ pub(super) mod baby_bear_keccak {
     #[serde(from = "std::marker::PhantomData<BabyBearKeccak>")]
     pub struct BabyBearKeccak {
         pcs: Pcs,
+        fri_params: FriParams,
     }
     // Implement serialization manually instead of using serde(into) to avoid cloing the config
     impl Serialize for BabyBearKeccak {
@@ -652,6 +3779,30 @@ pub(super) mod baby_bear_keccak {
     impl BabyBearKeccak {
         #[allow(dead_code)]
         pub fn new() -> Self {
+            Self::with_params(FriParams::core())
+        }
+
+        /// Creates a config from a set of FRI parameters.
+        #[allow(dead_code)]
+        pub fn with_params(fri_params: FriParams) -> Self {
+            fri_params.debug_assert_secure();
+            Self::from_params(fri_params)
+        }
+
+        /// Creates an insecure config with [FriParams::fast_test], for tests only.
+        #[allow(dead_code)]
+        pub fn fast_test() -> Self {
+            Self::from_params(FriParams::fast_test())
+        }
+
+        /// Creates a config whose number of FRI queries meets `target_bits` of conjectured
+        /// security, as given by [FriParams::for_security_bits].
+        #[allow(dead_code)]
+        pub fn for_security_bits(target_bits: usize) -> Self {
+            Self::from_params(FriParams::for_security_bits(target_bits))
+        }
+
+        fn from_params(fri_params: FriParams) -> Self {
             let byte_hash = ByteHash {};
             let field_hash = FieldHash::new(byte_hash);
 
@@ -664,14 +3815,14 @@ pub(super) mod baby_bear_keccak {
             let dft = Dft {};
 
             let fri_config = FriConfig {
-                log_blowup: 1,
-                num_queries: 100,
-                proof_of_work_bits: 16,
+                log_blowup: fri_params.log_blowup,
+                num_queries: fri_params.num_queries,
+                proof_of_work_bits: fri_params.proof_of_work_bits,
                 mmcs: challenge_mmcs,
             };
             let pcs = Pcs::new(LOG_DEGREE_BOUND, dft, val_mmcs, fri_config);
 
-            Self { pcs }
+            Self { pcs, fri_params }
         }
     }
 
@@ -683,7 +3834,7 @@ pub(super) mod baby_bear_keccak {
 
     impl Clone for BabyBearKeccak {
         fn clone(&self) -> Self {
-            Self::new()
+            Self::from_params(self.fri_params)
         }
     }
 
@@ -704,6 +3855,14 @@ pub(super) mod baby_bear_keccak {
             let byte_hash = ByteHash {};
             Challenger::from_hasher(vec![], byte_hash)
         }
+
+        fn conjectured_security_bits(&self) -> Option<usize> {
+            Some(self.fri_params.conjectured_security_bits())
+        }
+
+        fn fri_query_shape(&self) -> Option<(usize, usize)> {
+            Some((self.fri_params.log_blowup, self.fri_params.num_queries))
+        }
     }
 }
 
@@ -722,6 +3881,7 @@ pub(super) mod baby_bear_blake3 {
 
     use crate::stark::StarkGenericConfig;
 
+    use super::baby_bear_poseidon2::FriParams;
     use super::LOG_DEGREE_BOUND;
 
     pub type Val = BabyBear;
@@ -746,6 +3906,7 @@ pub(super) mod baby_bear_blake3 {
     #[serde(from = "std::marker::PhantomData<BabyBearBlake3>")]
     pub struct BabyBearBlake3 {
         pcs: Pcs,
+        fri_params: FriParams,
     }
 
     // Implement serialization manually instead of using serde(into) to avoid cloing the config
@@ -766,12 +3927,37 @@ pub(super) mod baby_bear_blake3 {
 
     impl Clone for BabyBearBlake3 {
         fn clone(&self) -> Self {
-            Self::new()
+            Self::from_params(self.fri_params)
         }
     }
 
     impl BabyBearBlake3 {
+        #[allow(dead_code)]
         pub fn new() -> Self {
+            Self::with_params(FriParams::core())
+        }
+
+        /// Creates a config from a set of FRI parameters.
+        #[allow(dead_code)]
+        pub fn with_params(fri_params: FriParams) -> Self {
+            fri_params.debug_assert_secure();
+            Self::from_params(fri_params)
+        }
+
+        /// Creates an insecure config with [FriParams::fast_test], for tests only.
+        #[allow(dead_code)]
+        pub fn fast_test() -> Self {
+            Self::from_params(FriParams::fast_test())
+        }
+
+        /// Creates a config whose number of FRI queries meets `target_bits` of conjectured
+        /// security, as given by [FriParams::for_security_bits].
+        #[allow(dead_code)]
+        pub fn for_security_bits(target_bits: usize) -> Self {
+            Self::from_params(FriParams::for_security_bits(target_bits))
+        }
+
+        fn from_params(fri_params: FriParams) -> Self {
             let byte_hash = ByteHash {};
             let field_hash = FieldHash::new(byte_hash);
 
@@ -783,19 +3969,15 @@ pub(super) mod baby_bear_blake3 {
 
             let dft = Dft {};
 
-            let num_queries = match std::env::var("FRI_QUERIES") {
-                Ok(value) => value.parse().unwrap(),
-                Err(_) => 100,
-            };
             let fri_config = FriConfig {
-                log_blowup: 1,
-                num_queries,
-                proof_of_work_bits: 16,
+                log_blowup: fri_params.log_blowup,
+                num_queries: fri_params.num_queries,
+                proof_of_work_bits: fri_params.proof_of_work_bits,
                 mmcs: challenge_mmcs,
             };
             let pcs = Pcs::new(LOG_DEGREE_BOUND, dft, val_mmcs, fri_config);
 
-            Self { pcs }
+            Self { pcs, fri_params }
         }
     }
 
@@ -822,5 +4004,988 @@ pub(super) mod baby_bear_blake3 {
             let byte_hash = ByteHash {};
             Challenger::from_hasher(vec![], byte_hash)
         }
+
+        fn conjectured_security_bits(&self) -> Option<usize> {
+            Some(self.fri_params.conjectured_security_bits())
+        }
+
+        fn fri_query_shape(&self) -> Option<(usize, usize)> {
+            Some((self.fri_params.log_blowup, self.fri_params.num_queries))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p3_baby_bear::BabyBear;
+    use p3_field::AbstractField;
+
+    use super::baby_bear_poseidon2::ChallengerState;
+    use super::*;
+    use crate::air::PV_DIGEST_NUM_WORDS;
+    use crate::runtime::tests::{fibonacci_program, simple_program};
+    use crate::runtime::{Instruction, Opcode};
+    use crate::stark::tests::prove_fibonacci;
+    use crate::stark::ConfigCapabilities;
+    use crate::stark::MachineVerificationError;
+    use crate::stark::ShardTranscript;
+    use crate::utils::{setup_logger, BabyBearPoseidon2Inner};
+
+    #[test]
+    fn test_config_capabilities() {
+        let all = ConfigCapabilities {
+            supports_recursion: true,
+            supports_wrapping: true,
+        };
+        assert_eq!(BabyBearPoseidon2::capabilities(), all);
+        assert_eq!(
+            BabyBearKeccak::capabilities(),
+            ConfigCapabilities::default()
+        );
+        assert_eq!(
+            BabyBearBlake3::capabilities(),
+            ConfigCapabilities::default()
+        );
+    }
+
+    #[test]
+    fn test_trace_checkpoint_program_mismatch() {
+        let program = simple_program();
+        let opts = SP1CoreOpts::default();
+        let mut runtime = Runtime::new(program.clone(), opts);
+        let checkpoint = runtime.checkpoint_iter().next().unwrap().unwrap();
+
+        let mut file = save_checkpoint(&program, &checkpoint).unwrap();
+        assert!(trace_checkpoint(program, &file, opts, CheckpointCodec::Plain).is_ok());
+        reset_seek(&mut file).unwrap();
+
+        let result = trace_checkpoint(fibonacci_program(), &file, opts, CheckpointCodec::Plain);
+        assert!(matches!(result, Err(SP1CoreProverError::ProgramMismatch)));
+    }
+
+    #[test]
+    fn test_prove_core_to_writer() {
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let config = BabyBearPoseidon2::fast_test();
+        let machine = RiscvAir::machine(config.clone());
+        let (_, vk) = machine.setup(runtime.program.as_ref());
+
+        let mut bytes = Vec::new();
+        prove_core_to_writer(config, runtime, &mut bytes).unwrap();
+        let proof = read_machine_proof_from_reader(bytes.as_slice()).unwrap();
+        let mut challenger = machine.config().challenger();
+        machine.verify(&vk, &proof, &mut challenger).unwrap();
+
+        let truncated = &bytes[..bytes.len() - 1];
+        let result = read_machine_proof_from_reader::<BabyBearPoseidon2, _>(truncated);
+        assert!(matches!(result, Err(SP1CoreProverError::IoError(_))));
+    }
+
+    #[test]
+    fn test_max_stdin_bytes() {
+        let mut stdin = SP1Stdin::new();
+        stdin.write_slice(&[0; 8]);
+        let options = ProveOptions {
+            max_stdin_bytes: Some(4),
+            ..Default::default()
+        };
+        let result = prove_with_options(
+            simple_program(),
+            &stdin,
+            BabyBearPoseidon2::fast_test(),
+            options,
+        );
+        assert!(matches!(
+            result,
+            Err(SP1CoreProverError::StdinTooLarge(StdinTooLarge {
+                len: 8,
+                max_bytes: 4
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_prove_with_metrics_sink() {
+        #[derive(Debug, Default)]
+        struct RecordingSink(std::sync::Mutex<Vec<ProveStats>>);
+
+        impl MetricsSink for RecordingSink {
+            fn emit(&self, metrics: &ProveStats) {
+                self.0.lock().unwrap().push(metrics.clone());
+            }
+        }
+
+        let sink = Arc::new(RecordingSink::default());
+        let metrics_sink: Arc<dyn MetricsSink> = sink.clone();
+        let options = ProveOptions {
+            metrics_sink: Some(metrics_sink),
+            ..Default::default()
+        };
+        let (proof, _) = prove_with_options(
+            simple_program(),
+            &SP1Stdin::new(),
+            BabyBearPoseidon2::fast_test(),
+            options.clone(),
+        )
+        .unwrap();
+
+        // Proving on a worker pool emits its statistics to the same sink.
+        let (pool_proof, _) = prove_with_worker_pool(
+            simple_program(),
+            &SP1Stdin::new(),
+            BabyBearPoseidon2::fast_test(),
+            options,
+            &LocalWorkerPool::new(1),
+        )
+        .unwrap();
+
+        let emitted = sink.0.lock().unwrap();
+        assert_eq!(emitted.len(), 2);
+        assert_eq!(emitted[0].shard_count, proof.shard_proofs.len());
+        assert_eq!(emitted[1].shard_count, pool_proof.shard_proofs.len());
+        assert!(emitted[1].checkpoint_bytes > 0);
+    }
+
+    #[test]
+    fn test_check_reexecution() {
+        assert!(check_reexecution(None, &[1, 2]).is_ok());
+        assert!(check_reexecution(Some(&[1, 2]), &[1, 2]).is_ok());
+        assert!(matches!(
+            check_reexecution(Some(&[1, 2]), &[1, 3]),
+            Err(SP1CoreProverError::NonDeterministicExecution)
+        ));
+
+        let options = ProveOptions {
+            verify_reexecution: true,
+            ..Default::default()
+        };
+        let result = prove_with_options(
+            fibonacci_program(),
+            &SP1Stdin::new(),
+            BabyBearPoseidon2::fast_test(),
+            options,
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_run_test_io_with_report() {
+        let (public_values, report) =
+            run_test_io_with_report(simple_program(), SP1Stdin::new()).unwrap();
+        assert!(public_values.as_slice().is_empty());
+        assert_eq!(report.instruction_counts[&Opcode::ADD], 3);
+        assert_eq!(report.total_syscall_count(), 0);
+        assert_eq!(report.event_counts["cpu_events"], 3);
+        assert_eq!(report.event_counts["add_events"], 3);
+    }
+
+    #[test]
+    fn test_verify_machine_proof_from_reader() {
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let config = BabyBearPoseidon2::fast_test();
+        let machine = RiscvAir::machine(config.clone());
+        let (_, vk) = machine.setup(runtime.program.as_ref());
+
+        let mut bytes = Vec::new();
+        prove_core_to_writer(config.clone(), runtime, &mut bytes).unwrap();
+        verify_machine_proof_from_reader(&vk, bytes.as_slice(), config.clone()).unwrap();
+
+        // A corrupted shard proof fails verification rather than deserialization.
+        let mut proof =
+            read_machine_proof_from_reader::<BabyBearPoseidon2, _>(bytes.as_slice()).unwrap();
+        proof.shard_proofs[0].public_values[0] += BabyBear::one();
+        let shard_bytes = bincode::serialize(&proof.shard_proofs[0]).unwrap();
+        let mut corrupted = 1u64.to_le_bytes().to_vec();
+        corrupted.extend_from_slice(&(shard_bytes.len() as u64).to_le_bytes());
+        corrupted.extend_from_slice(&shard_bytes);
+        let result = verify_machine_proof_from_reader(&vk, corrupted.as_slice(), config.clone());
+        assert!(matches!(result, Err(SP1CoreProverError::InvalidProof(_))));
+
+        let empty = 0u64.to_le_bytes();
+        let result = verify_machine_proof_from_reader(&vk, empty.as_slice(), config.clone());
+        assert!(matches!(result, Err(SP1CoreProverError::InvalidProof(_))));
+
+        // A length prefix larger than the stream fails without allocating it.
+        let mut truncated = 1u64.to_le_bytes().to_vec();
+        truncated.extend_from_slice(&u64::MAX.to_le_bytes());
+        truncated.extend_from_slice(&shard_bytes[..16]);
+        let result = verify_machine_proof_from_reader(&vk, truncated.as_slice(), config);
+        assert!(matches!(result, Err(SP1CoreProverError::IoError(_))));
+    }
+
+    #[test]
+    fn test_prove_with_memory() {
+        let instructions = vec![
+            Instruction::new(Opcode::LW, 29, 0, 0x1000, false, true),
+            Instruction::new(Opcode::LW, 30, 0, 0x1004, false, true),
+            Instruction::new(Opcode::ADD, 31, 30, 29, false, false),
+        ];
+        let mut program = Program::new(instructions, 0, 0);
+        program.memory_image.insert(0x2000, 7);
+        let config = BabyBearPoseidon2::fast_test();
+        let opts = SP1CoreOpts::default();
+
+        let preload = vec![(0x1000, vec![5, 0, 0, 0, 37])];
+        let (proof, _) = prove_with_memory(
+            program.clone(),
+            &SP1Stdin::new(),
+            config.clone(),
+            opts,
+            preload,
+        )
+        .unwrap();
+        let machine = RiscvAir::machine(config.clone());
+        let (_, vk) = machine.setup(&program);
+        let mut challenger = machine.config().challenger();
+        machine.verify(&vk, &proof, &mut challenger).unwrap();
+
+        let preload = vec![(0x2000, vec![1, 2, 3, 4])];
+        let result = prove_with_memory(program, &SP1Stdin::new(), config, opts, preload);
+        assert!(matches!(
+            result,
+            Err(SP1CoreProverError::InvalidMemoryPreload(
+                MemoryPreloadError::OverlapsProgram(0x2000)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_run_test_with_config() {
+        let program = simple_program();
+        run_test_with_config(program.clone(), SP1Stdin::new(), BabyBearKeccak::new()).unwrap();
+        run_test_with_config(program, SP1Stdin::new(), BabyBearBlake3::new()).unwrap();
+    }
+
+    #[test]
+    fn test_run_test_machine_no_verify() {
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let machine = RiscvAir::machine(BabyBearPoseidon2::fast_test());
+        let (pk, vk) = machine.setup(runtime.program.as_ref());
+
+        let proof = run_test_machine_no_verify(runtime.record, machine, pk);
+        let machine = RiscvAir::machine(BabyBearPoseidon2::fast_test());
+        let mut challenger = machine.config().challenger();
+        machine.verify(&vk, &proof, &mut challenger).unwrap();
+    }
+
+    #[test]
+    fn test_verify_public_values_digest() {
+        let program = fibonacci_program();
+        let mut runtime = Runtime::new(program.clone(), SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let public_values = runtime.record.public_values;
+        let mut proof = prove_record(BabyBearPoseidon2::fast_test(), &program, runtime.record);
+        verify_public_values_digest(&proof, &public_values).unwrap();
+
+        let last = proof.shard_proofs.len() - 1;
+        proof.shard_proofs[last].public_values[0] += BabyBear::one();
+        let result = verify_public_values_digest(&proof, &public_values);
+        assert!(matches!(
+            result,
+            Err(MachineVerificationError::PublicValuesDigestMismatch(shard)) if shard == last
+        ));
+    }
+
+    #[test]
+    fn test_check_shard_public_values() {
+        let program = fibonacci_program();
+        let opts = SP1CoreOpts {
+            shard_size: 1 << 10,
+            ..Default::default()
+        };
+        let mut runtime = Runtime::new(program, opts);
+        runtime.run().unwrap();
+        let public_values = runtime.record.public_values;
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let mut shards = machine.shard(runtime.record, &ShardingConfig::default());
+        assert!(shards.len() > 1);
+        check_shard_public_values::<BabyBear>(&shards, 0, &public_values).unwrap();
+
+        // A shard that disagrees on the digests is reported by its position in the execution.
+        shards[1].public_values.committed_value_digest[0] ^= 1;
+        let result = check_shard_public_values::<BabyBear>(&shards, 3, &public_values);
+        assert!(matches!(
+            result,
+            Err(SP1CoreProverError::PublicValuesDigestMismatch { shard: 4 })
+        ));
+    }
+
+    #[test]
+    fn test_checkpoint_guard() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = ["a.bin", "b.bin"].map(|name| dir.path().join(name));
+        for path in paths.iter() {
+            File::create(path).unwrap();
+        }
+
+        let mut guard = CheckpointGuard::new();
+        guard.track(paths[0].clone());
+        guard.commit();
+        assert!(paths[0].exists());
+
+        // A panic while the guard is alive removes its checkpoints.
+        let result = std::panic::catch_unwind(|| {
+            let mut guard = CheckpointGuard::new();
+            guard.track(paths[1].clone());
+            panic!("proving failed");
+        });
+        assert!(result.is_err());
+        assert!(!paths[1].exists());
+    }
+
+    #[test]
+    fn test_prove_resumable_from_checkpoints() {
+        let program = simple_program();
+        let stdin = SP1Stdin::new();
+        let config = BabyBearPoseidon2::fast_test();
+        let options = ProveOptions {
+            checkpoint_codec: CheckpointCodec::Zstd(3),
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let (proof, public_values_stream) = prove_resumable(
+            program.clone(),
+            &stdin,
+            config.clone(),
+            options.clone(),
+            dir.path(),
+        )
+        .unwrap();
+
+        // Forget every shard proof, as if the run had been interrupted right after committing.
+        let manifest_path = dir.path().join("manifest.bin");
+        let mut manifest: ResumeManifest<BabyBearPoseidon2> = read_bincode(&manifest_path).unwrap();
+        manifest.proved.clear();
+        write_atomic(&manifest_path, &manifest).unwrap();
+        std::fs::remove_file(dir.path().join("proof_0.bin")).unwrap();
+
+        // The resumed run proves from the compressed checkpoint and the recorded commitments.
+        let (resumed, resumed_stream) =
+            prove_resumable(program, &stdin, config, options, dir.path()).unwrap();
+        assert_eq!(resumed_stream, public_values_stream);
+        assert_eq!(
+            bincode::serialize(&resumed).unwrap(),
+            bincode::serialize(&proof).unwrap()
+        );
+        assert!(dir.path().join("proof_0.bin").exists());
+    }
+
+    #[test]
+    fn test_extract_public_values() {
+        let program = fibonacci_program();
+        let mut runtime = Runtime::new(program.clone(), SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let expected = runtime.record.public_values;
+        let config = BabyBearPoseidon2::fast_test();
+        let proof = prove_record(config.clone(), &program, runtime.record);
+
+        let machine = RiscvAir::machine(config);
+        let public_values = extract_public_values(&proof, &machine).unwrap();
+        let digest = public_values
+            .committed_value_digest
+            .map(|word| word.to_u32());
+        assert_eq!(digest, expected.committed_value_digest);
+        assert_eq!(public_values.exit_code, BabyBear::zero());
+        assert_eq!(public_values.next_pc, BabyBear::zero());
+
+        let empty = MachineProof::<BabyBearPoseidon2> {
+            shard_proofs: Vec::new(),
+        };
+        assert!(extract_public_values(&empty, &machine).is_none());
+    }
+
+    #[test]
+    fn test_checkpoint_cycle_counts() {
+        let program = fibonacci_program();
+        let options = ProveOptions {
+            shard_batch_size: Some(1),
+            ..Default::default()
+        };
+        let stdin = SP1Stdin::new();
+        let cycle_counts = checkpoint_cycle_counts(program.clone(), &stdin, &options).unwrap();
+        let checkpoints = count_checkpoints(&program, &stdin, &options).unwrap();
+        assert_eq!(cycle_counts.len(), checkpoints.len());
+
+        let mut runtime = Runtime::new(program, options.core_opts());
+        runtime.run().unwrap();
+        assert_eq!(cycle_counts.iter().sum::<u64>(), runtime.state.global_clk);
+    }
+
+    #[test]
+    fn test_simple_prove_with_stats() {
+        setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let cycles = runtime.state.global_clk;
+
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let (pk, vk) = machine.setup(runtime.program.as_ref());
+        let (proof, stats) = run_test_machine_with_stats(runtime.record, machine, pk, vk).unwrap();
+        assert_eq!(stats.cycles, cycles);
+        assert_eq!(stats.shard_count, proof.shard_proofs.len());
+        assert!(stats.proof_bytes > 0);
+
+        for shard_proof in proof.shard_proofs.iter() {
+            let sizes = shard_proof.size_breakdown().unwrap();
+            assert!(sizes.main_commit_bytes > 0);
+            assert!(sizes.opening_proof_bytes < stats.proof_bytes);
+            assert!(sizes.quotient_degree > 0);
+            assert!(sizes.num_openings > 0);
+        }
+    }
+
+    #[test]
+    fn test_simple_prove_strict() {
+        setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+        runtime.run().unwrap();
+
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let (pk, vk) = machine.setup(runtime.program.as_ref());
+        run_test_machine_with_determinism(runtime.record, machine, pk, vk, Determinism::Strict)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_fibonacci_prove_with_options() {
+        let options = ProveOptions {
+            shard_batch_size: Some(1),
+            reuse_single_batch: true,
+            parallel_shard_proving: true,
+            ..Default::default()
+        };
+        prove_fibonacci(options).unwrap();
+    }
+
+    #[test]
+    fn test_simple_prove_record() {
+        setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program.clone(), SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let config = BabyBearPoseidon2::new();
+        let proof = prove_record(config.clone(), &program, runtime.record);
+
+        let machine = RiscvAir::machine(config.clone());
+        let (_, vk) = machine.setup(&program);
+        verify_machine_proof(&vk, &proof, config).unwrap();
+    }
+
+    #[test]
+    fn test_fibonacci_prove_with_worker_pool() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let config = BabyBearPoseidon2::new();
+        let options = ProveOptions {
+            shard_batch_size: Some(1),
+            ..Default::default()
+        };
+        let machine = RiscvAir::machine(config.clone());
+        let (_, vk) = machine.setup(&program);
+        let pool = LocalWorkerPool::new(2);
+        let (proof, _) =
+            prove_with_worker_pool(program, &stdin, config.clone(), options, &pool).unwrap();
+
+        verify_machine_proof(&vk, &proof, config).unwrap();
+    }
+
+    #[test]
+    fn test_fibonacci_prove_per_shard_timeout() {
+        let options = ProveOptions {
+            shard_batch_size: Some(1),
+            per_shard_timeout: Some(Duration::from_nanos(1)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            prove_fibonacci(options.clone()),
+            Err(SP1CoreProverError::ShardTimeout { index: 0 })
+        ));
+
+        prove_fibonacci(ProveOptions {
+            per_shard_timeout: Some(Duration::from_secs(600)),
+            parallel_shard_proving: true,
+            ..options
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_fibonacci_prove_zstd_checkpoints() {
+        let options = ProveOptions {
+            shard_batch_size: Some(1),
+            checkpoint_codec: CheckpointCodec::Zstd(3),
+            ..Default::default()
+        };
+        prove_fibonacci(options).unwrap();
+    }
+
+    #[test]
+    fn test_fibonacci_prove_interleaved() {
+        let options = ProveOptions {
+            shard_batch_size: Some(1),
+            strategy: ProveStrategy::Interleaved,
+            ..Default::default()
+        };
+        prove_fibonacci(options).unwrap();
+    }
+
+    #[test]
+    fn test_fibonacci_prove_expected_public_values() {
+        let mut expected = PublicValues::<u32, u32>::default();
+        expected.committed_value_digest = [u32::MAX; PV_DIGEST_NUM_WORDS];
+        let options = ProveOptions {
+            expected_public_values: Some(expected),
+            ..Default::default()
+        };
+        let result = prove_fibonacci(options);
+        assert!(matches!(
+            result,
+            Err(SP1CoreProverError::PublicValuesMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_fibonacci_prove_max_cycles() {
+        let options = ProveOptions {
+            max_cycles: Some(100),
+            ..Default::default()
+        };
+        let result = prove_fibonacci(options);
+        assert!(matches!(
+            result,
+            Err(SP1CoreProverError::ExecutionError(
+                ExecutionError::ExceededCycleLimit(100)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_fibonacci_commit_checkpoints() {
+        use p3_challenger::CanSample;
+
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let options = ProveOptions::default();
+        let config = BabyBearPoseidon2::new();
+        let (mut expected, public_values) =
+            observe_checkpoints(program.clone(), &stdin, config.clone(), &options).unwrap();
+
+        let mut runtime = Runtime::new(program.clone(), options.core_opts());
+        let mut checkpoints = runtime
+            .checkpoint_iter()
+            .map(|checkpoint| save_checkpoint(&program, &checkpoint.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+
+        // Two workers commit disjoint ranges of checkpoints, and the coordinator observes them.
+        let machine = RiscvAir::machine(config);
+        let (_, vk) = machine.setup(&program);
+        let (first, second) = checkpoints.split_at_mut(checkpoints.len() / 2);
+        let mut shard_commitments =
+            commit_checkpoints(&machine, program.clone(), first, &options, public_values).unwrap();
+        shard_commitments.extend(
+            commit_checkpoints(&machine, program.clone(), second, &options, public_values).unwrap(),
+        );
+        let mut challenger = machine.config().challenger();
+        vk.observe_into(&mut challenger);
+        observe_commitments(&machine, &mut challenger, &shard_commitments);
+
+        let expected: <BabyBearPoseidon2 as StarkGenericConfig>::Val = expected.sample();
+        let actual: <BabyBearPoseidon2 as StarkGenericConfig>::Val = challenger.sample();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_observe_commitments_transcript_domain() {
+        use p3_challenger::CanSample;
+
+        setup_logger();
+        let program = simple_program();
+        let mut runtime = Runtime::new(program.clone(), SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let machine =
+            RiscvAir::machine(BabyBearPoseidon2::new()).with_transcript_domain(Some(*b"test"));
+        let (pk, vk) = machine.setup(&program);
+        let proof = machine.prove::<LocalProver<_, _>>(
+            &pk,
+            runtime.record,
+            &mut machine.config().challenger(),
+            SP1CoreOpts::default(),
+        );
+
+        // A coordinator observing the commitments ends up with the verifier's challenger.
+        let mut expected = machine.config().challenger();
+        machine.verify(&vk, &proof, &mut expected).unwrap();
+        let shard_commitments = proof
+            .shard_proofs
+            .iter()
+            .map(ShardTranscript::new)
+            .map(|transcript| {
+                (
+                    transcript.main_commit,
+                    transcript.public_values[0..machine.num_pv_elts()].to_vec(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut challenger = machine.config().challenger();
+        vk.observe_into(&mut challenger);
+        observe_commitments(&machine, &mut challenger, &shard_commitments);
+
+        let expected: <BabyBearPoseidon2 as StarkGenericConfig>::Val = expected.sample();
+        let actual: <BabyBearPoseidon2 as StarkGenericConfig>::Val = challenger.sample();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_fibonacci_prove_checkpoints_with_challenger() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let options = ProveOptions::default();
+        let config = BabyBearPoseidon2::new();
+
+        // The coordinator observes every commitment once and ships the challenger state.
+        let (challenger, public_values) =
+            observe_checkpoints(program.clone(), &stdin, config.clone(), &options).unwrap();
+        let state = bincode::serialize(&ChallengerState::capture(&challenger)).unwrap();
+
+        // Each worker restores the challenger and proves a single checkpoint.
+        let state: ChallengerState = bincode::deserialize(&state).unwrap();
+        let challenger = state.restore(&config);
+        let setup_cache = SetupCache::new();
+        let mut shard_proofs = Vec::new();
+        let mut num_checkpoints = 0;
+        loop {
+            match prove_checkpoint_with_challenger(
+                program.clone(),
+                &stdin,
+                config.clone(),
+                &options,
+                num_checkpoints,
+                public_values,
+                &challenger,
+                &setup_cache,
+            ) {
+                Ok(mut proofs) => shard_proofs.append(&mut proofs),
+                Err(SP1CoreProverError::CheckpointOutOfRange(_)) => break,
+                Err(e) => panic!("failed to prove checkpoint: {}", e),
+            }
+            num_checkpoints += 1;
+        }
+        assert!(!shard_proofs.is_empty());
+        assert_eq!(setup_cache.len(), 1);
+
+        // A worker can also prove every checkpoint at once.
+        let prove_range = |range| {
+            prove_checkpoint_range_with_challenger(
+                program.clone(),
+                &stdin,
+                config.clone(),
+                &options,
+                range,
+                public_values,
+                &challenger,
+                &setup_cache,
+            )
+        };
+        let range_proofs = prove_range(0..num_checkpoints).unwrap();
+        assert_eq!(
+            bincode::serialize(&range_proofs).unwrap(),
+            bincode::serialize(&shard_proofs).unwrap()
+        );
+        assert!(matches!(
+            prove_range(0..num_checkpoints + 1),
+            Err(SP1CoreProverError::CheckpointOutOfRange(n)) if n == num_checkpoints
+        ));
+        assert!(matches!(
+            prove_range(1..1),
+            Err(SP1CoreProverError::InvalidCheckpointRange(_))
+        ));
+
+        // Checkpoints saved while counting are proven without executing the program again.
+        let mut checkpoints = count_checkpoints(&program, &stdin, &options).unwrap();
+        assert_eq!(checkpoints.len(), num_checkpoints);
+        let mut saved_proofs = Vec::new();
+        for checkpoint in checkpoints.files.iter_mut() {
+            saved_proofs.extend(
+                prove_saved_checkpoint_with_challenger(
+                    program.clone(),
+                    checkpoint,
+                    config.clone(),
+                    &options,
+                    checkpoints.public_values,
+                    &challenger,
+                    &setup_cache,
+                )
+                .unwrap(),
+            );
+        }
+        assert_eq!(
+            bincode::serialize(&saved_proofs).unwrap(),
+            bincode::serialize(&shard_proofs).unwrap()
+        );
+
+        let machine = RiscvAir::machine(config);
+        let (_, vk) = machine.setup(&program);
+
+        // The coordinator spot-checks each shard against the challenger it shipped.
+        for shard_proof in shard_proofs.iter() {
+            machine.verify_shard(&vk, shard_proof, &challenger).unwrap();
+        }
+        let unobserved = machine.config().challenger();
+        assert!(matches!(
+            machine.verify_shard(&vk, &shard_proofs[0], &unobserved),
+            Err(MachineVerificationError::InvalidSegmentProof(0, _))
+        ));
+
+        let proof = MachineProof { shard_proofs };
+        let mut challenger = machine.config().challenger();
+        machine.verify(&vk, &proof, &mut challenger).unwrap();
+    }
+
+    #[test]
+    fn test_fibonacci_prove_resumable() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let config = BabyBearPoseidon2::new();
+        let options = ProveOptions::default();
+        let dir = tempfile::tempdir().unwrap();
+        let prove_in_dir = |program: &Program| {
+            prove_resumable(
+                program.clone(),
+                &stdin,
+                config.clone(),
+                options.clone(),
+                dir.path(),
+            )
+        };
+
+        let (proof, _) = prove_in_dir(&program).unwrap();
+        assert!(dir.path().join("manifest.bin").exists());
+        assert!(dir.path().join("checkpoint_0.bin").exists());
+        assert!(dir.path().join("proof_0.bin").exists());
+
+        // A resumed run reads every shard proof back from the checkpoint directory.
+        let (resumed, _) = prove_in_dir(&program).unwrap();
+        assert_eq!(
+            bincode::serialize(&proof).unwrap(),
+            bincode::serialize(&resumed).unwrap()
+        );
+
+        let machine = RiscvAir::machine(config.clone());
+        let (_, vk) = machine.setup(&program);
+        verify_machine_proof(&vk, &resumed, config.clone()).unwrap();
+
+        // A different program cannot resume from the same directory.
+        let result = prove_in_dir(&simple_program());
+        assert!(matches!(result, Err(SP1CoreProverError::ResumeMismatch)));
+    }
+
+    #[test]
+    fn test_fibonacci_prove_shard_too_large() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let config = BabyBearPoseidon2::with_log_degree_bound(6);
+        assert_eq!(config.log_degree_bound(), 6);
+
+        let result = prove(program, &stdin, config, SP1CoreOpts::default());
+        assert!(matches!(
+            result,
+            Err(SP1CoreProverError::ShardTooLarge {
+                max_log_height: 5,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_fibonacci_analyze_sharding() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let report = analyze_sharding(
+            program,
+            &stdin,
+            BabyBearPoseidon2::new(),
+            &ShardingConfig::default(),
+        )
+        .unwrap();
+
+        assert!(report.total_cycles > 0);
+        assert_eq!(report.shard_count, report.per_shard_chip_heights.len());
+        for heights in report.per_shard_chip_heights.iter() {
+            assert!(heights.contains_key("CPU"));
+            assert!(heights.values().all(|height| height.is_power_of_two()));
+        }
+    }
+
+    #[test]
+    fn test_fibonacci_estimate_proof_size() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let config = BabyBearPoseidon2::new();
+        let report =
+            analyze_sharding(program, &stdin, config.clone(), &ShardingConfig::default()).unwrap();
+        let estimate = estimate_proof_size(&report, &config).unwrap();
+        assert!(estimate_proof_size(&report, &BabyBearPoseidon2Inner::new()).is_none());
+
+        let (proof, _) = prove_fibonacci(ProveOptions::default()).unwrap();
+        let size = bincode::serialize(&proof).unwrap().len();
+        assert!(estimate > size / 2 && estimate < size * 2);
+    }
+
+    #[test]
+    fn test_fibonacci_partial_proof_store() {
+        let opts = SP1CoreOpts {
+            shard_size: 1 << 10,
+            shard_batch_size: 0,
+            ..Default::default()
+        };
+        let (proof, vk) = prove_fibonacci(ProveOptions::from(opts)).unwrap();
+        assert!(proof.shard_proofs.len() > 1);
+        let (first, second) = proof.shard_proofs.split_at(1);
+
+        let config = BabyBearPoseidon2::new();
+        let dir = tempfile::tempdir().unwrap();
+        let store = PartialProofStore::open(dir.path(), &vk, 2).unwrap();
+        store.save(0, first).unwrap();
+        assert!(matches!(
+            store.finalize(),
+            Err(SP1CoreProverError::MissingCheckpoints(missing)) if missing == vec![1]
+        ));
+        assert!(store.save(2, second).is_err());
+
+        // A resumed coordinator only has to prove the missing checkpoint.
+        let store = PartialProofStore::open(dir.path(), &vk, 2).unwrap();
+        assert_eq!(store.completed(), vec![0]);
+        assert_eq!(store.missing(), vec![1]);
+        assert_eq!(store.load(0).unwrap().unwrap().len(), 1);
+        assert!(store.load(1).unwrap().is_none());
+        store.save(1, second).unwrap();
+        let merged = store.finalize().unwrap();
+        verify_machine_proof(&vk, &merged, config.clone()).unwrap();
+
+        store.save(1, first).unwrap();
+        assert!(matches!(
+            store.finalize(),
+            Err(SP1CoreProverError::InvalidPartialProofs(
+                MergeError::DuplicateShard(1)
+            ))
+        ));
+
+        let result = PartialProofStore::open(dir.path(), &vk, 3);
+        assert!(matches!(result, Err(SP1CoreProverError::ResumeMismatch)));
+
+        // The parts of one program cannot be assembled into a proof of another.
+        let (_, other_vk) = RiscvAir::machine(config).setup(&simple_program());
+        let result = PartialProofStore::open(dir.path(), &other_vk, 2);
+        assert!(matches!(result, Err(SP1CoreProverError::ResumeMismatch)));
+    }
+
+    #[test]
+    fn test_fibonacci_prove_cancelled() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let cancel = CancellationToken::new();
+        cancel.clone().cancel();
+        assert!(cancel.is_cancelled());
+
+        let result = prove_cancellable(
+            program,
+            &stdin,
+            BabyBearPoseidon2::new(),
+            ProveOptions::default(),
+            &cancel,
+        );
+        assert!(matches!(result, Err(SP1CoreProverError::Cancelled)));
+    }
+
+    #[test]
+    fn test_fibonacci_prove_with_progress() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let options = ProveOptions {
+            core_opts: SP1CoreOpts {
+                shard_size: 1 << 10,
+                ..Default::default()
+            },
+            shard_batch_size: Some(1),
+            ..Default::default()
+        };
+        let mut updates = Vec::new();
+        let (proof, _) = prove_with_progress(
+            program,
+            &stdin,
+            BabyBearPoseidon2::new(),
+            options,
+            |progress| updates.push(progress),
+        )
+        .unwrap();
+
+        let last = updates.last().unwrap();
+        assert_eq!(last.phase, ProvePhase::Prove);
+        assert_eq!(last.shard_index, proof.shard_proofs.len());
+        assert_eq!(last.total_shards, Some(proof.shard_proofs.len()));
+        assert!(updates.iter().any(|p| p.phase == ProvePhase::Commit));
+    }
+
+    #[test]
+    fn test_fibonacci_prove_single_batch() {
+        setup_logger();
+        let program = fibonacci_program();
+        let stdin = SP1Stdin::new();
+        let core_opts = SP1CoreOpts {
+            shard_size: 1 << 10,
+            shard_batch_size: 0,
+            ..Default::default()
+        };
+        let mut runtime = Runtime::new(program.clone(), core_opts);
+        runtime.run().unwrap();
+        let num_shards = runtime.state.current_shard as usize;
+        assert!(num_shards > 1);
+
+        let config = BabyBearPoseidon2::new();
+        let machine = RiscvAir::machine(config.clone());
+        let (_, vk) = machine.setup(&program);
+        let prove_in_batches = |shard_batch_size, reuse_single_batch| {
+            let options = ProveOptions {
+                core_opts,
+                shard_batch_size: Some(shard_batch_size),
+                reuse_single_batch,
+                verify_reexecution: true,
+                ..Default::default()
+            };
+            let mut updates = Vec::new();
+            let (proof, _) = prove_with_progress(
+                program.clone(),
+                &stdin,
+                config.clone(),
+                options,
+                |progress| updates.push(progress),
+            )
+            .unwrap();
+            verify_machine_proof(&vk, &proof, config.clone()).unwrap();
+            updates.iter().any(|p| p.phase == ProvePhase::Commit)
+        };
+
+        // A program of exactly one batch is proven without checkpoints unless that is disabled, a
+        // longer one with them.
+        assert!(!prove_in_batches(num_shards, true));
+        assert!(prove_in_batches(num_shards, false));
+        assert!(prove_in_batches(num_shards - 1, true));
     }
 }
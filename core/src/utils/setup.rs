@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use p3_field::PrimeField32;
+use web_time::Instant;
+
+use crate::runtime::Program;
+use crate::stark::{RiscvAir, StarkGenericConfig, StarkMachine, StarkProvingKey, StarkVerifyingKey};
+use crate::utils::{program_digest, SP1CoreProverError};
+
+/// The proving and verifying keys of a program.
+pub type SetupKeys<SC> = (StarkProvingKey<SC>, StarkVerifyingKey<SC>);
+
+/// A cache of the keys computed by [StarkMachine::setup], keyed by the digest of the program.
+///
+/// The keys also depend on the config, so a cache must only be used with machines built from the
+/// same config.
+pub struct SetupCache<SC: StarkGenericConfig> {
+    keys: Mutex<HashMap<[u8; 32], (Arc<SetupKeys<SC>>, Duration)>>,
+}
+
+impl<SC: StarkGenericConfig> SetupCache<SC> {
+    pub fn new() -> Self {
+        Self {
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the keys of `program`, running the setup only if they are not already cached.
+    pub fn get_or_setup(
+        &self,
+        machine: &StarkMachine<SC, RiscvAir<SC::Val>>,
+        program: &Program,
+    ) -> Result<Arc<SetupKeys<SC>>, SP1CoreProverError>
+    where
+        SC::Val: PrimeField32,
+    {
+        let digest = program_digest(program)?;
+        if let Some((keys, setup_time)) = self.keys.lock().unwrap().get(&digest) {
+            tracing::debug!("setup cache hit, saved {}ms", setup_time.as_millis());
+            return Ok(keys.clone());
+        }
+
+        let start = Instant::now();
+        let keys = Arc::new(machine.setup(program));
+        let setup_time = start.elapsed();
+        tracing::debug!("setup cache miss, setup took {}ms", setup_time.as_millis());
+        self.keys
+            .lock()
+            .unwrap()
+            .insert(digest, (keys.clone(), setup_time));
+        Ok(keys)
+    }
+
+    /// The number of programs whose keys are cached.
+    pub fn len(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the keys of every program from the cache.
+    pub fn clear(&self) {
+        self.keys.lock().unwrap().clear();
+    }
+}
+
+impl<SC: StarkGenericConfig> Default for SetupCache<SC> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::tests::{fibonacci_program, simple_program};
+    use crate::utils::BabyBearPoseidon2;
+
+    #[test]
+    fn test_setup_cache() {
+        let machine = RiscvAir::machine(BabyBearPoseidon2::new());
+        let cache = SetupCache::new();
+        let program = simple_program();
+
+        let first = cache.get_or_setup(&machine, &program).unwrap();
+        let second = cache.get_or_setup(&machine, &program).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.len(), 1);
+
+        let other = cache.get_or_setup(&machine, &fibonacci_program()).unwrap();
+        assert!(!Arc::ptr_eq(&first, &other));
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
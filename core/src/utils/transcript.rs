@@ -0,0 +1,205 @@
+use std::fmt::Debug;
+
+use p3_challenger::{CanObserve, CanSample, CanSampleBits, FieldChallenger, GrindingChallenger};
+use p3_commit::Pcs;
+use p3_field::Field;
+use serde::{Deserialize, Serialize};
+
+use crate::stark::{ConfigCapabilities, StarkGenericConfig};
+
+/// A call made on a [RecordingChallenger], with values in their debug representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptEvent {
+    Observe(String),
+    Sample(String),
+    SampleBits { bits: usize, value: usize },
+    Grind { bits: usize, witness: String },
+    CheckWitness { bits: usize, witness: String, valid: bool },
+}
+
+/// A challenger that forwards every call to an inner challenger and records it as a
+/// [TranscriptEvent].
+///
+/// Clones carry a copy of the transcript so far, so the transcript of a challenger cloned for
+/// each shard only records that shard's calls after the point it was cloned.
+#[derive(Debug, Clone)]
+pub struct RecordingChallenger<C> {
+    inner: C,
+    transcript: Vec<TranscriptEvent>,
+}
+
+impl<C> RecordingChallenger<C> {
+    pub const fn new(inner: C) -> Self {
+        Self {
+            inner,
+            transcript: Vec::new(),
+        }
+    }
+
+    /// The calls made on this challenger, in order.
+    pub fn transcript(&self) -> &[TranscriptEvent] {
+        &self.transcript
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: CanObserve<T>, T: Debug> CanObserve<T> for RecordingChallenger<C> {
+    fn observe(&mut self, value: T) {
+        self.transcript.push(TranscriptEvent::Observe(format!("{:?}", value)));
+        self.inner.observe(value);
+    }
+}
+
+impl<C: CanSample<T>, T: Debug> CanSample<T> for RecordingChallenger<C> {
+    fn sample(&mut self) -> T {
+        let value = self.inner.sample();
+        self.transcript.push(TranscriptEvent::Sample(format!("{:?}", value)));
+        value
+    }
+}
+
+impl<C: CanSampleBits<usize>> CanSampleBits<usize> for RecordingChallenger<C> {
+    fn sample_bits(&mut self, bits: usize) -> usize {
+        let value = self.inner.sample_bits(bits);
+        self.transcript.push(TranscriptEvent::SampleBits { bits, value });
+        value
+    }
+}
+
+impl<F: Field, C: FieldChallenger<F>> FieldChallenger<F> for RecordingChallenger<C> {}
+
+impl<C: GrindingChallenger> GrindingChallenger for RecordingChallenger<C> {
+    type Witness = C::Witness;
+
+    fn grind(&mut self, bits: usize) -> Self::Witness {
+        let witness = self.inner.grind(bits);
+        self.transcript.push(TranscriptEvent::Grind {
+            bits,
+            witness: format!("{:?}", witness),
+        });
+        witness
+    }
+
+    fn check_witness(&mut self, bits: usize, witness: Self::Witness) -> bool {
+        let valid = self.inner.check_witness(bits, witness);
+        self.transcript.push(TranscriptEvent::CheckWitness {
+            bits,
+            witness: format!("{:?}", witness),
+            valid,
+        });
+        valid
+    }
+}
+
+type RecordingCommitment<SC> = <<SC as StarkGenericConfig>::Pcs as Pcs<
+    <SC as StarkGenericConfig>::Challenge,
+    RecordingChallenger<<SC as StarkGenericConfig>::Challenger>,
+>>::Commitment;
+
+/// A config that proves and verifies like `SC`, but with its challenger wrapped in a
+/// [RecordingChallenger].
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct RecordingConfig<SC: StarkGenericConfig>(pub SC);
+
+impl<SC> StarkGenericConfig for RecordingConfig<SC>
+where
+    SC: StarkGenericConfig,
+    SC::Pcs: Pcs<SC::Challenge, RecordingChallenger<SC::Challenger>, Domain = SC::Domain>,
+    RecordingChallenger<SC::Challenger>:
+        FieldChallenger<SC::Val> + CanObserve<RecordingCommitment<SC>> + CanSample<SC::Challenge>,
+{
+    type Val = SC::Val;
+
+    type Domain = SC::Domain;
+
+    type Pcs = SC::Pcs;
+
+    type Challenge = SC::Challenge;
+
+    type Challenger = RecordingChallenger<SC::Challenger>;
+
+    fn pcs(&self) -> &Self::Pcs {
+        self.0.pcs()
+    }
+
+    fn challenger(&self) -> Self::Challenger {
+        RecordingChallenger::new(self.0.challenger())
+    }
+
+    fn max_log_trace_height(&self) -> Option<usize> {
+        self.0.max_log_trace_height()
+    }
+
+    fn conjectured_security_bits(&self) -> Option<usize> {
+        self.0.conjectured_security_bits()
+    }
+
+    fn fri_query_shape(&self) -> Option<(usize, usize)> {
+        self.0.fri_query_shape()
+    }
+
+    fn capabilities() -> ConfigCapabilities {
+        SC::capabilities()
+    }
+}
+
+/// Logs two transcripts side by side from the first event at which they differ, if any, and
+/// returns its index.
+pub fn log_transcript_divergence(
+    prover: &[TranscriptEvent],
+    verifier: &[TranscriptEvent],
+) -> Option<usize> {
+    let len = prover.len().max(verifier.len());
+    let index = (0..len).find(|&i| prover.get(i) != verifier.get(i))?;
+    tracing::error!("transcripts diverge at event {}", index);
+    for i in index..len {
+        tracing::error!(
+            "{:>6} | prover: {:?} | verifier: {:?}",
+            i,
+            prover.get(i),
+            verifier.get(i)
+        );
+    }
+    Some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::tests::simple_program;
+    use crate::runtime::Runtime;
+    use crate::stark::RiscvAir;
+    use crate::utils::{run_test_machine_with_transcripts, BabyBearPoseidon2, SP1CoreOpts};
+    use p3_baby_bear::BabyBear;
+    use p3_field::AbstractField;
+
+    #[test]
+    fn test_transcript_divergence() {
+        let config = RecordingConfig(BabyBearPoseidon2::new());
+        let mut prover = config.challenger();
+        let mut verifier = config.challenger();
+        prover.observe(BabyBear::one());
+        verifier.observe(BabyBear::one());
+        let divergence = log_transcript_divergence(prover.transcript(), verifier.transcript());
+        assert_eq!(divergence, None);
+
+        prover.observe(BabyBear::two());
+        verifier.observe(BabyBear::zero());
+        let _: BabyBear = prover.sample();
+        let divergence = log_transcript_divergence(prover.transcript(), verifier.transcript());
+        assert_eq!(divergence, Some(1));
+    }
+
+    #[test]
+    fn test_run_test_machine_with_transcripts() {
+        let mut runtime = Runtime::new(simple_program(), SP1CoreOpts::default());
+        runtime.run().unwrap();
+        let machine = RiscvAir::machine(RecordingConfig(BabyBearPoseidon2::new()));
+        let (pk, vk) = machine.setup(runtime.program.as_ref());
+        run_test_machine_with_transcripts(runtime.record, machine, pk, vk).unwrap();
+    }
+}
@@ -0,0 +1,389 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::Mutex;
+
+use crate::utils::ShardingReport;
+
+/// A deterministic assignment of jobs to the workers of a [LocalWorkerPool], so that a re-run
+/// places every job on the same worker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssignmentStrategy {
+    /// Job `i` runs on worker `i % num_workers`.
+    RoundRobin,
+    /// The jobs are split into contiguous ranges, one per worker, so that neighbouring jobs share
+    /// a worker.
+    Sticky,
+    /// Each job, from the largest to the smallest, goes to the worker with the least work assigned
+    /// so far. Ties go to the lowest worker index, so the largest jobs land on the first workers.
+    BySize(Vec<u64>),
+}
+
+impl AssignmentStrategy {
+    /// Assigns jobs by the total trace height of each shard in `report`.
+    pub fn by_size(report: &ShardingReport) -> Self {
+        Self::BySize(
+            report
+                .per_shard_chip_heights
+                .iter()
+                .map(|heights| heights.values().map(|&height| height as u64).sum())
+                .collect(),
+        )
+    }
+
+    /// Returns the worker of each of `num_jobs` jobs, with workers numbered from zero.
+    ///
+    /// Jobs without a size under [AssignmentStrategy::BySize] are treated as empty.
+    pub fn assign(&self, num_jobs: usize, num_workers: usize) -> Vec<usize> {
+        let num_workers = num_workers.max(1);
+        match self {
+            Self::RoundRobin => (0..num_jobs).map(|job| job % num_workers).collect(),
+            Self::Sticky => {
+                let jobs_per_worker = num_jobs.div_ceil(num_workers).max(1);
+                (0..num_jobs).map(|job| job / jobs_per_worker).collect()
+            }
+            Self::BySize(sizes) => {
+                let size = |job: usize| sizes.get(job).copied().unwrap_or(0);
+                let mut jobs = (0..num_jobs).collect::<Vec<_>>();
+                jobs.sort_by_key(|&job| (Reverse(size(job)), job));
+
+                // Every job counts for at least one unit so that empty jobs are spread out too.
+                let mut loads = vec![0u64; num_workers];
+                let mut assignment = vec![0; num_jobs];
+                for job in jobs {
+                    let worker = (0..num_workers)
+                        .min_by_key(|&worker| (loads[worker], worker))
+                        .unwrap();
+                    loads[worker] += size(job).max(1);
+                    assignment[job] = worker;
+                }
+                assignment
+            }
+        }
+    }
+}
+
+/// The results of [LocalWorkerPool::run_verified].
+#[derive(Debug)]
+pub struct VerifiedRun<R, E> {
+    /// The result of each job, in the order of the jobs.
+    pub results: Vec<Result<R, VerifiedJobError<E>>>,
+    /// The workers that returned a result which failed verification, in the order they failed.
+    pub blacklisted_workers: Vec<usize>,
+}
+
+/// The reason [LocalWorkerPool::run_verified] gave up on a job.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifiedJobError<E> {
+    /// Every attempt at the job failed verification, the last one with this error.
+    Rejected(E),
+    /// Every worker was blacklisted before the job could be run.
+    NoWorkers,
+}
+
+/// A job of [LocalWorkerPool::run_verified] with its index, the number of times it was retried
+/// and the error of its last attempt.
+type PendingJob<T, E> = (usize, T, usize, Option<E>);
+
+/// A pool of worker threads that run jobs and return their results in submission order.
+#[derive(Debug, Clone)]
+pub struct LocalWorkerPool {
+    num_threads: usize,
+    strategy: Option<AssignmentStrategy>,
+    max_retries: usize,
+}
+
+impl LocalWorkerPool {
+    /// Creates a pool that runs jobs on `num_threads` threads, or on one thread if it is zero.
+    pub fn new(num_threads: usize) -> Self {
+        Self {
+            num_threads: num_threads.max(1),
+            strategy: None,
+            max_retries: 1,
+        }
+    }
+
+    /// Sets how many times [Self::run_verified] retries a job whose result fails verification.
+    /// Defaults to one.
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Assigns jobs to workers up front with `strategy` instead of letting each worker take the
+    /// next pending job.
+    pub fn with_strategy(mut self, strategy: AssignmentStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    pub const fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    pub const fn max_retries(&self) -> usize {
+        self.max_retries
+    }
+
+    /// Runs `f` on every job, returning the results in the order of the jobs.
+    ///
+    /// Without an [AssignmentStrategy], each worker takes the next job as soon as it finishes its
+    /// previous one, so jobs of different sizes are balanced across the workers. With one, each
+    /// worker runs the jobs assigned to it in order.
+    pub fn run<T, R, F>(&self, jobs: Vec<T>, f: F) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+        F: Fn(T) -> R + Sync,
+    {
+        if let Some(strategy) = &self.strategy {
+            return self.run_assigned(jobs, strategy, f);
+        }
+
+        let num_jobs = jobs.len();
+        let jobs = Mutex::new(jobs.into_iter().enumerate());
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for _ in 0..self.num_threads.min(num_jobs) {
+                let sender = sender.clone();
+                let (jobs, f) = (&jobs, &f);
+                scope.spawn(move || loop {
+                    let job = jobs.lock().unwrap().next();
+                    let Some((index, job)) = job else {
+                        break;
+                    };
+                    sender.send((index, f(job))).unwrap();
+                });
+            }
+        });
+        drop(sender);
+
+        let mut results = receiver.into_iter().collect::<Vec<_>>();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Runs every job on the worker `strategy` assigns it to.
+    fn run_assigned<T, R, F>(&self, jobs: Vec<T>, strategy: &AssignmentStrategy, f: F) -> Vec<R>
+    where
+        T: Send,
+        R: Send,
+        F: Fn(T) -> R + Sync,
+    {
+        let assignment = strategy.assign(jobs.len(), self.num_threads);
+        let mut queues = (0..self.num_threads)
+            .map(|_| Vec::new())
+            .collect::<Vec<_>>();
+        for ((index, job), worker) in jobs.into_iter().enumerate().zip(assignment) {
+            queues[worker].push((index, job));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for queue in queues.into_iter().filter(|queue| !queue.is_empty()) {
+                let (sender, f) = (sender.clone(), &f);
+                scope.spawn(move || {
+                    for (index, job) in queue {
+                        sender.send((index, f(job))).unwrap();
+                    }
+                });
+            }
+        });
+        drop(sender);
+
+        let mut results = receiver.into_iter().collect::<Vec<_>>();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Runs `f` on every job like [Self::run], checking every result against its job with `verify`.
+    ///
+    /// A worker whose result fails `verify` is blacklisted: it takes no further jobs, and the job
+    /// is requeued for the remaining workers up to [Self::max_retries] times. The jobs a
+    /// blacklisted worker was assigned by an [AssignmentStrategy] are reassigned among the
+    /// remaining workers.
+    pub fn run_verified<T, R, E, F, V>(&self, jobs: Vec<T>, f: F, verify: V) -> VerifiedRun<R, E>
+    where
+        T: Send,
+        R: Send,
+        E: Send,
+        F: Fn(&T) -> R + Sync,
+        V: Fn(&T, &R) -> Result<(), E> + Sync,
+    {
+        let mut results = jobs.iter().map(|_| None).collect::<Vec<_>>();
+        let mut pending: Vec<PendingJob<T, E>> = jobs
+            .into_iter()
+            .enumerate()
+            .map(|(index, job)| (index, job, 0, None))
+            .collect();
+        let mut workers = (0..self.num_threads).collect::<Vec<_>>();
+        let mut blacklisted_workers = Vec::new();
+
+        // Each round runs every pending job once. A round either finishes all of its jobs or
+        // blacklists at least one worker, so the loop ends.
+        while !pending.is_empty() && !workers.is_empty() {
+            let queues = match &self.strategy {
+                Some(strategy) => {
+                    let assignment = strategy.assign(pending.len(), workers.len());
+                    let mut queues = workers.iter().map(|_| VecDeque::new()).collect::<Vec<_>>();
+                    for (job, slot) in pending.drain(..).zip(assignment) {
+                        queues[slot].push_back(job);
+                    }
+                    queues
+                }
+                None => vec![pending.drain(..).collect()],
+            };
+            let queues = queues.into_iter().map(Mutex::new).collect::<Vec<_>>();
+
+            let (sender, receiver) = mpsc::channel();
+            std::thread::scope(|scope| {
+                for (slot, &worker) in workers.iter().enumerate() {
+                    let queue = &queues[slot.min(queues.len() - 1)];
+                    let (sender, f, verify) = (sender.clone(), &f, &verify);
+                    scope.spawn(move || loop {
+                        let next = queue.lock().unwrap().pop_front();
+                        let Some((index, job, retries, _)) = next else {
+                            break;
+                        };
+                        let result = f(&job);
+                        match verify(&job, &result) {
+                            Ok(()) => sender.send(Ok((index, result))).unwrap(),
+                            Err(error) => {
+                                sender
+                                    .send(Err((worker, index, job, retries, error)))
+                                    .unwrap();
+                                break;
+                            }
+                        }
+                    });
+                }
+            });
+            drop(sender);
+
+            for outcome in receiver {
+                match outcome {
+                    Ok((index, result)) => results[index] = Some(Ok(result)),
+                    Err((worker, index, job, retries, error)) => {
+                        blacklisted_workers.push(worker);
+                        if retries < self.max_retries {
+                            pending.push((index, job, retries + 1, Some(error)));
+                        } else {
+                            results[index] = Some(Err(VerifiedJobError::Rejected(error)));
+                        }
+                    }
+                }
+            }
+            // Jobs left in the queue of a blacklisted worker were never run, so they are not
+            // counted as retried.
+            for queue in queues {
+                pending.extend(queue.into_inner().unwrap());
+            }
+            pending.sort_by_key(|(index, ..)| *index);
+            workers.retain(|worker| !blacklisted_workers.contains(worker));
+        }
+
+        for (index, _, _, error) in pending {
+            let error = error.map_or(VerifiedJobError::NoWorkers, VerifiedJobError::Rejected);
+            results[index] = Some(Err(error));
+        }
+        VerifiedRun {
+            results: results.into_iter().map(Option::unwrap).collect(),
+            blacklisted_workers,
+        }
+    }
+}
+
+impl Default for LocalWorkerPool {
+    fn default() -> Self {
+        Self::new(num_cpus::get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worker_pool_preserves_order() {
+        let pool = LocalWorkerPool::new(4);
+        let jobs = (0..100u64).collect::<Vec<_>>();
+        let results = pool.run(jobs, |job| {
+            std::thread::sleep(std::time::Duration::from_micros((100 - job) * 10));
+            job * 2
+        });
+        assert_eq!(results, (0..100u64).map(|job| job * 2).collect::<Vec<_>>());
+        assert!(LocalWorkerPool::new(4)
+            .run(Vec::<u64>::new(), |job| job)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_assignment_strategies() {
+        assert_eq!(
+            AssignmentStrategy::RoundRobin.assign(5, 2),
+            vec![0, 1, 0, 1, 0]
+        );
+        assert_eq!(AssignmentStrategy::Sticky.assign(5, 2), vec![0, 0, 0, 1, 1]);
+        assert_eq!(AssignmentStrategy::Sticky.assign(2, 4), vec![0, 1]);
+        let by_size = AssignmentStrategy::BySize(vec![1, 8, 4, 4]);
+        assert_eq!(by_size.assign(4, 2), vec![0, 0, 1, 1]);
+        assert_eq!(by_size.assign(4, 2), by_size.assign(4, 2));
+
+        let pool = LocalWorkerPool::new(3).with_strategy(AssignmentStrategy::Sticky);
+        let results = pool.run((0..10u64).collect(), |job| job * 2);
+        assert_eq!(results, (0..10u64).map(|job| job * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_run_verified() {
+        // The first result of job 3 is rejected, so it is retried on another worker.
+        let rejected = std::sync::atomic::AtomicBool::new(false);
+        let run = LocalWorkerPool::new(4).run_verified(
+            (0..10u64).collect(),
+            |job| job * 2,
+            |_, &result| {
+                let reject =
+                    result == 6 && !rejected.swap(true, std::sync::atomic::Ordering::SeqCst);
+                if reject {
+                    Err("invalid")
+                } else {
+                    Ok(())
+                }
+            },
+        );
+        let results = run.results.into_iter().map(Result::unwrap);
+        assert_eq!(
+            results.collect::<Vec<_>>(),
+            (0..10u64).map(|job| job * 2).collect::<Vec<_>>()
+        );
+        assert_eq!(run.blacklisted_workers.len(), 1);
+
+        // Without retries, the job fails with the error of its only attempt.
+        let pool = LocalWorkerPool::new(2).with_max_retries(0);
+        let run = pool.run_verified(
+            vec![1u64, 2],
+            |&job| job,
+            |_, &result| match result {
+                1 => Err("invalid"),
+                _ => Ok(()),
+            },
+        );
+        assert_eq!(
+            run.results,
+            vec![Err(VerifiedJobError::Rejected("invalid")), Ok(2)]
+        );
+
+        // Once its only worker is blacklisted, the pool cannot run the remaining jobs.
+        let pool = LocalWorkerPool::new(1).with_strategy(AssignmentStrategy::Sticky);
+        let run = pool.run_verified(vec![1u64, 2, 3], |&job| job, |_, _| Err("invalid"));
+        assert_eq!(
+            run.results,
+            vec![
+                Err(VerifiedJobError::Rejected("invalid")),
+                Err(VerifiedJobError::NoWorkers),
+                Err(VerifiedJobError::NoWorkers),
+            ]
+        );
+        assert_eq!(run.blacklisted_workers, vec![0]);
+    }
+}
@@ -0,0 +1,74 @@
+//! Measures the allocations made while writing deferred proofs to a runtime.
+//!
+//! The counting allocator is global to the test binary, so this file holds a single test.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sp1_core::io::{DeferredProofInput, SP1Stdin};
+use sp1_core::runtime::{Instruction, Opcode, Program, Runtime};
+use sp1_core::stark::RiscvAir;
+use sp1_core::utils::{prove, BabyBearPoseidon2, SP1CoreOpts};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Runs `f`, returning its result and the number of allocations it made.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let result = f();
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+#[test]
+fn test_write_proofs_allocations() {
+    let instructions = vec![
+        Instruction::new(Opcode::ADD, 29, 0, 5, false, true),
+        Instruction::new(Opcode::ADD, 30, 0, 37, false, true),
+        Instruction::new(Opcode::ADD, 31, 30, 29, false, false),
+    ];
+    let program = Program::new(instructions, 0, 0);
+    let config = BabyBearPoseidon2::fast_test();
+    let (proof, _) = prove(
+        program.clone(),
+        &SP1Stdin::new(),
+        config.clone(),
+        SP1CoreOpts::default(),
+    )
+    .unwrap();
+    let (_, vk) = RiscvAir::machine(config).setup(&program);
+    let inputs = vec![DeferredProofInput::new(proof.shard_proofs[0].clone(), vk); 4];
+
+    let mut runtime = Runtime::new(program, SP1CoreOpts::default());
+    let (_, written) = count_allocations(|| runtime.write_proofs(&inputs));
+    let (_, cloned) = count_allocations(|| {
+        inputs
+            .iter()
+            .map(|input| (input.proof.clone(), input.vk.clone()))
+            .collect::<Vec<_>>()
+    });
+    println!(
+        "allocations for {} deferred proofs: write_proofs={}, clone={}",
+        inputs.len(),
+        written,
+        cloned
+    );
+
+    // Serializing makes one buffer per proof, where cloning copies every vector of the proof.
+    assert!(written < cloned);
+}
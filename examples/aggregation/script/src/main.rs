@@ -1,6 +1,8 @@
 //! A simple example showing how to aggregate proofs of multiple programs with SP1.
 
-use sp1_sdk::{HashableKey, ProverClient, SP1CompressedProof, SP1Stdin, SP1VerifyingKey};
+use sp1_sdk::{
+    DeferredProofInput, HashableKey, ProverClient, SP1CompressedProof, SP1Stdin, SP1VerifyingKey,
+};
 
 /// A program that aggregates the proofs of the simple program.
 const AGGREGATION_ELF: &[u8] = include_bytes!("../../program/elf/riscv32im-succinct-zkvm-elf");
@@ -89,7 +91,7 @@ fn main() {
         // Note: this data will not actually be read by the aggregation program, instead it will be
         // witnessed by the prover during the recursive aggregation process inside SP1 itself.
         for input in inputs {
-            stdin.write_proof(input.proof.proof, input.vk.vk);
+            stdin.write_proof_input(DeferredProofInput::new(input.proof.proof, input.vk.vk));
         }
 
         // Generate the plonk bn254 proof.
@@ -25,17 +25,19 @@ use p3_field::{AbstractField, PrimeField};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use rayon::prelude::*;
 use sp1_core::air::{PublicValues, Word};
-pub use sp1_core::io::{SP1PublicValues, SP1Stdin};
+pub use sp1_core::io::{DeferredProofInput, SP1PublicValues, SP1Stdin};
 use sp1_core::runtime::{ExecutionError, ExecutionReport, Runtime};
 use sp1_core::stark::{Challenge, StarkProvingKey};
 use sp1_core::stark::{Challenger, MachineVerificationError};
-use sp1_core::utils::{SP1CoreOpts, DIGEST_SIZE};
+use sp1_core::utils::{
+    ProveOptions, ProvePhase, ProveProgress, SP1CoreOpts, SetupCache, DIGEST_SIZE,
+};
 use sp1_core::{
     runtime::Program,
     stark::{
         LocalProver, RiscvAir, ShardProof, StarkGenericConfig, StarkMachine, StarkVerifyingKey, Val,
     },
-    utils::{BabyBearPoseidon2, SP1CoreProverError},
+    utils::{baby_bear_poseidon2::Preset, BabyBearPoseidon2, SP1CoreProverError},
 };
 use sp1_primitives::hash_deferred_proof;
 use sp1_recursion_circuit::witness::Witnessable;
@@ -135,17 +137,20 @@ pub struct SP1Prover {
 
     /// The machine used for proving the wrapping step.
     pub wrap_machine: StarkMachine<OuterSC, WrapAir<<OuterSC as StarkGenericConfig>::Val>>,
+
+    /// The keys of the programs proven with the core prover.
+    pub core_setup_cache: SetupCache<CoreSC>,
 }
 
 impl SP1Prover {
     /// Initializes a new [SP1Prover].
     #[instrument(name = "initialize prover", level = "debug", skip_all)]
     pub fn new() -> Self {
-        let core_machine = RiscvAir::machine(CoreSC::default());
+        let core_machine = RiscvAir::machine(CoreSC::preset(Preset::Core));
 
         // Get the recursive verifier and setup the proving and verifying keys.
         let recursion_program = SP1RecursiveVerifier::<InnerConfig, _>::build(&core_machine);
-        let compress_machine = ReduceAir::machine(InnerSC::default());
+        let compress_machine = ReduceAir::machine(InnerSC::preset(Preset::Compress));
         let (rec_pk, rec_vk) = compress_machine.setup(&recursion_program);
 
         // Get the deferred program and keys.
@@ -163,7 +168,7 @@ impl SP1Prover {
         // Get the compress program, machine, and keys.
         let shrink_program =
             SP1RootVerifier::<InnerConfig, _, _>::build(&compress_machine, &compress_vk, true);
-        let shrink_machine = CompressAir::wrap_machine_dyn(InnerSC::compressed());
+        let shrink_machine = CompressAir::wrap_machine_dyn(InnerSC::preset(Preset::Shrink));
         let (shrink_pk, shrink_vk) = shrink_machine.setup(&shrink_program);
 
         // Get the wrap program, machine, and keys.
@@ -192,6 +197,7 @@ impl SP1Prover {
             compress_machine,
             shrink_machine,
             wrap_machine,
+            core_setup_cache: SetupCache::new(),
         }
     }
 
@@ -209,6 +215,19 @@ impl SP1Prover {
         (pk, vk)
     }
 
+    /// Computes the core keys of a RISC-V ELF ahead of time, so that proving it skips the setup.
+    pub fn warm_setup_cache(&self, elf: &[u8]) -> Result<(), SP1CoreProverError> {
+        let machine = RiscvAir::machine(CoreSC::default());
+        self.core_setup_cache
+            .get_or_setup(&machine, &Program::from(elf))
+            .map(|_| ())
+    }
+
+    /// Removes the core keys of every program from the setup cache.
+    pub fn clear_setup_cache(&self) {
+        self.core_setup_cache.clear();
+    }
+
     /// Generate a proof of an SP1 program with the specified inputs.
     #[instrument(name = "execute", level = "info", skip_all)]
     pub fn execute(
@@ -219,9 +238,7 @@ impl SP1Prover {
         let opts = SP1CoreOpts::default();
         let mut runtime = Runtime::new(program, opts);
         runtime.write_vecs(&stdin.buffer);
-        for (proof, vkey) in stdin.proofs.iter() {
-            runtime.write_proof(proof.clone(), vkey.clone());
-        }
+        runtime.write_proofs(&stdin.proofs);
         runtime.run_untraced()?;
         Ok((
             SP1PublicValues::from(&runtime.state.public_values_stream),
@@ -229,6 +246,36 @@ impl SP1Prover {
         ))
     }
 
+    /// Verifies every deferred proof of `stdin` against the verifying key it is paired with,
+    /// under the config it was made with.
+    ///
+    /// Only compressed proofs can be deferred, since the recursion program that verifies deferred
+    /// proofs runs the compress verifier. Proofs made with any other config fail with
+    /// [SP1CoreProverError::IncompatibleDeferredProof].
+    pub fn verify_deferred_proofs(&self, stdin: &SP1Stdin) -> Result<(), SP1CoreProverError> {
+        for (index, input) in stdin.proofs.iter().enumerate() {
+            if input.config != Preset::Compress {
+                return Err(SP1CoreProverError::IncompatibleDeferredProof {
+                    index,
+                    config: input.config,
+                });
+            }
+            let proof = SP1ReduceProof {
+                proof: input.proof.clone(),
+            };
+            let vk = SP1VerifyingKey {
+                vk: input.vk.clone(),
+            };
+            self.verify_compressed(&proof, &vk).map_err(|error| {
+                SP1CoreProverError::InvalidDeferredProof {
+                    index,
+                    error: error.to_string(),
+                }
+            })?;
+        }
+        Ok(())
+    }
+
     /// Generate shard proofs which split up and prove the valid execution of a RISC-V program with
     /// the core prover.
     #[instrument(name = "prove_core", level = "info", skip_all)]
@@ -237,15 +284,23 @@ impl SP1Prover {
         pk: &SP1ProvingKey,
         stdin: &SP1Stdin,
     ) -> Result<SP1CoreProof, SP1CoreProverError> {
+        // Fail before proving if a deferred proof would make the proof impossible to reduce.
+        self.verify_deferred_proofs(stdin)?;
+
         let config = CoreSC::default();
         let program = Program::from(&pk.elf);
-        let opts = SP1CoreOpts::default();
-        let (proof, public_values_stream) = sp1_core::utils::prove(program, stdin, config, opts)?;
-        let public_values = SP1PublicValues::from(&public_values_stream);
+        let options = ProveOptions::from(SP1CoreOpts::default());
+        let (proof, public_values_stream) = sp1_core::utils::prove_with_setup_cache(
+            program,
+            stdin,
+            config,
+            options,
+            &self.core_setup_cache,
+        )?;
         Ok(SP1CoreProof {
             proof: SP1CoreProofData(proof.shard_proofs),
             stdin: stdin.clone(),
-            public_values,
+            public_values: SP1PublicValues::from(&public_values_stream),
         })
     }
 
@@ -366,12 +421,24 @@ impl SP1Prover {
     }
 
     /// Reduce shards proofs to a single shard proof using the recursion prover.
-    #[instrument(name = "compress", level = "info", skip_all)]
     pub fn compress(
         &self,
         vk: &SP1VerifyingKey,
         proof: SP1CoreProof,
         deferred_proofs: Vec<ShardProof<InnerSC>>,
+    ) -> Result<SP1ReduceProof<InnerSC>, SP1RecursionProverError> {
+        self.compress_with_progress(vk, proof, deferred_proofs, |_| {})
+    }
+
+    /// Reduces shard proofs like [Self::compress], calling `progress` with a
+    /// [ProvePhase::Reduce] update each time a batch of proofs of a reduction layer is proven.
+    #[instrument(name = "compress", level = "info", skip_all)]
+    pub fn compress_with_progress<F: FnMut(ProveProgress)>(
+        &self,
+        vk: &SP1VerifyingKey,
+        proof: SP1CoreProof,
+        deferred_proofs: Vec<ShardProof<InnerSC>>,
+        mut progress: F,
     ) -> Result<SP1ReduceProof<InnerSC>, SP1RecursionProverError> {
         // Set the batch size for the reduction tree.
         let batch_size = 2;
@@ -468,18 +535,24 @@ impl SP1Prover {
             .flat_map(|(proof, kind)| proof.shard_proofs.into_iter().map(move |p| (p, kind)))
             .collect::<Vec<_>>();
 
+        // Every layer reduces each batch of proofs to one, and there is at least one layer.
+        let mut total_layers = 1;
+        let mut layer_size = reduce_proofs.len().div_ceil(batch_size);
+        while layer_size > 1 {
+            layer_size = layer_size.div_ceil(batch_size);
+            total_layers += 1;
+        }
+
         // Iterate over the recursive proof batches until there is one proof remaining.
         let mut is_complete;
-        loop {
+        for layer in 0.. {
             tracing::debug!("Recursive proof layer size: {}", reduce_proofs.len());
             is_complete = reduce_proofs.len() <= batch_size;
 
             let compress_inputs = reduce_proofs.chunks(batch_size).collect::<Vec<_>>();
-            let batched_compress_inputs =
-                compress_inputs.chunks(shard_batch_size).collect::<Vec<_>>();
-            reduce_proofs = batched_compress_inputs
-                .into_iter()
-                .flat_map(|batches| {
+            let mut layer_proofs = Vec::with_capacity(compress_inputs.len());
+            for batches in compress_inputs.chunks(shard_batch_size) {
+                layer_proofs.extend(
                     batches
                         .par_iter()
                         .map(|batch| {
@@ -501,9 +574,19 @@ impl SP1Prover {
                             );
                             (proof, ReduceProgramType::Reduce)
                         })
-                        .collect::<Vec<_>>()
-                })
-                .collect::<Vec<_>>();
+                        .collect::<Vec<_>>(),
+                );
+                progress(ProveProgress {
+                    phase: ProvePhase::Reduce {
+                        layer,
+                        total_layers,
+                    },
+                    shard_index: layer_proofs.len(),
+                    total_shards: Some(compress_inputs.len()),
+                    cycles_done: 0,
+                });
+            }
+            reduce_proofs = layer_proofs;
 
             if reduce_proofs.len() == 1 {
                 break;
@@ -723,6 +806,11 @@ mod tests {
         tracing::info!("setup elf");
         let (pk, vk) = prover.setup(elf);
 
+        tracing::info!("reload proving key");
+        let pk_file = tempfile::NamedTempFile::new()?;
+        pk.save(pk_file.path())?;
+        let pk = SP1ProvingKey::load(pk_file.path())?;
+
         tracing::info!("prove core");
         let stdin = SP1Stdin::new();
         let core_proof = prover.prove_core(&pk, &stdin)?;
@@ -732,7 +820,19 @@ mod tests {
         prover.verify(&core_proof.proof, &vk)?;
 
         tracing::info!("compress");
-        let compressed_proof = prover.compress(&vk, core_proof, vec![])?;
+        let mut updates = Vec::new();
+        let compressed_proof =
+            prover.compress_with_progress(&vk, core_proof, vec![], |p| updates.push(p))?;
+        let last = updates.last().unwrap();
+        let ProvePhase::Reduce {
+            layer,
+            total_layers,
+        } = last.phase
+        else {
+            panic!("expected a reduce progress update");
+        };
+        assert_eq!(layer + 1, total_layers);
+        assert_eq!((last.shard_index, last.total_shards), (1, Some(1)));
 
         tracing::info!("verify compressed");
         prover.verify_compressed(&compressed_proof, &vk)?;
@@ -838,9 +938,31 @@ mod tests {
             .unwrap();
         stdin.write(&vkey_digest);
         stdin.write(&vec![pv_1.clone(), pv_2.clone(), pv_2.clone()]);
-        stdin.write_proof(deferred_reduce_1.proof.clone(), keccak_vk.vk.clone());
-        stdin.write_proof(deferred_reduce_2.proof.clone(), keccak_vk.vk.clone());
-        stdin.write_proof(deferred_reduce_2.proof.clone(), keccak_vk.vk.clone());
+        for deferred_reduce in [&deferred_reduce_1, &deferred_reduce_2, &deferred_reduce_2] {
+            stdin.write_proof_input(DeferredProofInput::new(
+                deferred_reduce.proof.clone(),
+                keccak_vk.vk.clone(),
+            ));
+        }
+
+        tracing::info!("reject deferred proof with wrong vkey");
+        let mut invalid_stdin = stdin.clone();
+        invalid_stdin.proofs[1].vk = verify_vk.vk.clone();
+        assert!(matches!(
+            prover.prove_core(&verify_pk, &invalid_stdin),
+            Err(SP1CoreProverError::InvalidDeferredProof { index: 1, .. })
+        ));
+
+        tracing::info!("reject deferred proof with an incompatible config");
+        let mut invalid_stdin = stdin.clone();
+        invalid_stdin.proofs[2].config = Preset::Shrink;
+        assert!(matches!(
+            prover.prove_core(&verify_pk, &invalid_stdin),
+            Err(SP1CoreProverError::IncompatibleDeferredProof {
+                index: 2,
+                config: Preset::Shrink
+            })
+        ));
 
         tracing::info!("proving verify program (core)");
         let verify_proof = prover.prove_core(&verify_pk, &stdin)?;
@@ -31,6 +31,18 @@ pub struct SP1ProvingKey {
     pub vk: SP1VerifyingKey,
 }
 
+impl SP1ProvingKey {
+    /// Save the proving key so that another process can [Self::load] it instead of running setup.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        bincode::serialize_into(File::create(path)?, self).map_err(Into::into)
+    }
+
+    /// Load a proving key written by [Self::save].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        bincode::deserialize_from(File::open(path)?).map_err(Into::into)
+    }
+}
+
 /// The information necessary to verify a proof for a given RISC-V program.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SP1VerifyingKey {
@@ -21,11 +21,19 @@ pub mod utils {
 }
 
 use cfg_if::cfg_if;
-use std::{env, fmt::Debug, fs::File, path::Path};
+use std::{
+    env,
+    fmt::Debug,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
 
-use anyhow::{Ok, Result};
+use anyhow::{bail, Context, Ok, Result};
 
-pub use provers::{LocalProver, MockProver, Prover};
+pub use provers::{LocalProver, MockProver, Prover, ProverError};
+#[cfg(feature = "test-utils")]
+pub use provers::{ProverCall, RecordingProver};
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use sp1_core::{
@@ -33,8 +41,8 @@ use sp1_core::{
     stark::{MachineVerificationError, ShardProof},
 };
 pub use sp1_prover::{
-    CoreSC, HashableKey, InnerSC, OuterSC, PlonkBn254Proof, SP1Prover, SP1ProvingKey,
-    SP1PublicValues, SP1Stdin, SP1VerifyingKey,
+    CoreSC, DeferredProofInput, HashableKey, InnerSC, OuterSC, PlonkBn254Proof, SP1Prover,
+    SP1ProvingKey, SP1PublicValues, SP1Stdin, SP1VerifyingKey,
 };
 
 /// A client for interacting with SP1.
@@ -64,6 +72,21 @@ pub type SP1CompressedProofVerificationError = MachineVerificationError<InnerSC>
 /// A [SP1ProofWithPublicValues] generated with [ProverClient::prove_plonk].
 pub type SP1PlonkBn254Proof = SP1ProofWithPublicValues<PlonkBn254Proof>;
 
+/// The magic bytes at the start of a file written by [ProverClient::prove_to_file].
+pub const PROOF_FILE_MAGIC: [u8; 8] = *b"SP1PROOF";
+
+/// The version of the file format written by [ProverClient::prove_to_file].
+pub const PROOF_FILE_VERSION: u32 = 1;
+
+/// A proof stored by [ProverClient::prove_to_file], together with the hash of the verifying key it
+/// was proven against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SP1ProofFile {
+    pub proof: SP1Proof,
+    /// The verifying key hash, as returned by [Prover::vk_hash].
+    pub vk_hash: [u8; 32],
+}
+
 impl ProverClient {
     /// Creates a new [ProverClient].
     ///
@@ -215,7 +238,16 @@ impl ProverClient {
     /// stdin.write(&10usize);
     /// let (pk, vk) = client.setup(elf);
     /// ```
+    ///
+    /// Panics if the ELF is not a valid RV32IM executable; use [Self::try_setup] to handle that
+    /// case.
     pub fn setup(&self, elf: &[u8]) -> (SP1ProvingKey, SP1VerifyingKey) {
+        self.try_setup(elf).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like [Self::setup], but returns [ProverError::InvalidElf] instead of panicking when the
+    /// ELF cannot be loaded.
+    pub fn try_setup(&self, elf: &[u8]) -> Result<(SP1ProvingKey, SP1VerifyingKey), ProverError> {
         self.prover.setup(elf)
     }
 
@@ -246,7 +278,47 @@ impl ProverClient {
     /// let proof = client.prove(&pk, stdin).unwrap();
     /// ```
     pub fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof> {
-        self.prover.prove(pk, stdin)
+        Ok(self.prover.prove(pk, stdin)?)
+    }
+
+    /// Proves the execution of the given program like [Self::prove] and writes the proof to
+    /// `path`, along with its public values and the hash of the verifying key.
+    ///
+    /// The file starts with [PROOF_FILE_MAGIC] and [PROOF_FILE_VERSION] and is written to a
+    /// temporary file first, so `path` never holds a partially written proof. It can be read back
+    /// with [Self::load_proof_from_file].
+    pub fn prove_to_file(&self, pk: &SP1ProvingKey, stdin: SP1Stdin, path: &Path) -> Result<()> {
+        let proof = self.prove(pk, stdin)?;
+        let vk_hash = self.prover.vk_hash(&pk.vk);
+        write_proof_file(path, &SP1ProofFile { proof, vk_hash })
+    }
+
+    /// Loads a proof written by [Self::prove_to_file], failing if the file does not start with
+    /// [PROOF_FILE_MAGIC] or was written with a different [PROOF_FILE_VERSION].
+    pub fn load_proof_from_file(path: &Path) -> Result<SP1ProofFile> {
+        let mut reader = BufReader::new(
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+        );
+        let mut magic = [0u8; PROOF_FILE_MAGIC.len()];
+        reader
+            .read_exact(&mut magic)
+            .context("failed to read proof file header")?;
+        if magic != PROOF_FILE_MAGIC {
+            bail!("{} is not an SP1 proof file", path.display());
+        }
+        let mut version = [0u8; 4];
+        reader
+            .read_exact(&mut version)
+            .context("failed to read proof file header")?;
+        let version = u32::from_le_bytes(version);
+        if version != PROOF_FILE_VERSION {
+            bail!(
+                "unsupported proof file version {} (expected {})",
+                version,
+                PROOF_FILE_VERSION
+            );
+        }
+        Ok(bincode::deserialize_from(reader)?)
     }
 
     /// Proves the execution of the given program with the given input in the compressed mode.
@@ -279,7 +351,21 @@ impl ProverClient {
         pk: &SP1ProvingKey,
         stdin: SP1Stdin,
     ) -> Result<SP1CompressedProof> {
-        self.prover.prove_compressed(pk, stdin)
+        Ok(self.prover.prove_compressed(pk, stdin)?)
+    }
+
+    /// Compresses a proof generated by [Self::prove] with the same proving key and input, without
+    /// proving the program again.
+    ///
+    /// This is cheaper than calling both [Self::prove] and [Self::prove_compressed] when both the
+    /// core and the compressed proofs are needed.
+    pub fn compress_existing(
+        &self,
+        pk: &SP1ProvingKey,
+        core_proof: SP1Proof,
+        stdin: SP1Stdin,
+    ) -> Result<SP1CompressedProof> {
+        Ok(self.prover.compress_existing(pk, core_proof, stdin)?)
     }
 
     /// Proves the execution of the given program with the given input in the plonk bn254 mode.
@@ -309,7 +395,7 @@ impl ProverClient {
     /// ```
     /// Generates a plonk bn254 proof, verifiable onchain, of the given elf and stdin.
     pub fn prove_plonk(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1PlonkBn254Proof> {
-        self.prover.prove_plonk(pk, stdin)
+        Ok(self.prover.prove_plonk(pk, stdin)?)
     }
 
     /// Verifies that the given proof is valid and matches the given verification key produced by
@@ -364,7 +450,7 @@ impl ProverClient {
         proof: &SP1CompressedProof,
         vkey: &SP1VerifyingKey,
     ) -> Result<()> {
-        self.prover.verify_compressed(proof, vkey)
+        Ok(self.prover.verify_compressed(proof, vkey)?)
     }
 
     /// Verifies that the given plonk bn254 proof is valid and matches the given verification key
@@ -394,7 +480,7 @@ impl ProverClient {
     /// client.verify_plonk(&proof, &vk).unwrap();
     /// ```
     pub fn verify_plonk(&self, proof: &SP1PlonkBn254Proof, vkey: &SP1VerifyingKey) -> Result<()> {
-        self.prover.verify_plonk(proof, vkey)
+        Ok(self.prover.verify_plonk(proof, vkey)?)
     }
 }
 
@@ -404,6 +490,27 @@ impl Default for ProverClient {
     }
 }
 
+/// Writes `file` to a temporary file next to `path` and renames it into place.
+fn write_proof_file(path: &Path, file: &SP1ProofFile) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = Path::new(&tmp_path);
+
+    let write = || -> Result<()> {
+        let mut writer = BufWriter::new(File::create(tmp_path)?);
+        writer.write_all(&PROOF_FILE_MAGIC)?;
+        writer.write_all(&PROOF_FILE_VERSION.to_le_bytes())?;
+        bincode::serialize_into(&mut writer, file)?;
+        writer.into_inner()?.sync_all()?;
+        Ok(())
+    };
+    if let Err(e) = write().and_then(|_| Ok(fs::rename(tmp_path, path)?)) {
+        let _ = fs::remove_file(tmp_path);
+        return Err(e.context(format!("failed to write proof to {}", path.display())));
+    }
+    Ok(())
+}
+
 impl<P: Debug + Clone + Serialize + DeserializeOwned> SP1ProofWithPublicValues<P> {
     /// Saves the proof to a path.
     pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
@@ -427,7 +534,10 @@ impl SP1PlonkBn254Proof {
 #[cfg(test)]
 mod tests {
 
-    use crate::{utils, ProverClient, SP1Stdin};
+    use crate::{
+        utils, HashableKey, ProverClient, ProverError, SP1Stdin, PROOF_FILE_MAGIC,
+        PROOF_FILE_VERSION,
+    };
 
     #[test]
     fn test_execute() {
@@ -464,6 +574,23 @@ mod tests {
         client.verify_plonk(&proof, &vk).unwrap();
     }
 
+    #[test]
+    #[cfg(not(feature = "plonk"))]
+    fn test_prove_plonk_without_feature() {
+        utils::setup_logger();
+        let client = ProverClient::local();
+        let elf =
+            include_bytes!("../../examples/fibonacci/program/elf/riscv32im-succinct-zkvm-elf");
+        let (pk, _) = client.setup(elf);
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&10usize);
+        let err = client.prove_plonk(&pk, stdin).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ProverError>(),
+            Some(ProverError::Setup(_))
+        ));
+    }
+
     #[test]
     fn test_e2e_prove_plonk_mock() {
         utils::setup_logger();
@@ -476,4 +603,58 @@ mod tests {
         let proof = client.prove_plonk(&pk, stdin).unwrap();
         client.verify_plonk(&proof, &vk).unwrap();
     }
+
+    #[test]
+    fn test_prove_to_file() {
+        utils::setup_logger();
+        let client = ProverClient::mock();
+        let elf =
+            include_bytes!("../../examples/fibonacci/program/elf/riscv32im-succinct-zkvm-elf");
+        let (pk, vk) = client.setup(elf);
+        let mut stdin = SP1Stdin::new();
+        stdin.write(&10usize);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("proof.bin");
+        client.prove_to_file(&pk, stdin, &path).unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let file = ProverClient::load_proof_from_file(&path).unwrap();
+        assert_eq!(file.vk_hash, client.prover.vk_hash(&vk));
+        client.verify(&file.proof, &vk).unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[PROOF_FILE_MAGIC.len()..PROOF_FILE_MAGIC.len() + 4]
+            .copy_from_slice(&(PROOF_FILE_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(ProverClient::load_proof_from_file(&path).is_err());
+
+        bytes[0] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(ProverClient::load_proof_from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_vk_hash() {
+        utils::setup_logger();
+        let client = ProverClient::mock();
+        let elf =
+            include_bytes!("../../examples/fibonacci/program/elf/riscv32im-succinct-zkvm-elf");
+        let (_, vk) = client.setup(elf);
+        let vk_hash = client.prover.vk_hash(&vk);
+        assert_eq!(format!("0x{}", hex::encode(vk_hash)), vk.bytes32());
+    }
+
+    #[test]
+    fn test_try_setup_invalid_elf() {
+        utils::setup_logger();
+        let client = ProverClient::mock();
+        let elf =
+            include_bytes!("../../examples/fibonacci/program/elf/riscv32im-succinct-zkvm-elf");
+        let info = client.prover.validate_elf(elf).unwrap();
+        assert!(info.memory_image_size > 0);
+
+        let err = client.try_setup(&elf[..64]).unwrap_err();
+        assert!(matches!(err, ProverError::InvalidElf(_)));
+    }
 }
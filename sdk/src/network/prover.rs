@@ -14,7 +14,7 @@ use sp1_prover::utils::block_on;
 use sp1_prover::{SP1Prover, SP1Stdin};
 use tokio::{runtime, time::sleep};
 
-use crate::provers::{LocalProver, ProverType};
+use crate::provers::{LocalProver, ProverError, ProverType};
 
 /// An implementation of [crate::ProverClient] that can generate proofs on a remote RPC server.
 pub struct NetworkProver {
@@ -165,7 +165,7 @@ impl Prover for NetworkProver {
         ProverType::Network
     }
 
-    fn setup(&self, elf: &[u8]) -> (SP1ProvingKey, SP1VerifyingKey) {
+    fn setup(&self, elf: &[u8]) -> Result<(SP1ProvingKey, SP1VerifyingKey), ProverError> {
         self.local_prover.setup(elf)
     }
 
@@ -173,16 +173,25 @@ impl Prover for NetworkProver {
         self.local_prover.sp1_prover()
     }
 
-    fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof> {
-        block_on(self.prove_async(&pk.elf, stdin, ProofMode::Core))
+    fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof, ProverError> {
+        block_on(self.prove_async(&pk.elf, stdin, ProofMode::Core)).map_err(ProverError::Transport)
     }
 
-    fn prove_compressed(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1CompressedProof> {
+    fn prove_compressed(
+        &self,
+        pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1CompressedProof, ProverError> {
         block_on(self.prove_async(&pk.elf, stdin, ProofMode::Compressed))
+            .map_err(ProverError::Transport)
     }
 
-    fn prove_plonk(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1PlonkBn254Proof> {
-        block_on(self.prove_async(&pk.elf, stdin, ProofMode::Plonk))
+    fn prove_plonk(
+        &self,
+        pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1PlonkBn254Proof, ProverError> {
+        block_on(self.prove_async(&pk.elf, stdin, ProofMode::Plonk)).map_err(ProverError::Transport)
     }
 }
 
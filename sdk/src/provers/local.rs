@@ -1,13 +1,12 @@
-use anyhow::Result;
 use cfg_if::cfg_if;
-use sp1_prover::{SP1Prover, SP1Stdin};
+use sp1_prover::{CoreSC, SP1Prover, SP1Stdin};
 
 use crate::{
     Prover, SP1CompressedProof, SP1PlonkBn254Proof, SP1Proof, SP1ProofWithPublicValues,
     SP1ProvingKey, SP1VerifyingKey,
 };
 
-use super::ProverType;
+use super::{check_wrapping_supported, ProverError, ProverType};
 
 /// An implementation of [crate::ProverClient] that can generate end-to-end proofs locally.
 pub struct LocalProver {
@@ -27,15 +26,16 @@ impl Prover for LocalProver {
         ProverType::Local
     }
 
-    fn setup(&self, elf: &[u8]) -> (SP1ProvingKey, SP1VerifyingKey) {
-        self.prover.setup(elf)
+    fn setup(&self, elf: &[u8]) -> Result<(SP1ProvingKey, SP1VerifyingKey), ProverError> {
+        self.validate_elf(elf)?;
+        Ok(self.prover.setup(elf))
     }
 
     fn sp1_prover(&self) -> &SP1Prover {
         &self.prover
     }
 
-    fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof> {
+    fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof, ProverError> {
         let proof = self.prover.prove_core(pk, &stdin)?;
         Ok(SP1ProofWithPublicValues {
             proof: proof.proof.0,
@@ -44,29 +44,44 @@ impl Prover for LocalProver {
         })
     }
 
-    fn prove_compressed(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1CompressedProof> {
-        let proof = self.prover.prove_core(pk, &stdin)?;
-        let deferred_proofs = stdin.proofs.iter().map(|p| p.0.clone()).collect();
-        let public_values = proof.public_values.clone();
-        let reduce_proof = self.prover.compress(&pk.vk, proof, deferred_proofs)?;
-        Ok(SP1CompressedProof {
-            proof: reduce_proof.proof,
-            stdin,
-            public_values,
-        })
+    fn prove_compressed(
+        &self,
+        pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1CompressedProof, ProverError> {
+        let proof = self.prove(pk, stdin.clone())?;
+        self.compress_existing(pk, proof, stdin)
     }
 
     #[allow(unused)]
-    fn prove_plonk(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1PlonkBn254Proof> {
+    fn prove_plonk(
+        &self,
+        pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1PlonkBn254Proof, ProverError> {
+        check_wrapping_supported::<CoreSC>()?;
         cfg_if! {
             if #[cfg(feature = "plonk")] {
 
                 let proof = self.prover.prove_core(pk, &stdin)?;
-                let deferred_proofs = stdin.proofs.iter().map(|p| p.0.clone()).collect();
+                let deferred_proofs = stdin
+                    .proofs
+                    .iter()
+                    .map(|input| input.proof.clone())
+                    .collect();
                 let public_values = proof.public_values.clone();
-                let reduce_proof = self.prover.compress(&pk.vk, proof, deferred_proofs)?;
-                let compress_proof = self.prover.shrink(reduce_proof)?;
-                let outer_proof = self.prover.wrap_bn254(compress_proof)?;
+                let reduce_proof = self
+                    .prover
+                    .compress(&pk.vk, proof, deferred_proofs)
+                    .map_err(|e| ProverError::Prove(e.into()))?;
+                let compress_proof = self
+                    .prover
+                    .shrink(reduce_proof)
+                    .map_err(|e| ProverError::Prove(e.into()))?;
+                let outer_proof = self
+                    .prover
+                    .wrap_bn254(compress_proof)
+                    .map_err(|e| ProverError::Prove(e.into()))?;
 
                 let plonk_bn254_aritfacts = if sp1_prover::build::sp1_dev_mode() {
                     sp1_prover::build::try_build_plonk_bn254_artifacts_dev(
@@ -83,7 +98,9 @@ impl Prover for LocalProver {
                     public_values,
                 })
             } else {
-                panic!("plonk feature not enabled")
+                Err(ProverError::Setup(anyhow::anyhow!(
+                    "plonk feature not enabled: rebuild sp1-sdk with `--features plonk`"
+                )))
             }
         }
     }
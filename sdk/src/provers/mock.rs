@@ -3,13 +3,12 @@ use crate::{
     Prover, SP1CompressedProof, SP1PlonkBn254Proof, SP1Proof, SP1ProofVerificationError,
     SP1ProofWithPublicValues, SP1ProvingKey, SP1VerifyingKey,
 };
-use anyhow::Result;
 use p3_field::PrimeField;
 use sp1_prover::{
     verify::verify_plonk_bn254_public_inputs, HashableKey, PlonkBn254Proof, SP1Prover, SP1Stdin,
 };
 
-use super::ProverType;
+use super::{ProverError, ProverType};
 
 /// An implementation of [crate::ProverClient] that can generate mock proofs.
 pub struct MockProver {
@@ -29,15 +28,16 @@ impl Prover for MockProver {
         ProverType::Mock
     }
 
-    fn setup(&self, elf: &[u8]) -> (SP1ProvingKey, SP1VerifyingKey) {
-        self.prover.setup(elf)
+    fn setup(&self, elf: &[u8]) -> Result<(SP1ProvingKey, SP1VerifyingKey), ProverError> {
+        self.validate_elf(elf)?;
+        Ok(self.prover.setup(elf))
     }
 
     fn sp1_prover(&self) -> &SP1Prover {
         unimplemented!("MockProver does not support SP1Prover")
     }
 
-    fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof> {
+    fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof, ProverError> {
         let (public_values, _) = SP1Prover::execute(&pk.elf, &stdin)?;
         Ok(SP1ProofWithPublicValues {
             proof: vec![],
@@ -50,11 +50,15 @@ impl Prover for MockProver {
         &self,
         _pk: &SP1ProvingKey,
         _stdin: SP1Stdin,
-    ) -> Result<SP1CompressedProof> {
+    ) -> Result<SP1CompressedProof, ProverError> {
         unimplemented!()
     }
 
-    fn prove_plonk(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1PlonkBn254Proof> {
+    fn prove_plonk(
+        &self,
+        pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1PlonkBn254Proof, ProverError> {
         let (public_values, _) = SP1Prover::execute(&pk.elf, &stdin)?;
         Ok(SP1PlonkBn254Proof {
             proof: PlonkBn254Proof {
@@ -82,12 +86,17 @@ impl Prover for MockProver {
         &self,
         _proof: &SP1CompressedProof,
         _vkey: &SP1VerifyingKey,
-    ) -> Result<()> {
+    ) -> Result<(), ProverError> {
         Ok(())
     }
 
-    fn verify_plonk(&self, proof: &SP1PlonkBn254Proof, vkey: &SP1VerifyingKey) -> Result<()> {
-        verify_plonk_bn254_public_inputs(vkey, &proof.public_values, &proof.proof.public_inputs)?;
+    fn verify_plonk(
+        &self,
+        proof: &SP1PlonkBn254Proof,
+        vkey: &SP1VerifyingKey,
+    ) -> Result<(), ProverError> {
+        verify_plonk_bn254_public_inputs(vkey, &proof.public_values, &proof.proof.public_inputs)
+            .map_err(ProverError::Verify)?;
         Ok(())
     }
 }
@@ -1,17 +1,29 @@
 mod local;
 mod mock;
+#[cfg(feature = "test-utils")]
+mod recording;
+
+use std::time::{Duration, Instant};
 
 use crate::{SP1CompressedProof, SP1PlonkBn254Proof, SP1Proof};
-use anyhow::Result;
 pub use local::LocalProver;
 pub use mock::MockProver;
-use sp1_core::stark::MachineVerificationError;
+use p3_field::PrimeField;
+#[cfg(feature = "test-utils")]
+pub use recording::{ProverCall, RecordingProver};
+use sp1_core::disassembler::{Elf, ElfError, ElfInfo};
+use sp1_core::io::StdinTooLarge;
+use sp1_core::runtime::{ExecutionError, Program, SyscallCode};
+use sp1_core::stark::{MachineVerificationError, StarkGenericConfig};
+use sp1_core::utils::{baby_bear_poseidon2::Preset, SP1CoreProverError};
 use sp1_prover::CoreSC;
+use sp1_prover::SP1CoreProof;
 use sp1_prover::SP1CoreProofData;
 use sp1_prover::SP1Prover;
 use sp1_prover::SP1ReduceProof;
-use sp1_prover::{SP1ProvingKey, SP1Stdin, SP1VerifyingKey};
+use sp1_prover::{HashableKey, SP1ProvingKey, SP1Stdin, SP1VerifyingKey};
 use strum_macros::EnumString;
+use thiserror::Error;
 
 /// The type of prover.
 #[derive(Debug, PartialEq, EnumString)]
@@ -21,22 +33,138 @@ pub enum ProverType {
     Network,
 }
 
+/// An error returned by a [Prover], classified by the stage that failed.
+#[derive(Debug, Error)]
+pub enum ProverError {
+    #[error("invalid elf: {0}")]
+    InvalidElf(#[from] ElfError),
+    #[error("unsupported precompile: {name}")]
+    UnsupportedPrecompile { name: String },
+    #[error(
+        "deferred proof {index} was made with the {config:?} config, which cannot be deferred"
+    )]
+    IncompatibleDeferredProof { index: usize, config: Preset },
+    #[error("the {config} config does not support {operation}")]
+    UnsupportedConfig {
+        config: &'static str,
+        operation: &'static str,
+    },
+    #[error("{0}")]
+    StdinTooLarge(#[from] StdinTooLarge),
+    #[error("setup failed: {0}")]
+    Setup(anyhow::Error),
+    #[error("execution failed: {0}")]
+    Execution(#[from] ExecutionError),
+    #[error("commit failed: {0}")]
+    Commit(anyhow::Error),
+    #[error("proving failed: {0}")]
+    Prove(anyhow::Error),
+    #[error("verification failed: {0}")]
+    Verify(anyhow::Error),
+    #[error("transport failed: {0}")]
+    Transport(anyhow::Error),
+    #[error("serialization failed: {0}")]
+    Serialization(anyhow::Error),
+}
+
+/// Errors without a more specific stage are treated as proving failures.
+impl From<anyhow::Error> for ProverError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Prove(e)
+    }
+}
+
+impl From<SP1CoreProverError> for ProverError {
+    fn from(e: SP1CoreProverError) -> Self {
+        match e {
+            SP1CoreProverError::ExecutionError(e) => Self::Execution(e),
+            SP1CoreProverError::SerializationError(e) => Self::Serialization(e.into()),
+            SP1CoreProverError::IncompatibleDeferredProof { index, config } => {
+                Self::IncompatibleDeferredProof { index, config }
+            }
+            SP1CoreProverError::StdinTooLarge(e) => Self::StdinTooLarge(e),
+            e => Self::Prove(e.into()),
+        }
+    }
+}
+
 /// An implementation of [crate::ProverClient].
 pub trait Prover: Send + Sync {
     fn id(&self) -> ProverType;
 
     fn sp1_prover(&self) -> &SP1Prover;
 
-    fn setup(&self, elf: &[u8]) -> (SP1ProvingKey, SP1VerifyingKey);
+    /// Check that `elf` is a RV32IM executable before it is handed to [Prover::setup], and that
+    /// it only calls precompiles the prover supports.
+    fn validate_elf(&self, elf: &[u8]) -> Result<ElfInfo, ProverError> {
+        let info = Elf::inspect(elf)?;
+        if let Some(&id) = Program::from(elf).unsupported_syscall_ids().first() {
+            let name = SyscallCode::try_from_u32(id)
+                .map_or_else(|| format!("{:#010x}", id), |code| format!("{:?}", code));
+            return Err(ProverError::UnsupportedPrecompile { name });
+        }
+        Ok(info)
+    }
+
+    /// Compute the proving and verifying keys of a program, failing with
+    /// [ProverError::InvalidElf] if the ELF cannot be loaded and with
+    /// [ProverError::UnsupportedPrecompile] if it calls a precompile the prover lacks.
+    fn setup(&self, elf: &[u8]) -> Result<(SP1ProvingKey, SP1VerifyingKey), ProverError>;
+
+    /// The hash of a verifying key that PLONK proofs take as their first public input, as 32
+    /// big-endian bytes. Its hex encoding is [HashableKey::bytes32].
+    fn vk_hash(&self, vkey: &SP1VerifyingKey) -> [u8; 32] {
+        let bytes = vkey.hash_bn254().as_canonical_biguint().to_bytes_be();
+        let mut hash = [0u8; 32];
+        hash[32 - bytes.len()..].copy_from_slice(&bytes);
+        hash
+    }
 
     /// Prove the execution of a RISCV ELF with the given inputs.
-    fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof>;
+    fn prove(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof, ProverError>;
 
     /// Generate a compressed proof of the execution of a RISCV ELF with the given inputs.
-    fn prove_compressed(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1CompressedProof>;
+    fn prove_compressed(
+        &self,
+        pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1CompressedProof, ProverError>;
+
+    /// Compress a core proof generated by [Prover::prove], without proving the program again.
+    fn compress_existing(
+        &self,
+        pk: &SP1ProvingKey,
+        core_proof: SP1Proof,
+        stdin: SP1Stdin,
+    ) -> Result<SP1CompressedProof, ProverError> {
+        let deferred_proofs = stdin
+            .proofs
+            .iter()
+            .map(|input| input.proof.clone())
+            .collect();
+        let public_values = core_proof.public_values.clone();
+        let core_proof = SP1CoreProof {
+            proof: SP1CoreProofData(core_proof.proof),
+            stdin: core_proof.stdin,
+            public_values: core_proof.public_values,
+        };
+        let reduce_proof = self
+            .sp1_prover()
+            .compress(&pk.vk, core_proof, deferred_proofs)
+            .map_err(|e| ProverError::Prove(e.into()))?;
+        Ok(SP1CompressedProof {
+            proof: reduce_proof.proof,
+            stdin,
+            public_values,
+        })
+    }
 
     /// Given an SP1 program and input, generate a PLONK proof that can be verified on-chain.
-    fn prove_plonk(&self, pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1PlonkBn254Proof>;
+    fn prove_plonk(
+        &self,
+        pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1PlonkBn254Proof, ProverError>;
 
     /// Verify that an SP1 proof is valid given its vkey and metadata.
     fn verify(
@@ -49,7 +177,11 @@ pub trait Prover: Send + Sync {
     }
 
     /// Verify that a compressed SP1 proof is valid given its vkey and metadata.
-    fn verify_compressed(&self, proof: &SP1CompressedProof, vkey: &SP1VerifyingKey) -> Result<()> {
+    fn verify_compressed(
+        &self,
+        proof: &SP1CompressedProof,
+        vkey: &SP1VerifyingKey,
+    ) -> Result<(), ProverError> {
         self.sp1_prover()
             .verify_compressed(
                 &SP1ReduceProof {
@@ -57,12 +189,16 @@ pub trait Prover: Send + Sync {
                 },
                 vkey,
             )
-            .map_err(|e| e.into())
+            .map_err(|e| ProverError::Verify(e.into()))
     }
 
     /// Verify that a SP1 PLONK proof is valid. Verify that the public inputs of the PlonkBn254 proof match
     /// the hash of the VK and the committed public values of the SP1ProofWithPublicValues.
-    fn verify_plonk(&self, proof: &SP1PlonkBn254Proof, vkey: &SP1VerifyingKey) -> Result<()> {
+    fn verify_plonk(
+        &self,
+        proof: &SP1PlonkBn254Proof,
+        vkey: &SP1VerifyingKey,
+    ) -> Result<(), ProverError> {
         let sp1_prover = self.sp1_prover();
 
         let plonk_bn254_aritfacts = if sp1_prover::build::sp1_dev_mode() {
@@ -70,13 +206,128 @@ pub trait Prover: Send + Sync {
         } else {
             sp1_prover::build::try_install_plonk_bn254_artifacts()
         };
-        sp1_prover.verify_plonk_bn254(
-            &proof.proof,
-            vkey,
-            &proof.public_values,
-            &plonk_bn254_aritfacts,
-        )?;
+        sp1_prover
+            .verify_plonk_bn254(
+                &proof.proof,
+                vkey,
+                &proof.public_values,
+                &plonk_bn254_aritfacts,
+            )
+            .map_err(ProverError::Verify)?;
+
+        Ok(())
+    }
+
+    /// Set up, prove and verify a built-in program of a few cycles, returning the time it took.
+    ///
+    /// This catches a broken build or a mismatched config before the prover is trusted with real
+    /// jobs, at the cost of one small core proof.
+    fn self_test(&self) -> Result<Duration, ProverError> {
+        let start = Instant::now();
+        let (pk, vk) = self.setup(&self_test_elf())?;
+        let proof = self.prove(&pk, SP1Stdin::new())?;
+        self.verify(&proof, &vk)
+            .map_err(|e| ProverError::Verify(anyhow::anyhow!("{:?}", e)))?;
+        Ok(start.elapsed())
+    }
+}
 
+/// Fails with [ProverError::UnsupportedConfig] unless core proofs over `SC` can be wrapped into a
+/// PLONK proof, so that [Prover::prove_plonk] fails before any proving work is done.
+pub(crate) fn check_wrapping_supported<SC: StarkGenericConfig>() -> Result<(), ProverError> {
+    if SC::capabilities().supports_wrapping {
         Ok(())
+    } else {
+        Err(ProverError::UnsupportedConfig {
+            config: std::any::type_name::<SC>(),
+            operation: "PLONK wrapping",
+        })
+    }
+}
+
+/// The address the program of [Prover::self_test] is loaded at.
+const SELF_TEST_BASE: u32 = 0x0020_0800;
+
+/// The instructions of the program of [Prover::self_test], which adds two constants and falls off
+/// the end of the program.
+const SELF_TEST_INSTRUCTIONS: [u32; 3] = [
+    0x0050_0e93, // addi x29, x0, 5
+    0x0250_0f13, // addi x30, x0, 37
+    0x01df_0fb3, // add x31, x30, x29
+];
+
+/// Builds the ELF of the program of [Prover::self_test], with a single executable segment holding
+/// [SELF_TEST_INSTRUCTIONS].
+fn self_test_elf() -> Vec<u8> {
+    const EHDR_SIZE: u16 = 52;
+    const PHDR_SIZE: u16 = 32;
+    let code_offset = (EHDR_SIZE + PHDR_SIZE) as u32;
+    let code_size = (SELF_TEST_INSTRUCTIONS.len() * 4) as u32;
+
+    let mut elf = Vec::new();
+    // ELF header: 32-bit little-endian RISC-V executable with one program header.
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    elf.extend_from_slice(&2u16.to_le_bytes()); // e_type: ET_EXEC
+    elf.extend_from_slice(&243u16.to_le_bytes()); // e_machine: EM_RISCV
+    elf.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    elf.extend_from_slice(&SELF_TEST_BASE.to_le_bytes()); // e_entry
+    elf.extend_from_slice(&(EHDR_SIZE as u32).to_le_bytes()); // e_phoff
+    elf.extend_from_slice(&0u32.to_le_bytes()); // e_shoff
+    elf.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    elf.extend_from_slice(&EHDR_SIZE.to_le_bytes()); // e_ehsize
+    elf.extend_from_slice(&PHDR_SIZE.to_le_bytes()); // e_phentsize
+    elf.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    elf.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+    elf.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    elf.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    // Program header: a loadable, readable and executable segment.
+    elf.extend_from_slice(&1u32.to_le_bytes()); // p_type: PT_LOAD
+    elf.extend_from_slice(&code_offset.to_le_bytes()); // p_offset
+    elf.extend_from_slice(&SELF_TEST_BASE.to_le_bytes()); // p_vaddr
+    elf.extend_from_slice(&SELF_TEST_BASE.to_le_bytes()); // p_paddr
+    elf.extend_from_slice(&code_size.to_le_bytes()); // p_filesz
+    elf.extend_from_slice(&code_size.to_le_bytes()); // p_memsz
+    elf.extend_from_slice(&5u32.to_le_bytes()); // p_flags: PF_R | PF_X
+    elf.extend_from_slice(&4u32.to_le_bytes()); // p_align
+    for instruction in SELF_TEST_INSTRUCTIONS {
+        elf.extend_from_slice(&instruction.to_le_bytes());
+    }
+    elf
+}
+
+#[cfg(test)]
+mod tests {
+    use sp1_core::runtime::Runtime;
+    use sp1_core::utils::BabyBearKeccak;
+    use sp1_core::utils::SP1CoreOpts;
+
+    use super::*;
+
+    #[test]
+    fn test_check_wrapping_supported() {
+        assert!(check_wrapping_supported::<CoreSC>().is_ok());
+        assert!(matches!(
+            check_wrapping_supported::<BabyBearKeccak>(),
+            Err(ProverError::UnsupportedConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn test_self_test_elf() {
+        let elf = self_test_elf();
+        let info = Elf::inspect(&elf).unwrap();
+        assert_eq!(info.pc_start, SELF_TEST_BASE);
+
+        let mut runtime = Runtime::new(Program::from(elf.as_slice()), SP1CoreOpts::default());
+        runtime.run().unwrap();
+        assert_eq!(runtime.state.global_clk, 3);
+        assert_eq!(runtime.registers()[31], 42);
+    }
+
+    #[test]
+    fn test_self_test() {
+        let prover = MockProver::new();
+        assert!(prover.self_test().is_ok());
     }
 }
@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use p3_field::{AbstractField, PrimeField};
+use p3_fri::{FriProof, TwoAdicFriPcsProof};
+use sp1_core::{
+    runtime::Program,
+    stark::{Com, RiscvAir, ShardCommitment, ShardOpenedValues, ShardProof},
+    utils::{InnerVal, DIGEST_SIZE},
+};
+use sp1_prover::{
+    verify::verify_plonk_bn254_public_inputs, CoreSC, HashableKey, InnerSC, PlonkBn254Proof,
+    SP1Prover, SP1PublicValues, SP1Stdin,
+};
+
+use super::{ProverError, ProverType};
+use crate::{
+    Prover, SP1CompressedProof, SP1PlonkBn254Proof, SP1Proof, SP1ProofVerificationError,
+    SP1ProofWithPublicValues, SP1ProvingKey, SP1VerifyingKey,
+};
+
+/// A call made to a [RecordingProver].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProverCall {
+    Setup,
+    Prove,
+    ProveCompressed,
+    CompressExisting,
+    ProvePlonk,
+    Verify,
+    VerifyCompressed,
+    VerifyPlonk,
+}
+
+/// A [Prover] for unit tests that returns placeholder proofs without executing the program, and
+/// records every call made to it.
+///
+/// Setup computes the real keys of the program, but no recursion keys, so it is much cheaper than
+/// [super::MockProver]. Proofs have no shard proofs and empty public values, and every proof
+/// verifies. Compressed proofs are a stub shard proof without chips, unless one is given with
+/// [Self::with_compressed_proof].
+///
+/// It has no [SP1Prover], so every method of [Prover] that would use one is overridden.
+#[derive(Default)]
+pub struct RecordingProver {
+    calls: Mutex<Vec<ProverCall>>,
+    compressed_proof: Option<SP1CompressedProof>,
+}
+
+impl RecordingProver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `proof` from every call to [Prover::prove_compressed] and
+    /// [Prover::compress_existing], with its input replaced by the one given to the call.
+    pub fn with_compressed_proof(mut self, proof: SP1CompressedProof) -> Self {
+        self.compressed_proof = Some(proof);
+        self
+    }
+
+    /// The calls made to this prover so far, in order.
+    pub fn calls(&self) -> Vec<ProverCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn record(&self, call: ProverCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+
+    fn compressed_proof(&self, stdin: SP1Stdin) -> SP1CompressedProof {
+        let proof = self
+            .compressed_proof
+            .clone()
+            .unwrap_or_else(|| SP1CompressedProof {
+                proof: stub_shard_proof(),
+                stdin: SP1Stdin::new(),
+                public_values: SP1PublicValues::new(),
+            });
+        SP1CompressedProof { stdin, ..proof }
+    }
+}
+
+/// A shard proof of nothing, with zero commitments, no chips and an empty opening proof.
+fn stub_shard_proof() -> ShardProof<InnerSC> {
+    let commit: Com<InnerSC> = [InnerVal::zero(); DIGEST_SIZE].into();
+    ShardProof {
+        commitment: ShardCommitment {
+            main_commit: commit,
+            permutation_commit: commit,
+            quotient_commit: commit,
+        },
+        opened_values: ShardOpenedValues { chips: Vec::new() },
+        opening_proof: TwoAdicFriPcsProof {
+            fri_proof: FriProof {
+                commit_phase_commits: Vec::new(),
+                query_proofs: Vec::new(),
+                final_poly: AbstractField::zero(),
+                pow_witness: InnerVal::zero(),
+            },
+            query_openings: Vec::new(),
+        },
+        chip_ordering: HashMap::new(),
+        public_values: Vec::new(),
+    }
+}
+
+impl Prover for RecordingProver {
+    fn id(&self) -> ProverType {
+        ProverType::Mock
+    }
+
+    /// Panics, since a [RecordingProver] has no [SP1Prover]. No other method calls it.
+    fn sp1_prover(&self) -> &SP1Prover {
+        unimplemented!("RecordingProver does not support SP1Prover")
+    }
+
+    fn setup(&self, elf: &[u8]) -> Result<(SP1ProvingKey, SP1VerifyingKey), ProverError> {
+        self.record(ProverCall::Setup);
+        self.validate_elf(elf)?;
+        let machine = RiscvAir::machine(CoreSC::default());
+        let (pk, vk) = machine.setup(&Program::from(elf));
+        let vk = SP1VerifyingKey { vk };
+        let pk = SP1ProvingKey {
+            pk,
+            elf: elf.to_vec(),
+            vk: vk.clone(),
+        };
+        Ok((pk, vk))
+    }
+
+    fn prove(&self, _pk: &SP1ProvingKey, stdin: SP1Stdin) -> Result<SP1Proof, ProverError> {
+        self.record(ProverCall::Prove);
+        Ok(SP1ProofWithPublicValues {
+            proof: vec![],
+            stdin,
+            public_values: SP1PublicValues::new(),
+        })
+    }
+
+    fn prove_compressed(
+        &self,
+        _pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1CompressedProof, ProverError> {
+        self.record(ProverCall::ProveCompressed);
+        Ok(self.compressed_proof(stdin))
+    }
+
+    fn compress_existing(
+        &self,
+        _pk: &SP1ProvingKey,
+        _core_proof: SP1Proof,
+        stdin: SP1Stdin,
+    ) -> Result<SP1CompressedProof, ProverError> {
+        self.record(ProverCall::CompressExisting);
+        Ok(self.compressed_proof(stdin))
+    }
+
+    fn prove_plonk(
+        &self,
+        pk: &SP1ProvingKey,
+        stdin: SP1Stdin,
+    ) -> Result<SP1PlonkBn254Proof, ProverError> {
+        self.record(ProverCall::ProvePlonk);
+        let public_values = SP1PublicValues::new();
+        Ok(SP1PlonkBn254Proof {
+            proof: PlonkBn254Proof {
+                public_inputs: [
+                    pk.vk.hash_bn254().as_canonical_biguint().to_string(),
+                    public_values.hash().to_string(),
+                ],
+                encoded_proof: "".to_string(),
+                raw_proof: "".to_string(),
+            },
+            stdin,
+            public_values,
+        })
+    }
+
+    fn verify(
+        &self,
+        _proof: &SP1Proof,
+        _vkey: &SP1VerifyingKey,
+    ) -> Result<(), SP1ProofVerificationError> {
+        self.record(ProverCall::Verify);
+        Ok(())
+    }
+
+    fn verify_compressed(
+        &self,
+        _proof: &SP1CompressedProof,
+        _vkey: &SP1VerifyingKey,
+    ) -> Result<(), ProverError> {
+        self.record(ProverCall::VerifyCompressed);
+        Ok(())
+    }
+
+    fn verify_plonk(
+        &self,
+        proof: &SP1PlonkBn254Proof,
+        vkey: &SP1VerifyingKey,
+    ) -> Result<(), ProverError> {
+        self.record(ProverCall::VerifyPlonk);
+        verify_plonk_bn254_public_inputs(vkey, &proof.public_values, &proof.proof.public_inputs)
+            .map_err(ProverError::Verify)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProverClient;
+
+    #[test]
+    fn test_recording_prover() {
+        let client = ProverClient {
+            prover: Box::new(RecordingProver::new()),
+        };
+        let elf =
+            include_bytes!("../../../examples/fibonacci/program/elf/riscv32im-succinct-zkvm-elf");
+        let (pk, vk) = client.setup(elf);
+        let proof = client.prove(&pk, SP1Stdin::new()).unwrap();
+        assert!(proof.proof.is_empty());
+        client.verify(&proof, &vk).unwrap();
+        let proof = client.prove_plonk(&pk, SP1Stdin::new()).unwrap();
+        client.verify_plonk(&proof, &vk).unwrap();
+        let compressed = client.prove_compressed(&pk, SP1Stdin::new()).unwrap();
+        assert!(compressed.proof.opened_values.chips.is_empty());
+        client.verify_compressed(&compressed, &vk).unwrap();
+
+        // None of the methods that use an SP1Prover by default reach it.
+        let prover = RecordingProver::new();
+        prover.setup(elf).unwrap();
+        let proof = prover.prove(&pk, SP1Stdin::new()).unwrap();
+        prover.verify(&proof, &vk).unwrap();
+        prover
+            .compress_existing(&pk, proof, SP1Stdin::new())
+            .unwrap();
+        assert_eq!(
+            prover.calls(),
+            vec![
+                ProverCall::Setup,
+                ProverCall::Prove,
+                ProverCall::Verify,
+                ProverCall::CompressExisting
+            ]
+        );
+    }
+}